@@ -0,0 +1,29 @@
+#version 450
+precision highp float;
+
+// Tone maps the histogram of `buddhabrot.comp`, logarithmically so that
+// the faint orbits don't disappear next to the brightest spots.
+
+layout(set = 0, binding = 0) uniform WindowSize {
+    vec2 size;
+};
+
+layout(std430, set = 0, binding = 4) buffer Histogram {
+    uint max_count[4];
+    uint counts[];
+};
+
+layout(location = 0) out vec4 outColor;
+
+const float GAMMA = 3.0;
+
+void main() {
+    ivec2 pixel = ivec2(gl_FragCoord.xy);
+    uint index = uint(pixel.y * int(size.x) + pixel.x) * 3u;
+    vec3 color;
+    for (int channel = 0; channel < 3; channel++) {
+        float brightest = log(1.0 + float(max(max_count[channel], 1u)));
+        color[channel] = pow(log(1.0 + float(counts[index + uint(channel)])) / brightest, GAMMA);
+    }
+    outColor = vec4(color, 1.0);
+}
@@ -0,0 +1,199 @@
+#version 450
+precision highp float;
+
+// Colors the iteration counts that `mandelbrot.frag` left in `iteration_texture`, see `coloring.rs`.
+
+layout(set = 0, binding = 0) uniform Iterations {
+    float num_iters;
+};
+
+layout(set = 0, binding = 1) uniform Coloring {
+    float palette_offset;
+    float palette_scale;
+    int mode;
+    int interior_mode;
+};
+
+// The gradient of `palette.rs`, which wraps around.
+layout(set = 0, binding = 2) uniform texture1D palette_texture;
+layout(set = 0, binding = 3) uniform sampler palette_sampler;
+
+layout(set = 0, binding = 4) uniform texture2D iteration_texture;
+layout(set = 0, binding = 5) uniform sampler iteration_sampler;
+
+// Filled by `histogram.comp` and `histogram_cdf.comp`.
+const int HISTOGRAM_BINS = 1024;
+
+layout(std430, set = 0, binding = 6) buffer Histogram {
+    uint counts[HISTOGRAM_BINS];
+    // Fraction of the escaped pixels up to and including each bin.
+    float cdf[HISTOGRAM_BINS];
+};
+
+// What `visit` in `mandelbrot.frag` measured along the orbit: x is the distance from the trap,
+// z the period and w the average.
+layout(set = 0, binding = 7) uniform texture2D orbit_texture;
+
+layout(set = 0, binding = 8) uniform Trap {
+    vec2 trap_center;
+    float trap_size;
+    int trap_shape;
+};
+
+const int STALKS = 4;
+
+layout(set = 0, binding = 9) uniform Average {
+    int average;
+    float stripe_density;
+    float average_mix;
+};
+
+const int NO_AVERAGE = 0;
+
+// Which way the outside slopes, see `escaped` in `mandelbrot.frag`.
+layout(set = 0, binding = 10) uniform texture2D normal_texture;
+
+// See `lighting.rs`, the direction points at the light.
+layout(set = 0, binding = 11) uniform Lighting {
+    vec3 light_direction;
+    int lighting;
+    float light_height;
+    float light_specular;
+};
+
+// See `views/progress.rs`.
+layout(set = 0, binding = 12) uniform Progress {
+    ivec2 progress_origin;
+    int coarse;
+    int block;
+    int tile_size;
+    int tiles_per_row;
+    int tiles_done;
+};
+
+// Light that reaches the slopes facing away too, and how sharp the highlights are.
+const float AMBIENT = 0.3;
+const float SHININESS = 20.0;
+
+layout(location = 0) out vec4 outColor;
+
+const int SMOOTH = 0;
+const int HISTOGRAM = 1;
+const int LINES = 2;
+const int GLOW = 3;
+const int DEM = 4;
+const int TRAP = 5;
+
+const int BLACK = 0;
+const int MAGNITUDE = 1;
+const int ANGLE = 2;
+const int PERIOD = 3;
+const int DISTANCE = 4;
+
+// In pixels, keep in sync with `coloring.rs`.
+const float LINE_WIDTH = 1.5;
+const float GLOW_WIDTH = 8.0;
+const float DEM_THRESHOLD = 1.0;
+
+// Iterations per length of the gradient at a scale of 1. Keep in sync with `PALETTE_PERIOD` in `palette.rs`.
+const float PALETTE_PERIOD = 240.0;
+const float FADE_ITERATIONS = 50.0;
+// Fraction of the palette between neighbouring periods, the golden ratio keeps them apart.
+const float PERIOD_STEP = 0.618034;
+// Lengths of the palette per doubling of the interior distance.
+const float INTERIOR_DISTANCE_STEP = 0.1;
+const float PI = 3.14159265;
+
+// Fraction of the escaped pixels that took fewer iterations than `it`,
+// interpolated within the bin so that the bands stay smooth.
+float equalized(float it) {
+    float x = clamp(it / num_iters, 0.0, 1.0) * float(HISTOGRAM_BINS);
+    int bin = min(int(x), HISTOGRAM_BINS - 1);
+    float before = bin > 0 ? cdf[bin - 1] : 0.0;
+    return mix(before, cdf[bin], x - float(bin));
+}
+
+vec3 sample_palette(float position) {
+    return textureLod(sampler1D(palette_texture, palette_sampler), position * palette_scale + palette_offset, 0.0).rgb;
+}
+
+// `escape` is what `interior` in `mandelbrot.frag` left, black where the mode has nothing to go by.
+vec4 interior_color(vec4 escape, vec4 orbit) {
+    vec2 z = escape.yz;
+    float period = orbit.z;
+    float distance = escape.w;
+    if (interior_mode == MAGNITUDE) return vec4(sample_palette(length(z)), 1.0);
+    if (interior_mode == ANGLE) return vec4(sample_palette(atan(z.y, z.x) / (2.0 * PI)), 1.0);
+    if (interior_mode == PERIOD && period > 0.0) return vec4(sample_palette(period * PERIOD_STEP), 1.0);
+    if (interior_mode == DISTANCE && distance > 0.0) {
+        // Darkens towards the boundary, like the outside does towards the iterations running out.
+        vec3 rgb = sample_palette(log2(1.0 + distance) * INTERIOR_DISTANCE_STEP);
+        return vec4(rgb * distance / (1.0 + distance), 1.0);
+    }
+    return vec4(0, 0, 0, 1);
+}
+
+// `escape` is what `escaped` in `mandelbrot.frag` left: the iterations, the final `z` and the distance in pixels,
+// `orbit` what `visit` left.
+vec4 color(vec4 escape, vec4 orbit) {
+    float it = escape.x;
+    float distance = escape.w;
+    float trap = orbit.x;
+    // The closest pass by the trap picks the color and darkens it, inside of the set as well.
+    if (mode == TRAP && trap_shape != STALKS) return vec4(sample_palette(trap) / (1.0 + trap), 1.0);
+    // Formulas without a distance estimate fall back to `SMOOTH`, as do the stalks.
+    int coloring = mode >= LINES && (distance < 0.0 || mode == TRAP) ? SMOOTH : mode;
+    if (coloring == LINES) {
+        // The set is drawn as paper too, only its outline shows.
+        if (it == 0.0) return vec4(1.0);
+        return vec4(vec3(clamp(distance / LINE_WIDTH, 0.0, 1.0)), 1.0);
+    }
+    if (it == 0.0) return interior_color(escape, orbit);
+    if (coloring == DEM && distance < DEM_THRESHOLD) return vec4(0, 0, 0, 1);
+    // Fades to black right before the iterations run out, where the bands get too thin to make out.
+    float fade = min(1.0, (num_iters - it) / FADE_ITERATIONS);
+    if (coloring == GLOW) fade *= GLOW_WIDTH / (GLOW_WIDTH + distance);
+    float position;
+    if (coloring == HISTOGRAM) {
+        position = equalized(it);
+    } else {
+        position = it / PALETTE_PERIOD;
+    }
+    vec3 rgb = sample_palette(position) * fade;
+    if (average != NO_AVERAGE) rgb = mix(rgb, sample_palette(orbit.w) * fade, average_mix);
+    // The stalks are drawn in white over the escaped pixels whose orbits came within the size of the cross.
+    if (mode == TRAP && trap < 1.0) rgb = mix(rgb, vec3(1.0), 1.0 - trap);
+    return vec4(rgb, 1.0);
+}
+
+// Shades `rgb` as a surface with the set standing out of the plane, the outside sloping down away from it.
+vec3 light(vec3 rgb, vec2 slope) {
+    vec3 normal = normalize(vec3(slope * light_height, 1.0));
+    float diffuse = max(dot(normal, light_direction), 0.0);
+    // Blinn-Phong, seen from straight above.
+    vec3 halfway = normalize(light_direction + vec3(0.0, 0.0, 1.0));
+    float specular = light_specular * pow(max(dot(normal, halfway), 0.0), SHININESS);
+    return rgb * (AMBIENT + (1.0 - AMBIENT) * diffuse) + specular;
+}
+
+// Where the iteration texture has what belongs at `texel`: in tiles not yet drawn at full resolution
+// the corner of the block of the coarse pass.
+ivec2 drawn(ivec2 texel) {
+    ivec2 offset = texel - progress_origin;
+    ivec2 tile = offset / tile_size;
+    if (tile.y * tiles_per_row + tile.x < tiles_done) {
+        return texel;
+    }
+    return progress_origin + offset / block * block;
+}
+
+void main() {
+    ivec2 texel = drawn(ivec2(gl_FragCoord.xy));
+    vec4 escape = texelFetch(sampler2D(iteration_texture, iteration_sampler), texel, 0);
+    outColor = color(escape, texelFetch(sampler2D(orbit_texture, iteration_sampler), texel, 0));
+    // Only the escaped pixels with a distance estimate have a slope.
+    if (lighting != 0 && escape.x != 0.0 && escape.w >= 0.0) {
+        vec2 slope = texelFetch(sampler2D(normal_texture, iteration_sampler), texel, 0).xy;
+        outColor.rgb = light(outColor.rgb, slope);
+    }
+}
@@ -0,0 +1,76 @@
+#version 450
+precision highp float;
+
+// Lyapunov exponents of the logistic map, `a` growing to the right and `b` downwards.
+// Stable where negative, chaotic where positive. See `lyapunov.rs`.
+
+layout(set = 0, binding = 0) uniform WindowSize {
+    vec2 size;
+};
+
+layout(set = 0, binding = 1) uniform Zoom {
+    vec2 zoom;
+};
+
+layout(set = 0, binding = 2) uniform Pos {
+    vec4 pos;
+};
+
+layout(set = 0, binding = 3) uniform Iterations {
+    float num_iters;
+};
+
+layout(set = 0, binding = 10) uniform Lyapunov {
+    vec4 stable_color;
+    vec4 chaotic_color;
+    // Bit `i` is set where the `i`th letter is `B`.
+    uint sequence;
+    float sequence_length;
+    float warmup;
+};
+
+layout(location = 0) out vec4 outColor;
+
+// Keeps the logarithm finite where the derivative happens to be exactly 0.
+const float MIN_DERIVATIVE = 1e-30;
+
+vec2 transform(vec2 x) {
+    return zoom.x * (x-size/2) - pos.xy;
+}
+
+float rate(vec2 ab, int letter) {
+    return ((sequence >> uint(letter)) & 1u) == 1u ? ab.y : ab.x;
+}
+
+void main() {
+    vec2 ab = transform(gl_FragCoord.xy);
+    // Outside of these the map doesn't stay between 0 and 1.
+    if (any(lessThan(ab, vec2(0.0))) || any(greaterThan(ab, vec2(4.0)))) {
+        outColor = vec4(0, 0, 0, 1);
+        return;
+    }
+
+    int count = int(sequence_length);
+    int letter = 0;
+    float x = 0.5;
+    for (int i = 0; i < int(warmup); i++) {
+        float r = rate(ab, letter);
+        x = r * x * (1.0 - x);
+        letter = (letter + 1) % count;
+    }
+
+    float sum = 0.0;
+    for (int i = 0; i < num_iters; i++) {
+        float r = rate(ab, letter);
+        sum += log(max(abs(r * (1.0 - 2.0 * x)), MIN_DERIVATIVE));
+        x = r * x * (1.0 - x);
+        letter = (letter + 1) % count;
+    }
+    float exponent = sum / num_iters;
+
+    if (exponent < 0.0) {
+        outColor = vec4(stable_color.rgb * (1.0 - exp(exponent)), 1.0);
+    } else {
+        outColor = vec4(chaotic_color.rgb * (1.0 - exp(-exponent)), 1.0);
+    }
+}
@@ -6,12 +6,14 @@ layout(set = 0, binding = 0) uniform WindowSize { // https://github.com/gfx-rs/w
     vec2 size;
 };
 
+// `zoom` and `pos` are double-single numbers, the high part in `x` (or `xy`)
+// and the low part in `y` (or `zw`), see `split` in `utils.rs`.
 layout(set = 0, binding = 1) uniform Zoom {
-    float zoom;
+    vec2 zoom;
 };
 
 layout(set = 0, binding = 2) uniform Pos {
-    vec2 pos;
+    vec4 pos;
 };
 
 layout(set = 0, binding = 3) uniform Iterations {
@@ -23,82 +25,630 @@ layout(set = 0, binding = 4) uniform Julia {
 };
 
 layout(set = 0, binding = 5) uniform Generator {
-    vec2 generator;
+    vec4 generator;
 };
 
+// What gets iterated, see `Family` in `utils.rs`. `power` is the exponent of the Multibrot.
+layout(set = 0, binding = 8) uniform Formula {
+    int family;
+    float power;
+};
+
+const int MANDELBROT = 0;
+const int BURNING_SHIP = 1;
+const int TRICORN = 2;
+const int MULTIBROT = 3;
+const int CUSTOM = 4;
+
+// Reference orbits for perturbation, see `perturbation.rs`.
+const int MAX_REFERENCES = 8;
+
+layout(std430, set = 0, binding = 6) readonly buffer Orbits {
+    vec2 orbits[];
+};
+
+layout(set = 0, binding = 7) uniform Perturbation {
+    // xy: offset in pixels from the middle of the window, z: start in `orbits`, w: length.
+    vec4 references[MAX_REFERENCES];
+    float reference_count;
+    // The zoom is `delta_mantissa * exp2(delta_exponent)`.
+    float delta_mantissa;
+    float delta_exponent;
+    float perturbation;
+    // Series approximation for reference 0: `a` in `series_ab.xy`, `b` in `series_ab.zw`
+    // and `c` in `series_c.xy`, the iterations skipped in `series_c.z` and their exponent in `series_c.w`.
+    vec4 series_ab;
+    vec4 series_c;
+};
+
+// Orbit trap, see `trap.rs`.
+layout(set = 0, binding = 11) uniform Trap {
+    vec2 trap_center;
+    float trap_size;
+    int trap_shape;
+};
+
+const int POINT = 0;
+const int LINE = 1;
+const int CROSS = 2;
+const int CIRCLE = 3;
+const int STALKS = 4;
+
+// Averaged along the orbit by `visit`, see `average.rs`.
+layout(set = 0, binding = 12) uniform Average {
+    int average;
+    float stripe_density;
+    float average_mix;
+};
+
+// See `views/progress.rs`.
+layout(set = 0, binding = 13) uniform Progress {
+    ivec2 progress_origin;
+    int coarse;
+    int block;
+    int tile_size;
+    int tiles_per_row;
+    int tiles_done;
+};
+
+const int NO_AVERAGE = 0;
+const int STRIPE = 1;
+const int TRIANGLE = 2;
+const int CURVATURE = 3;
+
+// See `coloring.rs`, which tells `visit` whether to keep track of the trap and the cycles.
+layout(set = 0, binding = 14) uniform Coloring {
+    float palette_offset;
+    float palette_scale;
+    int coloring_mode;
+    int interior_mode;
+};
+
+const int TRAP = 5;
+const int PERIOD = 3;
+const int DISTANCE = 4;
+
 layout(location = 0) out vec4 outColor;
+// What the orbit passed by on the way, see `visit`.
+layout(location = 1) out vec4 outOrbit;
+// Which way the outside slopes, see `escaped`.
+layout(location = 2) out vec2 outNormal;
 
 float r = 200;
 float log_r = log(r);
 
+// Below this zoom neighbouring pixels are no longer distinguishable in `float`
+// and we switch to double-single arithmetic, on every adapter: there is no `double` path,
+// as wgpu 0.4 can neither tell whether an adapter has `shaderFloat64` nor enable it.
+// Keep in sync with `DOUBLE_SINGLE_ZOOM` in `utils.rs`.
+const float DOUBLE_SINGLE_ZOOM = 5e-6;
+
 vec2 transform(vec2 x) {
-    return zoom * (x-size/2) - pos;
+    return zoom.x * (x-size/2) - pos.xy;
 }
 
 vec2 transform_julia(vec2 x) {
     return x;
 }
 
-vec2 pow(vec2 z, float i_pow) {
-    return vec2(pow(z.x, i_pow) - pow(z.y, i_pow), i_pow * z.x *z.y);
-}
-
 vec2 square(vec2 z) {
     return vec2(pow(z.x, 2.0) - pow(z.y, 2.0), 2.0 * z.x * z.y);
 }
 
-float iterations_julia(vec2 c) {
-    vec2 gen = transform_julia(generator);
+vec2 cmul(vec2 a, vec2 b) {
+    return vec2(a.x * b.x - a.y * b.y, a.x * b.y + a.y * b.x);
+}
+
+// Up to this whole exponent `cpow` multiplies, which is exact and keeps the symmetry.
+const float MAX_MULTIPLIED_POWER = 16.0;
+
+bool is_multiplied(float p) {
+    return p == floor(p) && p >= 1.0 && p <= MAX_MULTIPLIED_POWER;
+}
+
+// z^p, the principal value for exponents that aren't whole.
+vec2 cpow(vec2 z, float p) {
+    if (is_multiplied(p)) {
+        vec2 result = z;
+        for (int i = 1; i < int(p); i++) result = cmul(result, z);
+        return result;
+    }
+    float len = length(z);
+    if (len == 0.0) return vec2(0.0);
+    float angle = p * atan(z.y, z.x);
+    return pow(len, p) * vec2(cos(angle), sin(angle));
+}
+
+// The formula without `+ c`.
+vec2 formula(vec2 z) {
+    if (family == BURNING_SHIP) return square(abs(z));
+    if (family == TRICORN) return square(vec2(z.x, -z.y));
+    if (family == MULTIBROT) return cpow(z, power);
+    return square(z);
+}
+
+// Complex functions of custom formulas, see `custom_formula.rs`.
+
+vec2 cdiv(vec2 a, vec2 b) {
+    return vec2(a.x * b.x + a.y * b.y, a.y * b.x - a.x * b.y) / dot(b, b);
+}
+
+vec2 cexp(vec2 z) {
+    return exp(z.x) * vec2(cos(z.y), sin(z.y));
+}
+
+vec2 clog(vec2 z) {
+    return vec2(log(length(z)), atan(z.y, z.x));
+}
+
+vec2 cpow_complex(vec2 z, vec2 p) {
+    if (z == vec2(0.0)) return vec2(0.0);
+    return cexp(cmul(p, clog(z)));
+}
+
+vec2 csqrt(vec2 z) {
+    return cpow(z, 0.5);
+}
+
+vec2 csin(vec2 z) {
+    return vec2(sin(z.x) * cosh(z.y), cos(z.x) * sinh(z.y));
+}
+
+vec2 ccos(vec2 z) {
+    return vec2(cos(z.x) * cosh(z.y), -sin(z.x) * sinh(z.y));
+}
+
+vec2 ctan(vec2 z) {
+    return cdiv(csin(z), ccos(z));
+}
+
+vec2 csinh(vec2 z) {
+    return vec2(sinh(z.x) * cos(z.y), cosh(z.x) * sin(z.y));
+}
+
+vec2 ccosh(vec2 z) {
+    return vec2(cosh(z.x) * cos(z.y), sinh(z.x) * sin(z.y));
+}
+
+vec2 ctanh(vec2 z) {
+    return cdiv(csinh(z), ccosh(z));
+}
+
+vec2 cabs(vec2 z) {
+    return abs(z);
+}
+
+vec2 cconj(vec2 z) {
+    return vec2(z.x, -z.y);
+}
+
+vec2 cre(vec2 z) {
+    return vec2(z.x, 0.0);
+}
+
+vec2 cim(vec2 z) {
+    return vec2(z.y, 0.0);
+}
+
+// The whole step of a custom formula, `+ c` included. `custom_formula.rs`
+// replaces the marked line with the formula the user typed in.
+vec2 custom(vec2 z, vec2 c) {
+    return cmul(z, z) + c; // CUSTOM_FORMULA
+}
+
+vec2 iterate(vec2 z, vec2 c) {
+    if (family == CUSTOM) return custom(z, c);
+    return formula(z) + c;
+}
+
+// Derivative of `formula` at `z` applied to `dz`, as a 2x2 matrix where the formula isn't holomorphic.
+// Custom formulas have none, see `escaped`.
+vec2 dformula(vec2 z, vec2 dz) {
+    if (family == BURNING_SHIP) return 2.0 * vec2(z.x * dz.x - z.y * dz.y, sign(z.x * z.y) * (z.y * dz.x + z.x * dz.y));
+    if (family == TRICORN) return 2.0 * cconj(cmul(z, dz));
+    if (family == MULTIBROT) return power * cmul(cpow(z, power - 1.0), dz);
+    return 2.0 * cmul(z, dz);
+}
+
+// Distance of `z` from the trap, in sizes of the trap.
+float trap_distance(vec2 z) {
+    vec2 d = z - trap_center;
+    float distance;
+    if (trap_shape == LINE) distance = abs(d.y);
+    else if (trap_shape == CROSS || trap_shape == STALKS) distance = min(abs(d.x), abs(d.y));
+    else if (trap_shape == CIRCLE) distance = abs(length(d) - trap_size);
+    else distance = length(d);
+    return distance / trap_size;
+}
+
+// x: the smallest distance from the trap, y: the iteration it was reached at,
+// z: the period of the cycle the orbit settled into, 0 while none was found,
+// w: the average, left by `escaped`.
+vec4 orbit;
+
+// The sum of the terms of the average with and without the last one, and how many there are.
+float average_sum;
+float average_last_sum;
+int average_terms;
+// The two `z` before, for the curvature, and how many were visited.
+vec2 last_z;
+vec2 before_last_z;
+int visits;
+
+// The direction of `z / dz`, which the distance to the set grows in. Left by `escaped`.
+vec2 normal;
+
+const float PI = 3.14159265;
+
+// Brent's cycle detection: `z` is compared with the one saved at the last power of two.
+vec2 cycle_z;
+int cycle_i;
+bool cycle_returned;
+
+// Orbits that come back closer than this have found their cycle.
+const float PERIOD_EPSILON = 1e-5;
+
+void begin_orbit() {
+    orbit = vec4(1e20, 0.0, 0.0, 0.0);
+    cycle_z = vec2(1e20);
+    cycle_i = 0;
+    cycle_returned = false;
+    average_sum = 0.0;
+    average_last_sum = 0.0;
+    average_terms = 0;
+    visits = 0;
+    normal = vec2(0.0);
+}
+
+// What `visit` adds to the average, `c` being what `iterate` adds.
+// Returns a negative number where there is nothing to add yet.
+float average_term(vec2 z, vec2 c) {
+    if (average == STRIPE) return 0.5 + 0.5 * sin(stripe_density * atan(z.y, z.x));
+    if (average == TRIANGLE) {
+        // `z - c` is what `formula` made of the `z` before, which bounds `|z|` from both sides.
+        float a = length(z - c);
+        float low = abs(a - length(c));
+        float high = a + length(c);
+        return high > low ? (length(z) - low) / (high - low) : -1.0;
+    }
+    if (average == CURVATURE && visits >= 2) {
+        vec2 turn = cdiv(z - last_z, last_z - before_last_z);
+        return abs(atan(turn.y, turn.x)) / PI;
+    }
+    return -1.0;
+}
+
+// Called with every `z` of the orbit.
+void visit(vec2 z, vec2 c, int i) {
+    if (coloring_mode == TRAP) {
+        float distance = trap_distance(z);
+        if (distance < orbit.x) orbit.xy = vec2(distance, float(i));
+    }
+    if (interior_mode == PERIOD || interior_mode == DISTANCE) {
+        // The first return is the period, later ones come back after multiples of it.
+        // Every save starts over, so that returns of an orbit still spiraling in get corrected.
+        if (!cycle_returned && length(z - cycle_z) < PERIOD_EPSILON) {
+            orbit.z = float(i - cycle_i);
+            cycle_returned = true;
+        }
+        if ((i & (i + 1)) == 0) {
+            cycle_z = z;
+            cycle_i = i;
+            cycle_returned = false;
+        }
+    }
+    if (average != NO_AVERAGE) {
+        float term = average_term(z, c);
+        if (term >= 0.0) {
+            average_last_sum = average_sum;
+            average_sum += term;
+            average_terms++;
+        }
+        before_last_z = last_z;
+        last_z = z;
+    }
+    visits++;
+}
+
+// Newton steps that pull the last `z` onto the cycle before `interior` measures it.
+const int NEWTON_STEPS = 4;
+
+// What is left in the iteration texture for pixels that don't escape: no iterations, the last `z`
+// and the distance to the boundary from inside in pixels, 0 where there is no estimate.
+// The estimate needs `formula` holomorphic and derivatives by `c`, so only the Mandelbrot and Multibrot sets get one.
+vec4 interior(vec2 z, vec2 c) {
+    int period = int(orbit.z);
+    if (period == 0 || is_julia || (family != MANDELBROT && family != MULTIBROT)) return vec4(0.0, z, 0.0);
+    vec2 z0 = z;
+    for (int n = 0; n < NEWTON_STEPS; n++) {
+        vec2 zp = z0;
+        vec2 dz = vec2(1.0, 0.0);
+        for (int j = 0; j < period; j++) {
+            dz = dformula(zp, dz);
+            zp = formula(zp) + c;
+        }
+        z0 -= cdiv(zp - z0, dz - vec2(1.0, 0.0));
+    }
+    // Derivatives of the cycle by `z` and `c`, and the second ones by `z` twice and by `z` and `c`.
+    vec2 dz = vec2(1.0, 0.0);
+    vec2 dc = vec2(0.0);
+    vec2 dzdz = vec2(0.0);
+    vec2 dzdc = vec2(0.0);
+    for (int j = 0; j < period; j++) {
+        vec2 d1 = dformula(z0, vec2(1.0, 0.0));
+        vec2 d2 = family == MULTIBROT ? power * (power - 1.0) * cpow(z0, power - 2.0) : vec2(2.0, 0.0);
+        dzdz = cmul(d2, cmul(dz, dz)) + cmul(d1, dzdz);
+        dzdc = cmul(d2, cmul(dz, dc)) + cmul(d1, dzdc);
+        dc = cmul(d1, dc) + vec2(1.0, 0.0);
+        dz = cmul(d1, dz);
+        z0 = formula(z0) + c;
+    }
+    float multiplier = dot(dz, dz);
+    // Not attracting after all, the orbit only passed by.
+    if (multiplier >= 1.0) return vec4(0.0, z, 0.0);
+    float distance = (1.0 - multiplier) / length(dzdc + cdiv(cmul(dzdz, dc), vec2(1.0, 0.0) - dz));
+    return vec4(0.0, z, distance / zoom.x);
+}
+
+// What is left in the iteration texture for `colorize.frag`: the smooth iteration count,
+// the final `z` and the distance to the set in pixels, `dz` being the derivative of `z` by the pixel.
+// Pixels that don't escape get an iteration count and a distance of 0, custom formulas a distance of -1.
+// Leaves the average in `orbit.w`, between the one with the last term and the one without
+// by how far past the escape radius `z` went, the way the smooth iteration count is, and the `normal`.
+vec4 escaped(int i, vec2 z, vec2 dz) {
+    float len = length(z);
+    float distance = family == CUSTOM ? -1.0 : len * log(len) / length(dz);
+    vec2 u = cdiv(z, dz);
+    // The derivative overflowed, right at the boundary.
+    if (family != CUSTOM && !any(isnan(u)) && !any(isinf(u))) normal = normalize(u);
+    if (average_terms >= 2) {
+        float last = average_sum / float(average_terms);
+        float before = average_last_sum / float(average_terms - 1);
+        orbit.w = mix(last, before, clamp(log(len)/log_r - 1.0, 0.0, 1.0));
+    }
+    return vec4(float(i) - log(len)/log_r, z, distance);
+}
+
+vec4 iterations_julia(vec2 c) {
+    vec2 gen = transform_julia(generator.xy);
 //    vec2 gen = generator;
     vec2 z = c;
+    vec2 dz = vec2(zoom.x, 0.0);
     for (int i = 0; i < num_iters; i++) {
-        z = square(z) + gen;
-        float len = length(z);
-        if (len > r) return float(i) - log(len)/log_r;
+        dz = dformula(z, dz);
+        z = iterate(z, gen);
+        visit(z, gen, i);
+        if (length(z) > r) return escaped(i, z, dz);
     }
-    return 0.0;
+    return interior(z, gen);
 }
 
-float iterations_mandelbrot(vec2 c) {
+vec4 iterations_mandelbrot(vec2 c) {
     vec2 z = c;
+    vec2 dz = vec2(zoom.x, 0.0);
     for (int i = 0; i < num_iters; i++) {
-        z = square(z) + c;
-        float len = length(z);
-        if (len > r) return float(i) - log(len)/log_r;
+        dz = dformula(z, dz) + vec2(zoom.x, 0.0);
+        z = iterate(z, c);
+        visit(z, c, i);
+        if (length(z) > r) return escaped(i, z, dz);
+    }
+    return interior(z, c);
+}
+
+// Double-single arithmetic: a number is the unevaluated sum `hi + lo` of two floats,
+// which gives about twice the precision of `float`. wgpu doesn't let us enable
+// `shaderFloat64`, so this is how we get past `float` on every adapter.
+// Everything has to be `precise`, otherwise the compiler is free to
+// simplify the error terms away. Port of DSFUN90 by David H. Bailey.
+
+vec2 ds_add(vec2 a, vec2 b) {
+    precise float t1 = a.x + b.x;
+    precise float e = t1 - a.x;
+    precise float t2 = ((b.x - e) + (a.x - (t1 - e))) + a.y + b.y;
+    precise float hi = t1 + t2;
+    precise float lo = t2 - (hi - t1);
+    return vec2(hi, lo);
+}
+
+vec2 ds_mul(vec2 a, vec2 b) {
+    const float split = 4097.0; // 2^12 + 1
+    precise float cona = a.x * split;
+    precise float a1 = cona - (cona - a.x);
+    precise float a2 = a.x - a1;
+    precise float conb = b.x * split;
+    precise float b1 = conb - (conb - b.x);
+    precise float b2 = b.x - b1;
+
+    precise float c11 = a.x * b.x;
+    precise float c21 = a2 * b2 + (a2 * b1 + (a1 * b2 + (a1 * b1 - c11)));
+    precise float c2 = a.x * b.y + a.y * b.x;
+
+    precise float t1 = c11 + c2;
+    precise float e = t1 - c11;
+    precise float t2 = a.y * b.y + ((c2 - e) + (c11 - (t1 - e))) + c21;
+    precise float hi = t1 + t2;
+    precise float lo = t2 - (hi - t1);
+    return vec2(hi, lo);
+}
+
+// Complex numbers of double-singles: real part in `xy`, imaginary in `zw`.
+
+vec4 dc_add(vec4 a, vec4 b) {
+    return vec4(ds_add(a.xy, b.xy), ds_add(a.zw, b.zw));
+}
+
+vec4 dc_square(vec4 z) {
+    vec2 re = ds_add(ds_mul(z.xy, z.xy), -ds_mul(z.zw, z.zw));
+    vec2 im = ds_mul(vec2(2.0, 0.0), ds_mul(z.xy, z.zw));
+    return vec4(re, im);
+}
+
+vec4 dc_mul(vec4 a, vec4 b) {
+    vec2 re = ds_add(ds_mul(a.xy, b.xy), -ds_mul(a.zw, b.zw));
+    vec2 im = ds_add(ds_mul(a.xy, b.zw), ds_mul(a.zw, b.xy));
+    return vec4(re, im);
+}
+
+vec2 ds_abs(vec2 a) {
+    return a.x < 0.0 ? -a : a;
+}
+
+// `formula` in double-single, exponents that aren't whole only get float precision.
+vec4 dc_formula(vec4 z) {
+    if (family == BURNING_SHIP) return dc_square(vec4(ds_abs(z.xy), ds_abs(z.zw)));
+    if (family == TRICORN) return dc_square(vec4(z.xy, -z.zw));
+    if (family == MULTIBROT) {
+        if (is_multiplied(power)) {
+            vec4 result = z;
+            for (int i = 1; i < int(power); i++) result = dc_mul(result, z);
+            return result;
+        }
+        vec2 result = cpow(z.xz, power);
+        return vec4(result.x, 0.0, result.y, 0.0);
     }
-    return 0.0;
+    return dc_square(z);
+}
+
+float dc_length(vec4 z) {
+    return length(vec2(z.x, z.z));
 }
 
-float hue2c(float p, float q, float t, int modifier) {
-    t = mod(t + float(modifier), 6.0);
-    if (t < 1.0) return p + (q - p) * t;
-    if (t < 3.0) return q;
-    if (t < 4.0) return p + (q - p) * (4.0 - t);
-    return p;
+vec4 transform_ds(vec2 x) {
+    vec2 offset = x - size/2;
+    return vec4(
+        ds_add(ds_mul(zoom, vec2(offset.x, 0.0)), -pos.xz),
+        ds_add(ds_mul(zoom, vec2(offset.y, 0.0)), -pos.yw)
+    );
+}
+
+// The derivative doesn't need the precision, it stays in float.
+vec4 iterations_julia_ds(vec4 c) {
+    vec4 gen = generator.xzyw;
+    vec4 z = c;
+    vec2 dz = vec2(zoom.x, 0.0);
+    for (int i = 0; i < num_iters; i++) {
+        dz = dformula(z.xz, dz);
+        z = dc_add(dc_formula(z), gen);
+        visit(z.xz, gen.xz, i);
+        if (dc_length(z) > r) return escaped(i, z.xz, dz);
+    }
+    return interior(z.xz, gen.xz);
 }
 
-vec4 hslToRgba(float h, float s, float l) {
-    if (s == 0.0) return vec4(l, l, l, 1.0);
-    float q = l < 0.5 ? l * (1.0 + s) : l + s - l * s;
-    float p = 2.0 * l - q;
-    h *= 6.0;
-    return vec4(hue2c(p, q, h, 2), hue2c(p, q, h, 0), hue2c(p, q, h, 4), 1);
+vec4 iterations_mandelbrot_ds(vec4 c) {
+    vec4 z = c;
+    vec2 dz = vec2(zoom.x, 0.0);
+    for (int i = 0; i < num_iters; i++) {
+        dz = dformula(z.xz, dz) + vec2(zoom.x, 0.0);
+        z = dc_add(dc_formula(z), c);
+        visit(z.xz, c.xz, i);
+        if (dc_length(z) > r) return escaped(i, z.xz, dz);
+    }
+    return interior(z.xz, c.xz);
 }
 
-vec4 color(float it) {
-    if (it == 0.0) return vec4(0, 0, 0, 1);
-    float l = min(1.0, (800.0 - it) / 50.0);
-    return hslToRgba(it / 240.0, 1.0, l * .5);
+// Perturbation: only the difference `d` from a reference orbit computed on the CPU is iterated.
+// While the true difference is too small for a float, `d` is kept divided by `exp2(e)`.
+// Pauldelbrot's criterion: where `|Z + d| < GLITCH_TOLERANCE * |Z|` the result can't be trusted.
+// Only used for `MANDELBROT`, see `compute` in `perturbation.rs`.
+const float GLITCH_TOLERANCE = 1e-3;
+
+// The derivatives of `d` and `dc` by the pixel, `dd` and `ddc`, are kept divided by `exp2(e)` as well.
+vec4 iterations_perturbed(vec2 x, int k, out bool glitched) {
+    glitched = false;
+    // The iterations skipped by the series approximation aren't visited.
+    begin_orbit();
+    int start = int(references[k].z);
+    int len = int(references[k].w);
+    int e = int(delta_exponent);
+    vec2 dc = delta_mantissa * (x - size/2 - references[k].xy);
+    vec2 d = dc;
+    vec2 ddc = vec2(delta_mantissa, 0.0);
+    vec2 dd = ddc;
+    int skipped = 0;
+    if (k == 0 && series_c.z > 0.0) {
+        vec2 dc2 = cmul(dc, dc);
+        d = cmul(series_ab.xy, dc) + cmul(series_ab.zw, dc2) + cmul(series_c.xy, cmul(dc, dc2));
+        dd = delta_mantissa * (series_ab.xy + 2.0 * cmul(series_ab.zw, dc) + 3.0 * cmul(series_c.xy, dc2));
+        int shift = int(series_c.w);
+        dc = ldexp(dc, ivec2(-shift));
+        ddc = ldexp(ddc, ivec2(-shift));
+        e += shift;
+        skipped = int(series_c.z);
+    }
+    vec2 z = orbits[start + skipped] + ldexp(d, ivec2(e));
+    vec2 c = is_julia ? generator.xy : orbits[start] + ldexp(dc, ivec2(e));
+    for (int i = skipped; i < num_iters; i++) {
+        if (i + 1 >= len) {
+            // The reference escaped before this pixel did.
+            glitched = true;
+            return vec4(0.0);
+        }
+        dd = 2.0 * cmul(z, dd);
+        d = 2.0 * cmul(orbits[start + i], d) + ldexp(cmul(d, d), ivec2(e));
+        if (!is_julia) {
+            d += dc;
+            dd += ddc;
+        }
+        float m = max(abs(d.x), abs(d.y));
+        if (e < 0 && m > 1.0) {
+            int s = min(-e, int(floor(log2(m))));
+            d = ldexp(d, ivec2(-s));
+            dc = ldexp(dc, ivec2(-s));
+            dd = ldexp(dd, ivec2(-s));
+            ddc = ldexp(ddc, ivec2(-s));
+            e += s;
+        }
+        vec2 z_ref = orbits[start + i + 1];
+        z = z_ref + ldexp(d, ivec2(e));
+        visit(z, c, i);
+        float len_z = length(z);
+        if (len_z > r) return escaped(i, z, ldexp(dd, ivec2(e)));
+        if (len_z < GLITCH_TOLERANCE * length(z_ref)) {
+            glitched = true;
+            return vec4(0.0);
+        }
+    }
+    return interior(z, c);
 }
 
+// Glitched pixels fall back to the next reference, which the CPU placed in a glitched spot.
+vec4 iterations_perturbation(vec2 x) {
+    bool glitched = true;
+    vec4 escape = vec4(0.0);
+    for (int k = 0; k < int(reference_count) && glitched; k++) {
+        escape = iterations_perturbed(x, k, glitched);
+    }
+    return escape;
+}
 
 void main() {
-    vec2 transformed = transform(gl_FragCoord.xy);
-    float iter;
-    if(is_julia) {
-        iter = iterations_julia(transformed);
+    // The coarse pass only iterates the top left corner of every block.
+    ivec2 texel = ivec2(gl_FragCoord.xy) - progress_origin;
+    if (coarse != 0 && (texel.x % block != 0 || texel.y % block != 0)) {
+        discard;
+    }
+    begin_orbit();
+    vec4 escape;
+    if (perturbation > 0.5) {
+        escape = iterations_perturbation(gl_FragCoord.xy);
+    } else if (zoom.x < DOUBLE_SINGLE_ZOOM && family != CUSTOM) {
+        vec4 transformed = transform_ds(gl_FragCoord.xy);
+        if(is_julia) {
+            escape = iterations_julia_ds(transformed);
+        } else {
+            escape = iterations_mandelbrot_ds(transformed);
+        }
     } else {
-        iter = iterations_mandelbrot(transformed);
+        vec2 transformed = transform(gl_FragCoord.xy);
+        if(is_julia) {
+            escape = iterations_julia(transformed);
+        } else {
+            escape = iterations_mandelbrot(transformed);
+        }
     }
-    outColor = color(iter);
+    // Colored by `colorize.frag`, see `escaped`.
+    outColor = escape;
+    outOrbit = orbit;
+    outNormal = normal;
 }
\ No newline at end of file
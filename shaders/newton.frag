@@ -0,0 +1,87 @@
+#version 450
+precision highp float;
+
+// Basins of attraction of Newton's method, colored by the root a pixel converges to
+// and darker the more iterations that took. See `polynomial.rs`.
+// The roots are spread over the palette, which is shared with the other views.
+
+layout(set = 0, binding = 0) uniform WindowSize {
+    vec2 size;
+};
+
+layout(set = 0, binding = 1) uniform Zoom {
+    vec2 zoom;
+};
+
+layout(set = 0, binding = 2) uniform Pos {
+    vec4 pos;
+};
+
+layout(set = 0, binding = 3) uniform Iterations {
+    float num_iters;
+};
+
+// Keep in sync with `MAX_ROOTS` in `polynomial.rs`.
+const int MAX_ROOTS = 8;
+
+layout(set = 0, binding = 9) uniform Roots {
+    // Only `xy` is used.
+    vec4 roots[MAX_ROOTS];
+    float root_count;
+};
+
+// See `coloring.rs`.
+layout(set = 0, binding = 14) uniform Coloring {
+    float palette_offset;
+    float palette_scale;
+    int coloring_mode;
+    int interior_mode;
+};
+
+// The gradient of `palette.rs`, which wraps around.
+layout(set = 0, binding = 15) uniform texture1D palette_texture;
+layout(set = 0, binding = 16) uniform sampler palette_sampler;
+
+layout(location = 0) out vec4 outColor;
+
+// Squared distance to a root below which a pixel counts as converged.
+const float TOLERANCE = 1e-8;
+
+vec2 transform(vec2 x) {
+    return zoom.x * (x-size/2) - pos.xy;
+}
+
+vec2 cinv(vec2 z) {
+    return vec2(z.x, -z.y) / dot(z, z);
+}
+
+vec3 sample_palette(float position) {
+    return textureLod(sampler1D(palette_texture, palette_sampler), position * palette_scale + palette_offset, 0.0).rgb;
+}
+
+vec4 color(int root, float it) {
+    return vec4(sample_palette(float(root) / root_count) * pow(0.93, it), 1.0);
+}
+
+void main() {
+    vec2 z = transform(gl_FragCoord.xy);
+    int count = int(root_count);
+    for (int i = 0; i < num_iters; i++) {
+        // p(z) / p'(z) is the inverse of the sum of the inverses of z - root.
+        vec2 sum = vec2(0.0);
+        for (int k = 0; k < count; k++) {
+            vec2 d = z - roots[k].xy;
+            float distance = dot(d, d);
+            if (distance < TOLERANCE) {
+                // Convergence is quadratic, each step doubles the logarithm of the distance.
+                // How far past the tolerance we got smooths out the bands between iterations.
+                float overshoot = log2(log(distance) / log(TOLERANCE));
+                outColor = color(k, max(0.0, float(i) - overshoot));
+                return;
+            }
+            sum += cinv(d);
+        }
+        z -= cinv(sum);
+    }
+    outColor = vec4(0, 0, 0, 1);
+}
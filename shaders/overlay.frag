@@ -0,0 +1,9 @@
+#version 450
+
+// Outline of the right-drag selection, see `src/views/selection.rs`.
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    outColor = vec4(1.0, 1.0, 1.0, 1.0);
+}
@@ -0,0 +1,119 @@
+//! Averages along the orbits of `mandelbrot.frag`, blended into the coloring of the escaped pixels.
+//!
+//! `visit` sums up a term of every `z`, `escaped` interpolates between the average
+//! with and without the last one by the smooth iteration count, so that no bands show.
+
+use std::fmt;
+use std::str::FromStr;
+use serde::{Serialize, Deserialize};
+use zerocopy::{AsBytes, FromBytes};
+
+/// Keep the order in sync with the constants in `mandelbrot.frag` and `colorize.frag`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AverageKind {
+	/// Nothing is averaged, nor blended in.
+	None,
+	/// Of `sin(density * arg z)`, which draws stripes that follow the field lines.
+	Stripe,
+	/// Where `|z|` lies between the bounds the triangle inequality gives it.
+	Triangle,
+	/// Of how sharply the orbit turns at every `z`.
+	Curvature,
+}
+
+impl AverageKind {
+	pub fn next(self) -> Self {
+		match self {
+			AverageKind::None => AverageKind::Stripe,
+			AverageKind::Stripe => AverageKind::Triangle,
+			AverageKind::Triangle => AverageKind::Curvature,
+			AverageKind::Curvature => AverageKind::None,
+		}
+	}
+}
+
+impl Default for AverageKind {
+	fn default() -> Self {
+		AverageKind::None
+	}
+}
+
+impl FromStr for AverageKind {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"none" => Ok(AverageKind::None),
+			"stripe" => Ok(AverageKind::Stripe),
+			"triangle" => Ok(AverageKind::Triangle),
+			"curvature" => Ok(AverageKind::Curvature),
+			_ => Err(format!("unknown average {:?}, expected none, stripe, triangle or curvature", s))
+		}
+	}
+}
+
+impl fmt::Display for AverageKind {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			AverageKind::None => write!(f, "No average"),
+			AverageKind::Stripe => write!(f, "Stripe average"),
+			AverageKind::Triangle => write!(f, "Triangle inequality average"),
+			AverageKind::Curvature => write!(f, "Curvature average"),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Average {
+	pub kind: AverageKind,
+	/// Stripes per turn around the origin of `AverageKind::Stripe`.
+	pub density: f32,
+	/// How much of the color comes from the average, 0 leaving the coloring as it is and 1 replacing it.
+	pub mix: f32,
+}
+
+/// `Average` as the shaders see it.
+#[repr(C)]
+#[derive(Clone, Copy, AsBytes, FromBytes)]
+pub struct AverageUniform {
+	pub kind: i32,
+	pub density: f32,
+	pub mix: f32,
+}
+
+impl Average {
+	pub fn uniform(&self) -> AverageUniform {
+		AverageUniform {
+			kind: self.kind as i32,
+			density: self.density,
+			mix: self.mix,
+		}
+	}
+
+	/// Moves the mix by `delta`, staying between 0 and 1.
+	pub fn add_mix(self, delta: f32) -> Self {
+		Self {
+			mix: (self.mix + delta).max(0.0).min(1.0),
+			..self
+		}
+	}
+
+	/// Moves the density by `delta`, keeping at least one stripe.
+	pub fn add_density(self, delta: f32) -> Self {
+		Self {
+			density: (self.density + delta).max(1.0),
+			..self
+		}
+	}
+}
+
+impl Default for Average {
+	fn default() -> Self {
+		Self {
+			kind: AverageKind::default(),
+			density: 5.0,
+			mix: 0.5,
+		}
+	}
+}
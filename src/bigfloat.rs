@@ -0,0 +1,337 @@
+//! Arbitrary precision numbers for the perturbation reference orbit.
+//!
+//! `BigFloat` is a fixed point number: an arbitrarily big integer
+//! scaled down by `2^bits`. `f64` runs out at a zoom of around `1e-16`,
+//! this keeps going for as long as we keep adding bits.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Sub, Mul, Neg};
+use std::str::FromStr;
+
+use num_bigint::{BigInt, Sign};
+use num_traits::{Zero, One, Signed, ToPrimitive};
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+
+/// Bits below the binary point that every number gets at least.
+pub const MIN_BITS: u32 = 64;
+
+/// Bits that parsing adds below the last digit given, and that printing leaves out again.
+const GUARD_BITS: u32 = 4;
+
+/// Decimal exponents parsing goes up to either way, far past any zoom that can be rendered,
+/// but before the powers of ten take up all the memory.
+const MAX_EXPONENT: i64 = 100_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BigFloat {
+	mantissa: BigInt,
+	bits: u32,
+}
+
+impl BigFloat {
+	pub fn zero(bits: u32) -> Self {
+		Self {
+			mantissa: BigInt::zero(),
+			bits,
+		}
+	}
+
+	/// Converts `value` exactly if `bits` allow it.
+	pub fn from_f64(value: f64, bits: u32) -> Self {
+		if value == 0.0 || !value.is_finite() {
+			return Self::zero(bits);
+		}
+		let (mantissa, exponent, sign) = integer_decode(value);
+		let mantissa = BigInt::from(mantissa) * BigInt::from(sign);
+		let shift = i64::from(exponent) + i64::from(bits);
+		let mantissa = if shift >= 0 {
+			mantissa << shift as usize
+		} else {
+			mantissa >> (-shift) as usize
+		};
+
+		Self { mantissa, bits }
+	}
+
+	pub fn to_f64(&self) -> f64 {
+		// Only the top bits fit into an `f64` anyway.
+		let length = self.mantissa.bits();
+		let shift = if length > 62 { length - 62 } else { 0 };
+		let top = (&self.mantissa >> shift).to_i64().unwrap_or(0) as f64;
+
+		top * 2f64.powi(shift as i32 - self.bits as i32)
+	}
+
+	pub fn bits(&self) -> u32 {
+		self.bits
+	}
+
+	/// Same number with `bits` bits below the binary point.
+	pub fn with_bits(&self, bits: u32) -> Self {
+		let mantissa = match bits.cmp(&self.bits) {
+			Ordering::Greater => &self.mantissa << (bits - self.bits) as usize,
+			Ordering::Less => &self.mantissa >> (self.bits - bits) as usize,
+			Ordering::Equal => self.mantissa.clone(),
+		};
+
+		Self { mantissa, bits }
+	}
+
+	/// Adds an `f64` without going through its limited precision first.
+	pub fn add_f64(&self, value: f64) -> Self {
+		self + &Self::from_f64(value, self.bits)
+	}
+
+	pub fn square(&self) -> Self {
+		self * self
+	}
+
+	fn aligned<'a>(a: &'a Self, b: &'a Self) -> (std::borrow::Cow<'a, Self>, std::borrow::Cow<'a, Self>) {
+		use std::borrow::Cow;
+		match a.bits.cmp(&b.bits) {
+			Ordering::Equal => (Cow::Borrowed(a), Cow::Borrowed(b)),
+			Ordering::Less => (Cow::Owned(a.with_bits(b.bits)), Cow::Borrowed(b)),
+			Ordering::Greater => (Cow::Borrowed(a), Cow::Owned(b.with_bits(a.bits))),
+		}
+	}
+}
+
+/// Like the deprecated `f64::integer_decode`, `value == mantissa * 2^exponent * sign`.
+fn integer_decode(value: f64) -> (u64, i16, i8) {
+	let bits = value.to_bits();
+	let sign: i8 = if bits >> 63 == 0 { 1 } else { -1 };
+	let mut exponent: i16 = ((bits >> 52) & 0x7ff) as i16;
+	let mantissa = if exponent == 0 {
+		(bits & 0x000f_ffff_ffff_ffff) << 1
+	} else {
+		(bits & 0x000f_ffff_ffff_ffff) | 0x0010_0000_0000_0000
+	};
+	exponent -= 1023 + 52;
+	(mantissa, exponent, sign)
+}
+
+impl<'a> Add<&'a BigFloat> for &'a BigFloat {
+	type Output = BigFloat;
+
+	fn add(self, other: &'a BigFloat) -> BigFloat {
+		let (a, b) = BigFloat::aligned(self, other);
+		BigFloat {
+			mantissa: &a.mantissa + &b.mantissa,
+			bits: a.bits,
+		}
+	}
+}
+
+impl<'a> Sub<&'a BigFloat> for &'a BigFloat {
+	type Output = BigFloat;
+
+	fn sub(self, other: &'a BigFloat) -> BigFloat {
+		let (a, b) = BigFloat::aligned(self, other);
+		BigFloat {
+			mantissa: &a.mantissa - &b.mantissa,
+			bits: a.bits,
+		}
+	}
+}
+
+impl<'a> Mul<&'a BigFloat> for &'a BigFloat {
+	type Output = BigFloat;
+
+	fn mul(self, other: &'a BigFloat) -> BigFloat {
+		let (a, b) = BigFloat::aligned(self, other);
+		BigFloat {
+			mantissa: (&a.mantissa * &b.mantissa) >> a.bits as usize,
+			bits: a.bits,
+		}
+	}
+}
+
+impl<'a> Neg for &'a BigFloat {
+	type Output = BigFloat;
+
+	fn neg(self) -> BigFloat {
+		BigFloat {
+			mantissa: -&self.mantissa,
+			bits: self.bits,
+		}
+	}
+}
+
+impl FromStr for BigFloat {
+	type Err = String;
+
+	/// Parses decimal numbers like `-0.74364388703715870475`, `1e-30` or `12.5E+3`,
+	/// with enough bits to hold every digit given, rounded to the nearest.
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let s = s.trim();
+		let (number, exponent) = match s.find(|c| c == 'e' || c == 'E') {
+			Some(index) => {
+				let exponent = s[index + 1..].parse::<i64>()
+					.map_err(|e| format!("exponent of {:?}: {}", s, e))?;
+				(&s[..index], exponent)
+			}
+			None => (s, 0)
+		};
+		let (negative, number) = match number.chars().next() {
+			Some('-') => (true, &number[1..]),
+			Some('+') => (false, &number[1..]),
+			_ => (false, number)
+		};
+		let (integer, fraction) = match number.find('.') {
+			Some(index) => (&number[..index], &number[index + 1..]),
+			None => (number, "")
+		};
+		let digits = format!("{}{}", integer, fraction);
+		if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+			return Err(format!("{:?} is not a number", s));
+		}
+
+		// value = digits * 10^exponent
+		let exponent = exponent.checked_sub(fraction.len() as i64)
+			.filter(|exponent| (-MAX_EXPONENT..=MAX_EXPONENT).contains(exponent))
+			.ok_or_else(|| format!("exponent of {:?} is out of range", s))?;
+		let bits = if exponent < 0 {
+			MIN_BITS.max((-exponent as f64 * std::f64::consts::LOG2_10).ceil() as u32 + GUARD_BITS)
+		} else {
+			MIN_BITS
+		};
+
+		let digits = BigInt::parse_bytes(digits.as_bytes(), 10)
+			.ok_or_else(|| format!("{:?} is not a number", s))?;
+		let ten = BigInt::from(10);
+		let mantissa = if exponent >= 0 {
+			(digits * num_traits::pow(ten, exponent as usize)) << bits as usize
+		} else {
+			let divisor = num_traits::pow(ten, -exponent as usize);
+			((digits << bits as usize) + (&divisor >> 1)) / divisor
+		};
+		let mantissa = if negative { -mantissa } else { mantissa };
+
+		Ok(Self { mantissa, bits })
+	}
+}
+
+impl fmt::Display for BigFloat {
+	/// Prints the decimal digits that the bits but the `GUARD_BITS` resolve. Parsing that gives back
+	/// the same number of bits, so that saving and loading a location over and over keeps it as it is.
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let magnitude = self.mantissa.abs();
+		let one = BigInt::one() << self.bits as usize;
+		let integer = &magnitude >> self.bits as usize;
+		let fraction = &magnitude - (&integer << self.bits as usize);
+
+		let digits = (f64::from(self.bits.saturating_sub(GUARD_BITS)) * std::f64::consts::LOG10_2).floor() as usize;
+		let scaled = (fraction * num_traits::pow(BigInt::from(10), digits) + (&one >> 1)) >> self.bits as usize;
+		let mut fraction_digits = scaled.to_str_radix(10);
+		// Rounding can carry all the way into the integer part.
+		let integer = if fraction_digits.len() > digits {
+			fraction_digits.remove(0);
+			integer + BigInt::one()
+		} else {
+			integer
+		};
+		let fraction_digits = format!("{:0>width$}", fraction_digits, width = digits);
+		let fraction_digits = fraction_digits.trim_end_matches('0');
+
+		if self.mantissa.sign() == Sign::Minus {
+			write!(f, "-")?;
+		}
+		if fraction_digits.is_empty() {
+			write!(f, "{}", integer)
+		} else {
+			write!(f, "{}.{}", integer, fraction_digits)
+		}
+	}
+}
+
+impl Serialize for BigFloat {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_str(&self.to_string())
+	}
+}
+
+/// Accepts plain numbers too, like the ones location files used to have.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BigFloatRepr {
+	Text(String),
+	Number(f64),
+}
+
+impl<'de> Deserialize<'de> for BigFloat {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		match BigFloatRepr::deserialize(deserializer)? {
+			BigFloatRepr::Text(text) => text.parse().map_err(serde::de::Error::custom),
+			BigFloatRepr::Number(number) => Ok(BigFloat::from_f64(number, MIN_BITS)),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn parse(text: &str) -> BigFloat {
+		text.parse().unwrap()
+	}
+
+	#[test]
+	fn texts_round_trip() {
+		for &text in &["0", "1", "-2.5", "-0.74364388703715870475", "0.1", "12500", "0.000000000000000000000000000001"] {
+			let number = parse(text);
+			assert_eq!(number.to_string(), text);
+			assert_eq!(parse(&number.to_string()), number);
+		}
+		assert_eq!(parse("12.5E+3").to_string(), "12500");
+		assert_eq!(parse("1e-30").to_string(), "0.000000000000000000000000000001");
+		assert_eq!(parse("-0.50"), parse("-.5"));
+	}
+
+	#[test]
+	fn saving_and_loading_keeps_the_bits() {
+		// A center moved around at a deep zoom, like a location file saves it.
+		let mut number = BigFloat::from_f64(-0.743643887037158, MIN_BITS).with_bits(180);
+		number = number.add_f64(1.234e-40);
+		let text = number.to_string();
+		let loaded = parse(&text);
+		assert!((&loaded - &number.with_bits(loaded.bits())).to_f64().abs() < 2f64.powi(10 - 180));
+
+		let mut again = loaded.clone();
+		for _ in 0..5 {
+			again = parse(&again.to_string());
+		}
+		assert_eq!(again, loaded);
+		assert_eq!(again.to_string(), text);
+	}
+
+	#[test]
+	fn f64_round_trips() {
+		for &value in &[0.0, 1.0, -2.5, 0.1, -0.743643887037158, 123456.789] {
+			assert_eq!(BigFloat::from_f64(value, MIN_BITS).to_f64(), value);
+		}
+		// Small numbers need the bits below their leading zeros as well.
+		for &value in &[1e-10, -3e-40] {
+			assert_eq!(BigFloat::from_f64(value, 200).with_bits(300).to_f64(), value);
+		}
+	}
+
+	#[test]
+	fn arithmetic() {
+		let a = BigFloat::from_f64(1.5, MIN_BITS);
+		let b = BigFloat::from_f64(-0.25, 100);
+		assert_eq!((&a + &b).to_f64(), 1.25);
+		assert_eq!((&a - &b).to_f64(), 1.75);
+		assert_eq!((&a * &b).to_f64(), -0.375);
+		assert_eq!(b.square().to_f64(), 0.0625);
+		assert_eq!((-&a).to_f64(), -1.5);
+	}
+
+	#[test]
+	fn malformed_texts() {
+		for &text in &["", "-", "abc", "1.2.3", "1e", "1e+", "0x10", "1 2", "1e999999999", "1e-999999999",
+			"1e-9223372036854775808", "1e9223372036854775807"] {
+			assert!(text.parse::<BigFloat>().is_err(), "{:?} parsed", text);
+		}
+	}
+}
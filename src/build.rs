@@ -47,6 +47,7 @@ fn pre_compile_shaders() {
 			let shader_type = match ext {
 				"frag" => glsl_to_spirv::ShaderType::Fragment,
 				"vert" => glsl_to_spirv::ShaderType::Vertex,
+				"comp" => glsl_to_spirv::ShaderType::Compute,
 				_ => continue
 			};
 
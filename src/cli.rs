@@ -0,0 +1,437 @@
+//! Command line options.
+//!
+//! Everything is optional, starting without arguments opens the
+//! Mandelbrot view just like before.
+
+use std::path::PathBuf;
+use std::str::FromStr;
+use structopt::StructOpt;
+use serde::{Serialize, Deserialize};
+
+use crate::utils::{FractalParams, Position, Center, Zoom, Iterations, Julia, Formula, Family, CurrentView};
+use crate::location::Location;
+use crate::polynomial::{self, Polynomial};
+use crate::lyapunov::{self, Lyapunov};
+use crate::palette::{Palette, Color};
+use crate::coloring::{Coloring, ColoringMode, InteriorMode};
+use crate::trap::{Trap, TrapShape};
+use crate::average::{Average, AverageKind};
+use crate::lighting::Lighting;
+use crate::custom_formula::CustomFormula;
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "mandelbrot", about = "Mandelbrot and Julia set explorer.")]
+pub struct Options {
+	/// Location file saved with Ctrl+S to start from. Any other options override its values.
+	#[structopt(long, parse(try_from_str = parse_location))]
+	pub location: Option<Location>,
+
+	/// View to start in: `single`, `julia`, `double`, `newton`, `buddhabrot`, `nebulabrot` or `lyapunov`.
+	#[structopt(long)]
+	pub view: Option<View>,
+
+	/// Point of the complex plane in the middle of the window, as `re,im`.
+	/// Takes as many digits as needed for deep zooms.
+	#[structopt(long, parse(try_from_str = parse_center), allow_hyphen_values = true)]
+	pub center: Option<Center>,
+
+	/// Distance in the complex plane between two neighbouring pixels. Smaller is deeper.
+	#[structopt(long)]
+	pub zoom: Option<f64>,
+
+	/// Maximum number of iterations per pixel.
+	#[structopt(long)]
+	pub iterations: Option<f32>,
+
+	/// Pick the iterations by how deep the zoom is. `--iterations` takes precedence.
+	#[structopt(long)]
+	pub auto_iterations: bool,
+
+	/// Formula to iterate: `mandelbrot`, `burning-ship`, `tricorn` or `multibrot`.
+	/// `--view julia` gives its Julia sets.
+	#[structopt(long)]
+	pub formula: Option<Family>,
+
+	/// Exponent of the Multibrot, doesn't have to be whole. Implies `--formula multibrot`.
+	#[structopt(long)]
+	pub power: Option<f32>,
+
+	/// Formula of your own in `z` and `c`, like `z*z*z + c*sin(z)`. Knows `+ - * / ^`, `i`, `pi`, `e`,
+	/// sin, cos, tan, sinh, cosh, tanh, exp, log, sqrt, abs, conj, re and im.
+	#[structopt(long, allow_hyphen_values = true, conflicts_with_all = &["formula", "power"])]
+	pub custom_formula: Option<CustomFormula>,
+
+	/// Constant `c` of the Julia set, as `re,im`.
+	#[structopt(long, parse(try_from_str = parse_complex), allow_hyphen_values = true)]
+	pub generator: Option<[f64; 2]>,
+
+	/// Roots of the polynomial of the Newton view, as `re,im;re,im;...`.
+	#[structopt(long, allow_hyphen_values = true)]
+	pub roots: Option<Polynomial>,
+
+	/// Coefficients of the polynomial of the Newton view from the highest power down,
+	/// as `re,im;re,im;...`. `1;0;0;-1` is `z^3 - 1`.
+	#[structopt(long, parse(try_from_str = parse_coefficients), allow_hyphen_values = true, conflicts_with = "roots")]
+	pub coefficients: Option<Polynomial>,
+
+	/// Order of the growth rates `a` and `b` of the Lyapunov view, like `AABAB`.
+	#[structopt(long, parse(try_from_str = Lyapunov::parse_sequence))]
+	pub sequence: Option<String>,
+
+	/// Iterations of the Lyapunov view's logistic map before the exponent is measured.
+	#[structopt(long)]
+	pub warmup: Option<u32>,
+
+	/// Color of the stable regions of the Lyapunov view, as `rrggbb`.
+	#[structopt(long)]
+	pub stable_color: Option<Color>,
+
+	/// Color of the chaotic regions of the Lyapunov view, as `rrggbb`.
+	#[structopt(long)]
+	pub chaotic_color: Option<Color>,
+
+	/// Gradient the escape time is colored with: `hsl`, or a `.toml` file of color stops,
+	/// a Fractint `.map` or an UltraFractal `.ugr` file, `gradients.ugr#name` picking one of its gradients.
+	#[structopt(long, parse(try_from_str = Palette::load))]
+	pub palette: Option<Palette>,
+
+	/// Moves the palette along, 1 being its whole length.
+	#[structopt(long, allow_hyphen_values = true)]
+	pub palette_offset: Option<f32>,
+
+	/// Lengths of the palette per 240 iterations, higher makes narrower bands.
+	#[structopt(long)]
+	pub palette_scale: Option<f32>,
+
+	/// How the palette is applied: `smooth`, `histogram` to spread it evenly over the pixels at any depth,
+	/// or by the distance to the set with `lines`, `glow` and `dem`.
+	#[structopt(long)]
+	pub coloring: Option<ColoringMode>,
+
+	/// How the inside of the set is colored: `black`, by the last `z` with `magnitude` and `angle`,
+	/// by the period of its cycle with `period` or by the distance to the boundary with `distance`.
+	#[structopt(long)]
+	pub interior: Option<InteriorMode>,
+
+	/// Shape the orbits are measured against with `--coloring trap`:
+	/// `point`, `line`, `cross`, `circle` or `stalks`.
+	#[structopt(long)]
+	pub trap: Option<TrapShape>,
+
+	/// Point of the complex plane the trap is placed at, as `re,im`.
+	#[structopt(long, parse(try_from_str = parse_complex), allow_hyphen_values = true)]
+	pub trap_center: Option<[f64; 2]>,
+
+	/// Distance from the trap that counts as 1, the radius of the circle trap.
+	#[structopt(long)]
+	pub trap_size: Option<f32>,
+
+	/// What is averaged along the orbits and blended into the coloring: `none`, `stripe`, `triangle` or `curvature`.
+	#[structopt(long)]
+	pub average: Option<AverageKind>,
+
+	/// Stripes per turn of the stripe average.
+	#[structopt(long)]
+	pub stripe_density: Option<f32>,
+
+	/// How much of the color comes from the average, from 0 to 1.
+	#[structopt(long)]
+	pub average_mix: Option<f32>,
+
+	/// Shade the outside of the set like a lit surface.
+	#[structopt(long)]
+	pub lighting: bool,
+
+	/// Direction the light comes from, in degrees counterclockwise from the right.
+	#[structopt(long, allow_hyphen_values = true)]
+	pub light_azimuth: Option<f32>,
+
+	/// Degrees of the light above the screen, 90 being straight out of it.
+	#[structopt(long)]
+	pub light_elevation: Option<f32>,
+
+	/// How steep the lit surface is, higher makes deeper shadows.
+	#[structopt(long)]
+	pub light_height: Option<f32>,
+
+	/// Strength of the highlights of the lit surface.
+	#[structopt(long)]
+	pub light_specular: Option<f32>,
+
+	/// Size of the window or of the rendered image, as `WIDTHxHEIGHT`.
+	#[structopt(long, parse(try_from_str = parse_size))]
+	pub size: Option<(u32, u32)>,
+
+	/// Start in fullscreen.
+	#[structopt(long)]
+	pub fullscreen: bool,
+
+	#[structopt(subcommand)]
+	pub command: Option<Command>,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum Command {
+	/// Render a single frame to a PNG file instead of opening a window.
+	Render {
+		/// Where to save the image.
+		#[structopt(parse(from_os_str), default_value = "mandelbrot.png")]
+		output: PathBuf,
+
+		/// Render on the CPU, for machines without a GPU.
+		#[structopt(long)]
+		cpu: bool,
+
+		/// Random points the Buddhabrot and Nebulabrot are accumulated from.
+		#[structopt(long, default_value = "10000000")]
+		samples: u64,
+	},
+}
+
+/// Size used by `render` when `--size` is not given.
+pub const DEFAULT_RENDER_SIZE: (u32, u32) = (1920, 1080);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum View {
+	Single,
+	Julia,
+	Double,
+	Newton,
+	Buddhabrot,
+	Nebulabrot,
+	Lyapunov,
+}
+
+impl View {
+	pub fn current_view(self) -> CurrentView {
+		match self {
+			View::Single | View::Julia => CurrentView::Single,
+			View::Double => CurrentView::Double,
+			View::Newton => CurrentView::Newton,
+			View::Buddhabrot | View::Nebulabrot => CurrentView::Buddhabrot,
+			View::Lyapunov => CurrentView::Lyapunov,
+		}
+	}
+
+	/// `FractalParams::default`, looking at the `(a, b)` plane where it is interesting for the Lyapunov view.
+	pub fn default_params(self) -> FractalParams {
+		match self {
+			View::Lyapunov => FractalParams {
+				center: Center::new(lyapunov::DEFAULT_CENTER),
+				..FractalParams::default()
+			},
+			_ => FractalParams::default()
+		}
+	}
+}
+
+impl FromStr for View {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"single" => Ok(View::Single),
+			"julia" => Ok(View::Julia),
+			"double" => Ok(View::Double),
+			"newton" => Ok(View::Newton),
+			"buddhabrot" => Ok(View::Buddhabrot),
+			"nebulabrot" => Ok(View::Nebulabrot),
+			"lyapunov" => Ok(View::Lyapunov),
+			_ => Err(format!("unknown view {:?}, expected single, julia, double, newton, buddhabrot, nebulabrot or lyapunov", s))
+		}
+	}
+}
+
+fn parse_location(s: &str) -> Result<Location, String> {
+	Location::load(&PathBuf::from(s))
+}
+
+fn parse_complex(s: &str) -> Result<[f64; 2], String> {
+	let mut parts = s.split(',');
+	match (parts.next(), parts.next(), parts.next()) {
+		(Some(re), Some(im), None) => {
+			let re = re.trim().parse::<f64>().map_err(|e| format!("real part {:?}: {}", re, e))?;
+			let im = im.trim().parse::<f64>().map_err(|e| format!("imaginary part {:?}: {}", im, e))?;
+			Ok([re, im])
+		}
+		_ => Err(format!("expected `re,im`, got {:?}", s))
+	}
+}
+
+fn parse_center(s: &str) -> Result<Center, String> {
+	let mut parts = s.split(',');
+	match (parts.next(), parts.next(), parts.next()) {
+		(Some(re), Some(im), None) => Ok(Center {
+			re: re.parse().map_err(|e| format!("real part: {}", e))?,
+			im: im.parse().map_err(|e| format!("imaginary part: {}", e))?,
+		}),
+		_ => Err(format!("expected `re,im`, got {:?}", s))
+	}
+}
+
+fn parse_coefficients(s: &str) -> Result<Polynomial, String> {
+	Polynomial::from_coefficients(&polynomial::parse_complex_list(s)?)
+}
+
+fn parse_size(s: &str) -> Result<(u32, u32), String> {
+	let mut parts = s.split('x');
+	match (parts.next(), parts.next(), parts.next()) {
+		(Some(width), Some(height), None) => {
+			let width = width.parse::<u32>().map_err(|e| format!("width {:?}: {}", width, e))?;
+			let height = height.parse::<u32>().map_err(|e| format!("height {:?}: {}", height, e))?;
+			if width == 0 || height == 0 {
+				return Err("size must not be zero".to_owned());
+			}
+			Ok((width, height))
+		}
+		_ => Err(format!("expected `WIDTHxHEIGHT`, got {:?}", s))
+	}
+}
+
+impl Options {
+	/// Starting view, `single` unless given directly or by the location file.
+	pub fn view(&self) -> View {
+		self.view
+			.or_else(|| self.location.as_ref().map(|location| location.view))
+			.unwrap_or(View::Single)
+	}
+
+	/// Starting parameters, falling back to the location file
+	/// and then to the defaults for anything not given.
+	pub fn params(&self) -> FractalParams {
+		let defaults = self.location.as_ref()
+			.map_or_else(|| self.view().default_params(), Location::params);
+		let zoom = self.zoom.map_or(defaults.zoom, |zoom| Zoom { zoom });
+		let auto_iterations = (self.auto_iterations || defaults.auto_iterations) && self.iterations.is_none();
+		FractalParams {
+			center: self.center.clone().unwrap_or(defaults.center),
+			zoom,
+			iterations: match self.iterations {
+				Some(iterations) => Iterations { iterations },
+				// Right away, the CPU renders don't go through the views.
+				None if auto_iterations => Iterations::auto(zoom),
+				None => defaults.iterations,
+			},
+			auto_iterations,
+			julia: self.view.map_or(defaults.julia, |view| Julia { is_julia: view == View::Julia }),
+			formula: Formula {
+				family: self.formula
+					.or_else(|| self.power.map(|_| Family::Multibrot))
+					.or_else(|| self.custom_formula.as_ref().map(|_| Family::Custom))
+					.unwrap_or(defaults.formula.family),
+				power: self.power.unwrap_or(defaults.formula.power),
+			},
+			custom_formula: self.custom_formula.clone().or(defaults.custom_formula),
+			generator: self.generator.map_or(defaults.generator, |pos| Position { pos }),
+			polynomial: self.roots.clone()
+				.or_else(|| self.coefficients.clone())
+				.unwrap_or(defaults.polynomial),
+			lyapunov: Lyapunov {
+				sequence: self.sequence.clone().unwrap_or(defaults.lyapunov.sequence),
+				warmup: self.warmup.unwrap_or(defaults.lyapunov.warmup),
+				stable_color: self.stable_color.unwrap_or(defaults.lyapunov.stable_color),
+				chaotic_color: self.chaotic_color.unwrap_or(defaults.lyapunov.chaotic_color),
+			},
+			palette: Palette {
+				offset: self.palette_offset.unwrap_or(defaults.palette.offset),
+				scale: self.palette_scale.unwrap_or(defaults.palette.scale),
+				..self.palette.clone().unwrap_or(defaults.palette)
+			},
+			coloring: Coloring {
+				mode: self.coloring.unwrap_or(defaults.coloring.mode),
+				interior: self.interior.unwrap_or(defaults.coloring.interior),
+			},
+			trap: Trap {
+				shape: self.trap.unwrap_or(defaults.trap.shape),
+				center: self.trap_center.unwrap_or(defaults.trap.center),
+				size: self.trap_size.unwrap_or(defaults.trap.size),
+			},
+			average: Average {
+				kind: self.average.unwrap_or(defaults.average.kind),
+				density: self.stripe_density.unwrap_or(defaults.average.density),
+				mix: self.average_mix.unwrap_or(defaults.average.mix),
+			},
+			lighting: Lighting {
+				enabled: self.lighting || defaults.lighting.enabled,
+				azimuth: self.light_azimuth.unwrap_or(defaults.lighting.azimuth),
+				elevation: self.light_elevation.unwrap_or(defaults.lighting.elevation),
+				height: self.light_height.unwrap_or(defaults.lighting.height),
+				specular: self.light_specular.unwrap_or(defaults.lighting.specular),
+			},
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_complex_numbers() {
+		assert_eq!(parse_complex("-0.75, 0.1"), Ok([-0.75, 0.1]));
+		for s in &["", "1", "1,", ",1", "1,2,3", "a,1", "1,b", "1;2"] {
+			assert!(parse_complex(s).is_err(), "{:?}", s);
+		}
+	}
+
+	#[test]
+	fn parses_centers() {
+		let center = parse_center("-1.75,-0.0625").unwrap();
+		assert_eq!(center.position().pos, [1.75, 0.0625]);
+		for s in &["", "1", "1,2,3", "x,1", "1,1e", "1,1e999999999"] {
+			assert!(parse_center(s).is_err(), "{:?}", s);
+		}
+	}
+
+	#[test]
+	fn parses_sizes() {
+		assert_eq!(parse_size("1920x1080"), Ok((1920, 1080)));
+		for s in &["", "1920", "1920x", "x1080", "0x1080", "1920x0", "-1x1", "1920X1080", "1x2x3", "1.5x2"] {
+			assert!(parse_size(s).is_err(), "{:?}", s);
+		}
+	}
+
+	#[test]
+	fn parses_views() {
+		assert_eq!("nebulabrot".parse::<View>(), Ok(View::Nebulabrot));
+		for s in &["", "Single", "mandelbrot"] {
+			assert!(s.parse::<View>().is_err(), "{:?}", s);
+		}
+	}
+
+	#[test]
+	fn rejects_bad_options() {
+		for args in &[
+			&["--zoom", "deep"][..],
+			&["--iterations", "-"],
+			&["--view", "triple"],
+			&["--size", "0x0"],
+			&["--center", "1"],
+			&["--generator", "0.3"],
+			&["--coefficients", "0;1"],
+			&["--formula", "custom"],
+			&["--custom-formula", "z*z +"],
+			&["--power", "3", "--custom-formula", "z*z + c"],
+			&["--roots", "1,0", "--coefficients", "1;-1"],
+		] {
+			let all = std::iter::once("mandelbrot").chain(args.iter().cloned());
+			assert!(Options::from_iter_safe(all).is_err(), "{:?}", args);
+		}
+	}
+
+	#[test]
+	fn takes_good_options() {
+		let options = Options::from_iter_safe(&[
+			"mandelbrot", "--view", "julia", "--center", "-0.5,0.25", "--zoom", "0.001", "--generator", "-0.8,0.156",
+			"--size", "640x480",
+		]).unwrap();
+		let params = options.params();
+		assert_eq!(options.view(), View::Julia);
+		assert!(params.julia.is_julia);
+		assert_eq!(params.center.position().pos, [0.5, -0.25]);
+		assert_eq!(params.zoom.zoom, 0.001);
+		assert_eq!(params.generator.pos, [-0.8, 0.156]);
+		assert_eq!(options.size, Some((640, 480)));
+	}
+}
@@ -0,0 +1,195 @@
+//! How the iteration counts of `mandelbrot.frag` are turned into colors.
+//!
+//! `mandelbrot.frag` only writes the smooth iteration count of every pixel into a float texture,
+//! `colorize.frag` looks the count up in the palette in a second pass, see `views::colorize`.
+//! `crate::cpu::colorize` does the same on the CPU.
+
+use std::fmt;
+use std::str::FromStr;
+use serde::{Serialize, Deserialize};
+use zerocopy::{AsBytes, FromBytes};
+
+use crate::palette::Palette;
+
+/// Buckets of the histogram of `ColoringMode::Histogram`, spread evenly between 0 and the iterations.
+/// Keep in sync with `histogram.comp`, `histogram_cdf.comp` and `colorize.frag`.
+pub const HISTOGRAM_BINS: u32 = 1024;
+
+/// Distance in pixels at which the lines of `ColoringMode::Lines` are fully faded out.
+/// Keep in sync with `colorize.frag`, as are the two below.
+pub const LINE_WIDTH: f32 = 1.5;
+
+/// Distance in pixels at which `ColoringMode::Glow` is half as bright as at the boundary.
+pub const GLOW_WIDTH: f32 = 8.0;
+
+/// `ColoringMode::Dem` blackens the pixels closer to the set than this many pixels.
+pub const DEM_THRESHOLD: f32 = 1.0;
+
+/// Keep the order in sync with the constants in `colorize.frag`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ColoringMode {
+	/// Goes through the palette every `PALETTE_PERIOD` iterations.
+	Smooth,
+	/// Goes through the palette once over all of the escaped pixels, each color covering
+	/// about as many of them as the next, however deep the zoom.
+	Histogram,
+	/// Black lines along the boundary of the set on white, from the distance estimate.
+	Lines,
+	/// `Smooth` dimmed with the distance from the set, so that the boundary glows.
+	Glow,
+	/// `Smooth` with the pixels within `DEM_THRESHOLD` of the set as black as the set,
+	/// which brings out the filaments that the iterations alone miss.
+	Dem,
+	/// By how close the orbit came to the trap, see `crate::trap`.
+	Trap,
+}
+
+impl ColoringMode {
+	pub fn next(self) -> Self {
+		match self {
+			ColoringMode::Smooth => ColoringMode::Histogram,
+			ColoringMode::Histogram => ColoringMode::Lines,
+			ColoringMode::Lines => ColoringMode::Glow,
+			ColoringMode::Glow => ColoringMode::Dem,
+			ColoringMode::Dem => ColoringMode::Trap,
+			ColoringMode::Trap => ColoringMode::Smooth,
+		}
+	}
+}
+
+impl Default for ColoringMode {
+	fn default() -> Self {
+		ColoringMode::Smooth
+	}
+}
+
+impl FromStr for ColoringMode {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"smooth" => Ok(ColoringMode::Smooth),
+			"histogram" => Ok(ColoringMode::Histogram),
+			"lines" => Ok(ColoringMode::Lines),
+			"glow" => Ok(ColoringMode::Glow),
+			"dem" => Ok(ColoringMode::Dem),
+			"trap" => Ok(ColoringMode::Trap),
+			_ => Err(format!("unknown coloring {:?}, expected smooth, histogram, lines, glow, dem or trap", s))
+		}
+	}
+}
+
+impl fmt::Display for ColoringMode {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ColoringMode::Smooth => write!(f, "Smooth coloring"),
+			ColoringMode::Histogram => write!(f, "Histogram coloring"),
+			ColoringMode::Lines => write!(f, "Boundary lines"),
+			ColoringMode::Glow => write!(f, "Boundary glow"),
+			ColoringMode::Dem => write!(f, "Distance estimation"),
+			ColoringMode::Trap => write!(f, "Orbit trap"),
+		}
+	}
+}
+
+/// How the pixels that never escape are colored, whichever the `ColoringMode`.
+/// Keep the order in sync with the constants in `colorize.frag`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InteriorMode {
+	Black,
+	/// Goes through the palette with the length of the last `z`.
+	Magnitude,
+	/// Goes through the palette once around the angle of the last `z`.
+	Angle,
+	/// A color for every period of the cycle the orbit settles into, see `visit` in `mandelbrot.frag`.
+	Period,
+	/// By the distance to the boundary from inside, which only the Mandelbrot and Multibrot sets have.
+	Distance,
+}
+
+impl InteriorMode {
+	pub fn next(self) -> Self {
+		match self {
+			InteriorMode::Black => InteriorMode::Magnitude,
+			InteriorMode::Magnitude => InteriorMode::Angle,
+			InteriorMode::Angle => InteriorMode::Period,
+			InteriorMode::Period => InteriorMode::Distance,
+			InteriorMode::Distance => InteriorMode::Black,
+		}
+	}
+}
+
+impl Default for InteriorMode {
+	fn default() -> Self {
+		InteriorMode::Black
+	}
+}
+
+impl FromStr for InteriorMode {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"black" => Ok(InteriorMode::Black),
+			"magnitude" => Ok(InteriorMode::Magnitude),
+			"angle" => Ok(InteriorMode::Angle),
+			"period" => Ok(InteriorMode::Period),
+			"distance" => Ok(InteriorMode::Distance),
+			_ => Err(format!("unknown interior {:?}, expected black, magnitude, angle, period or distance", s))
+		}
+	}
+}
+
+impl fmt::Display for InteriorMode {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			InteriorMode::Black => write!(f, "Black interior"),
+			InteriorMode::Magnitude => write!(f, "Interior by magnitude"),
+			InteriorMode::Angle => write!(f, "Interior by angle"),
+			InteriorMode::Period => write!(f, "Interior by period"),
+			InteriorMode::Distance => write!(f, "Interior distance estimation"),
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct Coloring {
+	pub mode: ColoringMode,
+	pub interior: InteriorMode,
+}
+
+/// `Coloring` and the palette's offset and scale as the shaders see them.
+#[repr(C)]
+#[derive(Clone, Copy, AsBytes, FromBytes)]
+pub struct ColoringUniform {
+	pub palette_offset: f32,
+	pub palette_scale: f32,
+	pub mode: i32,
+	pub interior: i32,
+}
+
+impl Coloring {
+	pub fn uniform(&self, palette: &Palette) -> ColoringUniform {
+		ColoringUniform {
+			palette_offset: palette.offset,
+			palette_scale: palette.scale,
+			mode: self.mode as i32,
+			interior: self.interior as i32,
+		}
+	}
+
+	/// Whether `visit` in `mandelbrot.frag` measures the orbits against the trap.
+	pub fn traps(&self) -> bool {
+		self.mode == ColoringMode::Trap
+	}
+
+	/// Whether `visit` in `mandelbrot.frag` looks for the cycles the orbits settle into.
+	pub fn detects_cycles(&self) -> bool {
+		match self.interior {
+			InteriorMode::Period | InteriorMode::Distance => true,
+			_ => false,
+		}
+	}
+}
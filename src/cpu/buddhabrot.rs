@@ -0,0 +1,186 @@
+//! Line by line port of `shaders/buddhabrot.comp` and `shaders/buddhabrot.frag`.
+//!
+//! The uniform of the compute shader and the iteration limits of every
+//! channel live here too, `crate::views::BuddhabrotViewManager` uploads them.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::thread;
+
+use zerocopy::{AsBytes, FromBytes};
+
+use crate::utils::FractalParams;
+use crate::palette::linear_to_srgb;
+use super::shader::Vec2;
+
+const SAMPLE_RADIUS: f32 = 2.0;
+const BAILOUT: f32 = 4.0;
+const GAMMA: f32 = 3.0;
+
+/// Invocations per workgroup, `local_size_x` of the compute shaders.
+pub const WORKGROUP_SIZE: u32 = 64;
+
+/// Random points every invocation of the compute shader tries.
+pub const SAMPLES_PER_INVOCATION: u32 = 16;
+
+#[repr(C)]
+#[derive(Clone, Copy, AsBytes, FromBytes)]
+pub struct BuddhabrotUniform {
+	pub limits: [f32; 4],
+	/// Different every frame, so that every frame adds different samples.
+	pub seed: f32,
+	/// Per invocation.
+	pub samples: f32,
+}
+
+/// Red, green and blue each get the orbits escaping within their limit.
+/// The Nebulabrot gives the channels a tenth of the iterations of the one before.
+pub fn limits(params: &FractalParams, nebulabrot: bool) -> [f32; 4] {
+	let iterations = params.iterations.iterations;
+	if nebulabrot {
+		[iterations, iterations / 10.0, iterations / 100.0, 0.0]
+	} else {
+		[iterations, iterations, iterations, 0.0]
+	}
+}
+
+pub fn pcg(v: u32) -> u32 {
+	let state = v.wrapping_mul(747_796_405).wrapping_add(2_891_336_453);
+	let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277_803_737);
+	(word >> 22) ^ word
+}
+
+pub fn random(state: &mut u32) -> f32 {
+	*state = pcg(*state);
+	*state as f32 / 4_294_967_296.0
+}
+
+pub fn is_inside(c: Vec2) -> bool {
+	let q = (c[0] - 0.25) * (c[0] - 0.25) + c[1] * c[1];
+	if q * (q + (c[0] - 0.25)) <= 0.25 * c[1] * c[1] {
+		return true;
+	}
+	(c[0] + 1.0) * (c[0] + 1.0) + c[1] * c[1] <= 0.0625
+}
+
+fn square(z: Vec2) -> Vec2 {
+	[z[0] * z[0] - z[1] * z[1], 2.0 * z[0] * z[1]]
+}
+
+/// The histogram, `counts` of every pixel's red, green and blue, row by row.
+pub struct Histogram {
+	pub max_count: [AtomicU32; 3],
+	pub counts: Vec<AtomicU32>,
+	pub size: Vec2,
+	pub zoom: f32,
+	pub pos: Vec2,
+	pub limits: [f32; 4],
+}
+
+fn add_orbit(c: Vec2, escaped: usize, h: &Histogram) {
+	let window = [h.size[0] as i32, h.size[1] as i32];
+	let mut z = [0.0, 0.0];
+	for _ in 0..escaped {
+		let sq = square(z);
+		z = [sq[0] + c[0], sq[1] + c[1]];
+		let pixel = [
+			((z[0] + h.pos[0]) / h.zoom + h.size[0] / 2.0).floor() as i32,
+			((z[1] + h.pos[1]) / h.zoom + h.size[1] / 2.0).floor() as i32,
+		];
+		if pixel[0] < 0 || pixel[1] < 0 || pixel[0] >= window[0] || pixel[1] >= window[1] {
+			continue;
+		}
+		let index = (pixel[1] * window[0] + pixel[0]) as usize * 3;
+		for channel in 0..3 {
+			if escaped as f32 <= h.limits[channel] {
+				let count = h.counts[index + channel].fetch_add(1, Ordering::Relaxed) + 1;
+				h.max_count[channel].fetch_max(count, Ordering::Relaxed);
+			}
+		}
+	}
+}
+
+/// `main` of the compute shader, `invocation` being `gl_GlobalInvocationID.x`.
+pub fn sample(invocation: u32, seed: u32, samples: usize, h: &Histogram) {
+	let mut state = pcg(invocation ^ pcg(seed));
+	let limit = h.limits[0].max(h.limits[1].max(h.limits[2]));
+	for _ in 0..samples {
+		let c = [
+			SAMPLE_RADIUS * (2.0 * random(&mut state) - 1.0),
+			SAMPLE_RADIUS * (2.0 * random(&mut state) - 1.0),
+		];
+		if is_inside(c) {
+			continue;
+		}
+		let mut z = [0.0, 0.0];
+		let mut escaped = 0;
+		let mut i = 1;
+		while i as f32 <= limit {
+			let sq = square(z);
+			z = [sq[0] + c[0], sq[1] + c[1]];
+			if z[0] * z[0] + z[1] * z[1] > BAILOUT {
+				escaped = i;
+				break;
+			}
+			i += 1;
+		}
+		if escaped > 0 {
+			add_orbit(c, escaped, h);
+		}
+	}
+}
+
+/// The fragment shader, linear RGB of the pixel at `index` in the histogram.
+pub fn tone_map(index: usize, h: &Histogram) -> [f32; 3] {
+	let mut color = [0.0; 3];
+	for (channel, value) in color.iter_mut().enumerate() {
+		let brightest = (1.0 + h.max_count[channel].load(Ordering::Relaxed).max(1) as f32).ln();
+		let count = h.counts[index * 3 + channel].load(Ordering::Relaxed) as f32;
+		*value = ((1.0 + count).ln() / brightest).powf(GAMMA);
+	}
+	color
+}
+
+/// Accumulates `samples` random points on every core available and tone maps the result,
+/// returning pixels the same way as `super::render`.
+pub fn render(params: &FractalParams, width: u32, height: u32, nebulabrot: bool, samples: u64) -> Vec<u8> {
+	let threads = thread::available_parallelism()
+		.map(|n| n.get())
+		.unwrap_or(4);
+	log::info!("Accumulating {} Buddhabrot samples on the CPU with {} threads", samples, threads);
+
+	let pixel_count = width as usize * height as usize;
+	let position = params.center.position();
+	let histogram = Histogram {
+		max_count: [AtomicU32::new(0), AtomicU32::new(0), AtomicU32::new(0)],
+		counts: (0..pixel_count * 3).map(|_| AtomicU32::new(0)).collect(),
+		size: [width as f32, height as f32],
+		zoom: params.zoom.zoom as f32,
+		pos: [position.pos[0] as f32, position.pos[1] as f32],
+		limits: limits(params, nebulabrot),
+	};
+
+	// Same split as on the GPU, every invocation samples a few points.
+	let invocations = (samples / u64::from(SAMPLES_PER_INVOCATION)).max(1) as u32;
+	let histogram = &histogram;
+	thread::scope(|scope| {
+		for thread in 0..threads as u32 {
+			scope.spawn(move || {
+				let mut invocation = thread;
+				while invocation < invocations {
+					sample(invocation, 0, SAMPLES_PER_INVOCATION as usize, histogram);
+					invocation += threads as u32;
+				}
+			});
+		}
+	});
+
+	let mut pixels = vec![0u8; pixel_count * super::BYTES_PER_PIXEL];
+	for (index, pixel) in pixels.chunks_mut(super::BYTES_PER_PIXEL).enumerate() {
+		let color = tone_map(index, histogram);
+		for (channel, value) in pixel.iter_mut().zip(color.iter()) {
+			*channel = linear_to_srgb(*value);
+		}
+		pixel[3] = 255;
+	}
+	pixels
+}
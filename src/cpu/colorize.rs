@@ -0,0 +1,213 @@
+//! Line by line port of `shaders/colorize.frag`, `shaders/histogram.comp` and `shaders/histogram_cdf.comp`.
+//!
+//! Keep these in sync with the shaders, they are meant to produce the same
+//! pixels as the GPU does.
+
+use crate::utils::FractalParams;
+use crate::coloring::{ColoringMode, InteriorMode, HISTOGRAM_BINS, LINE_WIDTH, GLOW_WIDTH, DEM_THRESHOLD};
+use crate::palette::{Color, PALETTE_SIZE, PALETTE_PERIOD};
+use crate::trap::TrapShape;
+use crate::average::{Average, AverageKind};
+use crate::lighting::LightingUniform;
+use super::shader::{Vec2, Vec4, glsl_mod};
+
+/// Keep in sync with `FADE_ITERATIONS` in the shader.
+const FADE_ITERATIONS: f32 = 50.0;
+/// Keep in sync with `PERIOD_STEP` in the shader, as the one below.
+const PERIOD_STEP: f32 = 0.618034;
+const INTERIOR_DISTANCE_STEP: f32 = 0.1;
+/// Keep in sync with `AMBIENT` and `SHININESS` in the shader.
+const AMBIENT: f32 = 0.3;
+const SHININESS: f32 = 20.0;
+
+/// The uniforms of `colorize.frag`, laid out the way the shader receives them.
+pub struct Uniforms {
+	pub num_iters: f32,
+	pub palette_offset: f32,
+	pub palette_scale: f32,
+	pub mode: ColoringMode,
+	pub interior: InteriorMode,
+	/// The palette texture, decoded into linear colors the way sampling an sRGB texture does.
+	pub palette: Vec<Vec4>,
+	/// Left by `histogram`, only read with `ColoringMode::Histogram`.
+	pub cdf: Vec<f32>,
+	pub trap_shape: TrapShape,
+	pub average: Average,
+	pub lighting: LightingUniform,
+}
+
+impl Uniforms {
+	pub fn new(params: &FractalParams) -> Self {
+		Self {
+			num_iters: params.iterations.iterations,
+			palette_offset: params.palette.offset,
+			palette_scale: params.palette.scale,
+			mode: params.coloring.mode,
+			interior: params.coloring.interior,
+			palette: params.palette.texels()
+				.chunks(4)
+				.map(|texel| Color([texel[0], texel[1], texel[2]]).linear())
+				.collect(),
+			cdf: Vec::new(),
+			trap_shape: params.trap.shape,
+			average: params.average,
+			lighting: params.lighting.uniform(),
+		}
+	}
+}
+
+/// `histogram.comp` and `histogram_cdf.comp`, from the iterations of every pixel to `Uniforms::cdf`.
+pub fn histogram(iterations: &[Vec4], num_iters: f32) -> Vec<f32> {
+	let bins = HISTOGRAM_BINS as usize;
+	let mut counts = vec![0u32; bins];
+	for &[it, ..] in iterations {
+		// Inside of the set.
+		if it == 0.0 {
+			continue;
+		}
+		let bin = ((it / num_iters * bins as f32) as i32).max(0).min(bins as i32 - 1);
+		counts[bin as usize] += 1;
+	}
+
+	let total: u32 = counts.iter().sum();
+	let mut sum = 0;
+	counts.iter()
+		.map(|count| {
+			sum += count;
+			sum as f32 / total.max(1) as f32
+		})
+		.collect()
+}
+
+/// Fraction of the escaped pixels that took fewer iterations than `it`,
+/// interpolated within the bin so that the bands stay smooth.
+pub fn equalized(it: f32, u: &Uniforms) -> f32 {
+	let bins = HISTOGRAM_BINS as usize;
+	let x = (it / u.num_iters).max(0.0).min(1.0) * bins as f32;
+	let bin = (x as usize).min(bins - 1);
+	let before = if bin > 0 { u.cdf[bin - 1] } else { 0.0 };
+	before + (u.cdf[bin] - before) * (x - bin as f32)
+}
+
+/// `textureLod` of the palette, with linear filtering and the texture repeating.
+pub fn sample_palette(position: f32, u: &Uniforms) -> Vec4 {
+	let size = PALETTE_SIZE as i32;
+	// Texel centers are at `(i + 0.5) / size`.
+	let x = position * size as f32 - 0.5;
+	let i = x.floor();
+	let t = x - i;
+	let a = u.palette[glsl_mod(i, size as f32) as usize % u.palette.len()];
+	let b = u.palette[glsl_mod(i + 1.0, size as f32) as usize % u.palette.len()];
+	[
+		a[0] + (b[0] - a[0]) * t,
+		a[1] + (b[1] - a[1]) * t,
+		a[2] + (b[2] - a[2]) * t,
+		1.0,
+	]
+}
+
+/// `sample_palette` at `position` moved by the offset and scale.
+fn palette_at(position: f32, u: &Uniforms) -> Vec4 {
+	sample_palette(position * u.palette_scale + u.palette_offset, u)
+}
+
+/// `escape` being what `super::shader::interior` left.
+pub fn interior_color(escape: Vec4, orbit: Vec4, u: &Uniforms) -> Vec4 {
+	let [_, x, y, distance] = escape;
+	let period = orbit[2];
+	let rgb = match u.interior {
+		InteriorMode::Magnitude => palette_at((x * x + y * y).sqrt(), u),
+		InteriorMode::Angle => palette_at(y.atan2(x) / (2.0 * std::f32::consts::PI), u),
+		InteriorMode::Period if period > 0.0 => palette_at(period * PERIOD_STEP, u),
+		InteriorMode::Distance if distance > 0.0 => {
+			let rgb = palette_at((1.0 + distance).log2() * INTERIOR_DISTANCE_STEP, u);
+			let fade = distance / (1.0 + distance);
+			[rgb[0] * fade, rgb[1] * fade, rgb[2] * fade, 1.0]
+		}
+		_ => [0.0, 0.0, 0.0, 1.0],
+	};
+	[rgb[0], rgb[1], rgb[2], 1.0]
+}
+
+/// `escape` being a texel of the iteration texture, see `super::shader::escaped`,
+/// `orbit` one of the orbit texture, see `super::shader::visit`.
+pub fn color(escape: Vec4, orbit: Vec4, u: &Uniforms) -> Vec4 {
+	let [it, _, _, distance] = escape;
+	let trap = orbit[0];
+	if u.mode == ColoringMode::Trap && u.trap_shape != TrapShape::Stalks {
+		let rgb = palette_at(trap, u);
+		let fade = 1.0 / (1.0 + trap);
+		return [rgb[0] * fade, rgb[1] * fade, rgb[2] * fade, 1.0];
+	}
+	let coloring = match u.mode {
+		ColoringMode::Trap => ColoringMode::Smooth,
+		ColoringMode::Lines | ColoringMode::Glow | ColoringMode::Dem if distance < 0.0 => ColoringMode::Smooth,
+		mode => mode,
+	};
+	if coloring == ColoringMode::Lines {
+		if it == 0.0 {
+			return [1.0; 4];
+		}
+		let shade = (distance / LINE_WIDTH).max(0.0).min(1.0);
+		return [shade, shade, shade, 1.0];
+	}
+	if it == 0.0 {
+		return interior_color(escape, orbit, u);
+	}
+	if coloring == ColoringMode::Dem && distance < DEM_THRESHOLD {
+		return [0.0, 0.0, 0.0, 1.0];
+	}
+	let mut fade = ((u.num_iters - it) / FADE_ITERATIONS).min(1.0);
+	if coloring == ColoringMode::Glow {
+		fade *= GLOW_WIDTH / (GLOW_WIDTH + distance);
+	}
+	let position = match coloring {
+		ColoringMode::Histogram => equalized(it, u),
+		_ => it / PALETTE_PERIOD,
+	};
+	let mut rgb = palette_at(position, u);
+	if u.average.kind != AverageKind::None {
+		let average = palette_at(orbit[3], u);
+		for (channel, average) in rgb[..3].iter_mut().zip(&average) {
+			*channel += (average - *channel) * u.average.mix;
+		}
+	}
+	let mut rgb = [rgb[0] * fade, rgb[1] * fade, rgb[2] * fade, 1.0];
+	if u.mode == ColoringMode::Trap && trap < 1.0 {
+		for channel in &mut rgb[..3] {
+			*channel += (1.0 - *channel) * (1.0 - trap);
+		}
+	}
+	rgb
+}
+
+fn normalize(v: [f32; 3]) -> [f32; 3] {
+	let len = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+	[v[0] / len, v[1] / len, v[2] / len]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+	a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+/// Shades `rgb` as a surface with the set standing out of the plane, the outside sloping down away from it.
+pub fn light(rgb: Vec4, slope: Vec2, u: &Uniforms) -> Vec4 {
+	let l = &u.lighting;
+	let normal = normalize([slope[0] * l.height, slope[1] * l.height, 1.0]);
+	let diffuse = dot(normal, l.direction).max(0.0);
+	// Blinn-Phong, seen from straight above.
+	let halfway = normalize([l.direction[0], l.direction[1], l.direction[2] + 1.0]);
+	let specular = l.specular * dot(normal, halfway).max(0.0).powf(SHININESS);
+	let shade = AMBIENT + (1.0 - AMBIENT) * diffuse;
+	[rgb[0] * shade + specular, rgb[1] * shade + specular, rgb[2] * shade + specular, rgb[3]]
+}
+
+/// The shader's `main`, from the texels of the iteration, the orbit and the normal texture to the color.
+pub fn main(escape: Vec4, orbit: Vec4, normal: Vec2, u: &Uniforms) -> Vec4 {
+	let rgb = color(escape, orbit, u);
+	// Only the escaped pixels with a distance estimate have a slope.
+	if u.lighting.enabled != 0 && escape[0] != 0.0 && escape[3] >= 0.0 {
+		return light(rgb, normal, u);
+	}
+	rgb
+}
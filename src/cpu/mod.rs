@@ -0,0 +1,141 @@
+//! CPU implementation of the fragment shader.
+//!
+//! Slow compared to the GPU, but works on machines without one and
+//! serves as the reference the GPU output can be compared against.
+
+pub mod shader;
+pub mod colorize;
+pub mod buddhabrot;
+
+use std::thread;
+
+use crate::utils::FractalParams;
+use crate::perturbation;
+use crate::coloring::ColoringMode;
+use crate::palette::linear_to_srgb;
+use self::shader::{Uniforms, Vec2, Vec4};
+
+const BYTES_PER_PIXEL: usize = 4;
+
+fn render_row(row: &mut [(Vec4, Vec4, Vec2)], y: usize, uniforms: &Uniforms) {
+	for (x, texels) in row.iter_mut().enumerate() {
+		// Same as gl_FragCoord, which points at the center of the pixel.
+		let frag_coord = [x as f32 + 0.5, y as f32 + 0.5];
+		*texels = shader::main(frag_coord, uniforms);
+	}
+}
+
+/// Renders `params` into a `width` x `height` image using every core available.
+///
+/// Scanlines are dealt out to the threads in turns, so that rows inside
+/// the set (which take the longest) get spread between all of them.
+/// Returns tightly packed RGBA pixels, top row first, same as `crate::headless::render`.
+pub fn render(params: &FractalParams, width: u32, height: u32) -> Vec<u8> {
+	let threads = thread::available_parallelism()
+		.map(|n| n.get())
+		.unwrap_or(4);
+	log::info!("Rendering {}x{} on the CPU with {} threads", width, height, threads);
+
+	// The iteration, orbit and normal textures `colorize.frag` reads from.
+	let mut iterations = vec![([0f32; 4], [0f32; 4], [0f32; 2]); width as usize * height as usize];
+	let mut rows_per_thread: Vec<Vec<(usize, &mut [(Vec4, Vec4, Vec2)])>> = (0..threads).map(|_| Vec::new()).collect();
+	for (y, row) in iterations.chunks_mut(width as usize).enumerate() {
+		rows_per_thread[y % threads].push((y, row));
+	}
+
+	let size = [width as f32, height as f32];
+	let mut uniforms = Uniforms::new(params, size);
+	uniforms.perturbation = perturbation::compute(params, size, [0.0, 0.0, size[0], size[1]], true);
+	let uniforms = &uniforms;
+	thread::scope(|scope| {
+		for rows in rows_per_thread {
+			scope.spawn(move || {
+				for (y, row) in rows {
+					render_row(row, y, uniforms);
+				}
+			});
+		}
+	});
+
+	let mut colorize_uniforms = colorize::Uniforms::new(params);
+	if colorize_uniforms.mode == ColoringMode::Histogram {
+		let escapes: Vec<Vec4> = iterations.iter().map(|&(escape, _, _)| escape).collect();
+		colorize_uniforms.cdf = colorize::histogram(&escapes, colorize_uniforms.num_iters);
+	}
+	iterations.iter()
+		.flat_map(|&(escape, orbit, normal)| colorize::main(escape, orbit, normal, &colorize_uniforms).to_vec())
+		.map(linear_to_srgb)
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::utils::Center;
+
+	/// The whole set in a `WIDTH` x `HEIGHT` window.
+	const WIDTH: u32 = 64;
+	const HEIGHT: u32 = 48;
+
+	fn whole_set() -> FractalParams {
+		let mut params = FractalParams::default();
+		params.center = Center::new([-0.5, 0.0]);
+		params.zoom.zoom = 0.05;
+		params
+	}
+
+	fn pixel(pixels: &[u8], x: u32, y: u32) -> [u8; 4] {
+		let i = (y * WIDTH + x) as usize * BYTES_PER_PIXEL;
+		[pixels[i], pixels[i + 1], pixels[i + 2], pixels[i + 3]]
+	}
+
+	#[test]
+	fn transform_pixels() {
+		let uniforms = Uniforms::new(&whole_set(), [WIDTH as f32, HEIGHT as f32]);
+		assert_eq!(shader::transform([32.0, 24.0], &uniforms), [-0.5, 0.0]);
+		let corner = shader::transform([0.0, 0.0], &uniforms);
+		assert!((corner[0] + 2.1).abs() < 1e-6 && (corner[1] + 1.2).abs() < 1e-6, "{:?}", corner);
+		let corner = shader::transform([64.0, 48.0], &uniforms);
+		assert!((corner[0] - 1.1).abs() < 1e-6 && (corner[1] - 1.2).abs() < 1e-6, "{:?}", corner);
+	}
+
+	#[test]
+	fn smooth_escape_count() {
+		let uniforms = Uniforms::new(&whole_set(), [WIDTH as f32, HEIGHT as f32]);
+		// 1, 2, 5, 26, 677
+		let escape = shader::iterations_mandelbrot([1.0, 0.0], &uniforms, &mut shader::begin_orbit());
+		assert!((escape[0] - (3.0 - 677f32.ln() / shader::R.ln())).abs() < 1e-5, "{:?}", escape);
+		assert_eq!([escape[1], escape[2]], [677.0, 0.0]);
+
+		// Points inside never escape, `colorize.frag` tells them by the count of 0.
+		let escape = shader::iterations_mandelbrot([-0.5, 0.0], &uniforms, &mut shader::begin_orbit());
+		assert_eq!(escape[0], 0.0);
+	}
+
+	#[test]
+	fn palette_colors() {
+		let pixels = render(&whole_set(), WIDTH, HEIGHT);
+		assert_eq!(pixels.len(), (WIDTH * HEIGHT) as usize * BYTES_PER_PIXEL);
+		assert_eq!(pixel(&pixels, 32, 24), [0, 0, 0, 255]);
+		assert_eq!(pixel(&pixels, 0, 0), [255, 43, 0, 255]);
+		assert_eq!(pixel(&pixels, 10, 24), [255, 126, 0, 255]);
+		assert_eq!(pixel(&pixels, 20, 10), [255, 80, 0, 255]);
+		assert_eq!(pixel(&pixels, 44, 8), [255, 104, 0, 255]);
+		assert_eq!(pixel(&pixels, 50, 30), [255, 165, 0, 255]);
+		assert_eq!(pixel(&pixels, 63, 47), [255, 44, 0, 255]);
+	}
+
+	/// Needs a GPU, run it with `cargo test -- --ignored`.
+	#[test]
+	#[ignore]
+	fn matches_the_gpu() {
+		let gpu = crate::headless::render(&whole_set(), WIDTH, HEIGHT).unwrap();
+		let cpu = render(&whole_set(), WIDTH, HEIGHT);
+		// The boundary is where rounding differences flip pixels in or out.
+		let different = gpu.chunks(BYTES_PER_PIXEL)
+			.zip(cpu.chunks(BYTES_PER_PIXEL))
+			.filter(|(g, c)| g.iter().zip(c.iter()).any(|(&g, &c)| (i32::from(g) - i32::from(c)).abs() > 16))
+			.count();
+		assert!(different * 20 <= (WIDTH * HEIGHT) as usize, "{} pixels differ between the CPU and the GPU", different);
+	}
+}
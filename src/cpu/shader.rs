@@ -0,0 +1,704 @@
+//! Line by line port of `shaders/mandelbrot.frag`.
+//!
+//! Keep these in sync with the shader, they are meant to produce the same
+//! pixels as the GPU does.
+
+use crate::utils::{FractalParams, Family, DOUBLE_SINGLE_ZOOM};
+use crate::perturbation::Perturbation;
+use crate::custom_formula::{Expression, Operator, Function};
+use crate::trap::{TrapShape, TrapUniform};
+use crate::average::{AverageKind, AverageUniform};
+use crate::coloring::{ColoringMode, ColoringUniform, InteriorMode};
+
+pub type Vec2 = [f32; 2];
+pub type Vec4 = [f32; 4];
+
+/// Escape radius.
+pub const R: f32 = 200.0;
+
+/// Pauldelbrot's criterion, a pixel is glitched when `|Z + d| < GLITCH_TOLERANCE * |Z|`.
+pub const GLITCH_TOLERANCE: f32 = 1e-3;
+
+/// The uniforms, laid out the way the shader receives them.
+pub struct Uniforms {
+	pub size: Vec2,
+	pub zoom: Vec2,
+	pub pos: Vec4,
+	pub num_iters: f32,
+	pub is_julia: bool,
+	pub family: i32,
+	pub power: f32,
+	/// Compiled into the shader on the GPU, see `custom_formula.rs`.
+	pub custom: Option<Expression>,
+	pub generator: Vec4,
+	pub perturbation: Perturbation,
+	pub trap: TrapUniform,
+	pub average: AverageUniform,
+	pub coloring: ColoringUniform,
+}
+
+impl Uniforms {
+	pub fn new(params: &FractalParams, size: Vec2) -> Self {
+		let pos = params.center.position().uniform();
+		let generator = params.generator.uniform();
+		Self {
+			size,
+			zoom: params.zoom.uniform().zoom,
+			pos: [pos.hi[0], pos.hi[1], pos.lo[0], pos.lo[1]],
+			num_iters: params.iterations.iterations,
+			is_julia: params.julia.is_julia,
+			family: params.formula.family as i32,
+			power: params.formula.power,
+			custom: params.custom_formula.as_ref().map(|custom_formula| custom_formula.expression.clone()),
+			generator: [generator.hi[0], generator.hi[1], generator.lo[0], generator.lo[1]],
+			perturbation: Perturbation::disabled(),
+			trap: params.trap.uniform(),
+			average: params.average.uniform(),
+			coloring: params.coloring.uniform(&params.palette),
+		}
+	}
+}
+
+pub fn length(z: Vec2) -> f32 {
+	(z[0] * z[0] + z[1] * z[1]).sqrt()
+}
+
+/// GLSL `ldexp`, `x * 2^e` without `2^e` itself overflowing.
+fn ldexp(x: Vec2, e: i32) -> Vec2 {
+	let mut x = x;
+	let mut e = e;
+	while e != 0 && x != [0.0, 0.0] {
+		let step = e.max(-126).min(127);
+		let factor = 2f32.powi(step);
+		x = [x[0] * factor, x[1] * factor];
+		e -= step;
+	}
+	x
+}
+
+/// GLSL `mod`, which unlike `%` follows the sign of `y`.
+pub fn glsl_mod(x: f32, y: f32) -> f32 {
+	x - y * (x / y).floor()
+}
+
+pub fn transform(x: Vec2, u: &Uniforms) -> Vec2 {
+	[
+		u.zoom[0] * (x[0] - u.size[0] / 2.0) - u.pos[0],
+		u.zoom[0] * (x[1] - u.size[1] / 2.0) - u.pos[1],
+	]
+}
+
+pub fn square(z: Vec2) -> Vec2 {
+	[z[0] * z[0] - z[1] * z[1], 2.0 * z[0] * z[1]]
+}
+
+pub fn cmul(a: Vec2, b: Vec2) -> Vec2 {
+	[a[0] * b[0] - a[1] * b[1], a[0] * b[1] + a[1] * b[0]]
+}
+
+const MAX_MULTIPLIED_POWER: f32 = 16.0;
+
+fn is_multiplied(p: f32) -> bool {
+	p == p.floor() && p >= 1.0 && p <= MAX_MULTIPLIED_POWER
+}
+
+pub fn cpow(z: Vec2, p: f32) -> Vec2 {
+	if is_multiplied(p) {
+		let mut result = z;
+		for _ in 1..p as i32 {
+			result = cmul(result, z);
+		}
+		return result;
+	}
+	let len = length(z);
+	if len == 0.0 {
+		return [0.0, 0.0];
+	}
+	let angle = p * z[1].atan2(z[0]);
+	let len = len.powf(p);
+	[len * angle.cos(), len * angle.sin()]
+}
+
+pub fn formula(z: Vec2, u: &Uniforms) -> Vec2 {
+	if u.family == Family::BurningShip as i32 {
+		return square([z[0].abs(), z[1].abs()]);
+	}
+	if u.family == Family::Tricorn as i32 {
+		return square([z[0], -z[1]]);
+	}
+	if u.family == Family::Multibrot as i32 {
+		return cpow(z, u.power);
+	}
+	square(z)
+}
+
+pub fn cdiv(a: Vec2, b: Vec2) -> Vec2 {
+	let d = b[0] * b[0] + b[1] * b[1];
+	[(a[0] * b[0] + a[1] * b[1]) / d, (a[1] * b[0] - a[0] * b[1]) / d]
+}
+
+pub fn cexp(z: Vec2) -> Vec2 {
+	let e = z[0].exp();
+	[e * z[1].cos(), e * z[1].sin()]
+}
+
+pub fn clog(z: Vec2) -> Vec2 {
+	[length(z).ln(), z[1].atan2(z[0])]
+}
+
+pub fn cpow_complex(z: Vec2, p: Vec2) -> Vec2 {
+	if z == [0.0, 0.0] {
+		return [0.0, 0.0];
+	}
+	cexp(cmul(p, clog(z)))
+}
+
+pub fn csin(z: Vec2) -> Vec2 {
+	[z[0].sin() * z[1].cosh(), z[0].cos() * z[1].sinh()]
+}
+
+pub fn ccos(z: Vec2) -> Vec2 {
+	[z[0].cos() * z[1].cosh(), -z[0].sin() * z[1].sinh()]
+}
+
+pub fn csinh(z: Vec2) -> Vec2 {
+	[z[0].sinh() * z[1].cos(), z[0].cosh() * z[1].sin()]
+}
+
+pub fn ccosh(z: Vec2) -> Vec2 {
+	[z[0].cosh() * z[1].cos(), z[0].sinh() * z[1].sin()]
+}
+
+/// `custom()` with `expression` in place of the marked line.
+pub fn custom(expression: &Expression, z: Vec2, c: Vec2) -> Vec2 {
+	match expression {
+		Expression::Z => z,
+		Expression::C => c,
+		Expression::Constant(value) => [value[0] as f32, value[1] as f32],
+		Expression::Negate(a) => {
+			let a = custom(a, z, c);
+			[-a[0], -a[1]]
+		}
+		Expression::Binary(operator, a, b) => {
+			let left = custom(a, z, c);
+			match (operator, &**b) {
+				(Operator::Power, Expression::Constant([p, im])) if *im == 0.0 => cpow(left, *p as f32),
+				_ => {
+					let right = custom(b, z, c);
+					match operator {
+						Operator::Add => [left[0] + right[0], left[1] + right[1]],
+						Operator::Subtract => [left[0] - right[0], left[1] - right[1]],
+						Operator::Multiply => cmul(left, right),
+						Operator::Divide => cdiv(left, right),
+						Operator::Power => cpow_complex(left, right),
+					}
+				}
+			}
+		}
+		Expression::Call(function, a) => {
+			let a = custom(a, z, c);
+			match function {
+				Function::Sin => csin(a),
+				Function::Cos => ccos(a),
+				Function::Tan => cdiv(csin(a), ccos(a)),
+				Function::Sinh => csinh(a),
+				Function::Cosh => ccosh(a),
+				Function::Tanh => cdiv(csinh(a), ccosh(a)),
+				Function::Exp => cexp(a),
+				Function::Log => clog(a),
+				Function::Sqrt => cpow(a, 0.5),
+				Function::Abs => [a[0].abs(), a[1].abs()],
+				Function::Conj => [a[0], -a[1]],
+				Function::Re => [a[0], 0.0],
+				Function::Im => [a[1], 0.0],
+			}
+		}
+	}
+}
+
+pub fn iterate(z: Vec2, c: Vec2, u: &Uniforms) -> Vec2 {
+	if u.family == Family::Custom as i32 {
+		if let Some(expression) = &u.custom {
+			return custom(expression, z, c);
+		}
+	}
+	let f = formula(z, u);
+	[f[0] + c[0], f[1] + c[1]]
+}
+
+/// Derivative of `formula` at `z` applied to `dz`.
+pub fn dformula(z: Vec2, dz: Vec2, u: &Uniforms) -> Vec2 {
+	if u.family == Family::BurningShip as i32 {
+		let sign = if z[0] * z[1] > 0.0 { 1.0 } else if z[0] * z[1] < 0.0 { -1.0 } else { 0.0 };
+		return [2.0 * (z[0] * dz[0] - z[1] * dz[1]), 2.0 * sign * (z[1] * dz[0] + z[0] * dz[1])];
+	}
+	if u.family == Family::Tricorn as i32 {
+		let product = cmul(z, dz);
+		return [2.0 * product[0], -2.0 * product[1]];
+	}
+	if u.family == Family::Multibrot as i32 {
+		let product = cmul(cpow(z, u.power - 1.0), dz);
+		return [u.power * product[0], u.power * product[1]];
+	}
+	let product = cmul(z, dz);
+	[2.0 * product[0], 2.0 * product[1]]
+}
+
+/// The smooth iteration count, the final `z` and the distance in pixels, see `escaped` in the shader.
+/// Leaves the average and the normal in the orbit.
+pub fn escaped(i: usize, z: Vec2, dz: Vec2, orbit: &mut Orbit, u: &Uniforms) -> Vec4 {
+	let len = length(z);
+	let distance = if u.family == Family::Custom as i32 { -1.0 } else { len * len.ln() / length(dz) };
+	let n = cdiv(z, dz);
+	// The derivative overflowed, right at the boundary.
+	if u.family != Family::Custom as i32 && n.iter().all(|x| x.is_finite()) {
+		let len = length(n);
+		orbit.normal = [n[0] / len, n[1] / len];
+	}
+	if orbit.average_terms >= 2 {
+		let last = orbit.average_sum / orbit.average_terms as f32;
+		let before = orbit.average_last_sum / (orbit.average_terms - 1) as f32;
+		let t = (len.ln() / R.ln() - 1.0).max(0.0).min(1.0);
+		orbit.orbit[3] = last + (before - last) * t;
+	}
+	[i as f32 - len.ln() / R.ln(), z[0], z[1], distance]
+}
+
+/// Distance of `z` from the trap, in sizes of the trap.
+pub fn trap_distance(z: Vec2, u: &Uniforms) -> f32 {
+	let t = &u.trap;
+	let d = [z[0] - t.center[0], z[1] - t.center[1]];
+	let distance = if t.shape == TrapShape::Line as i32 {
+		d[1].abs()
+	} else if t.shape == TrapShape::Cross as i32 || t.shape == TrapShape::Stalks as i32 {
+		d[0].abs().min(d[1].abs())
+	} else if t.shape == TrapShape::Circle as i32 {
+		(length(d) - t.size).abs()
+	} else {
+		length(d)
+	};
+	distance / t.size
+}
+
+/// Orbits that come back closer than this have found their cycle.
+pub const PERIOD_EPSILON: f32 = 1e-5;
+
+/// Newton steps that pull the last `z` onto the cycle before `interior` measures it.
+pub const NEWTON_STEPS: usize = 4;
+
+/// The globals of the shader that `visit` keeps.
+pub struct Orbit {
+	/// What ends up in the orbit texture.
+	pub orbit: Vec4,
+	pub cycle_z: Vec2,
+	pub cycle_i: usize,
+	pub cycle_returned: bool,
+	pub average_sum: f32,
+	pub average_last_sum: f32,
+	pub average_terms: usize,
+	pub last_z: Vec2,
+	pub before_last_z: Vec2,
+	pub visits: usize,
+	/// What ends up in the normal texture.
+	pub normal: Vec2,
+}
+
+pub fn begin_orbit() -> Orbit {
+	Orbit {
+		orbit: [1e20, 0.0, 0.0, 0.0],
+		cycle_z: [1e20, 1e20],
+		cycle_i: 0,
+		cycle_returned: false,
+		average_sum: 0.0,
+		average_last_sum: 0.0,
+		average_terms: 0,
+		last_z: [0.0, 0.0],
+		before_last_z: [0.0, 0.0],
+		visits: 0,
+		normal: [0.0, 0.0],
+	}
+}
+
+/// What `visit` adds to the average, `None` where there is nothing to add yet.
+pub fn average_term(orbit: &Orbit, z: Vec2, c: Vec2, u: &Uniforms) -> Option<f32> {
+	let kind = u.average.kind;
+	if kind == AverageKind::Stripe as i32 {
+		return Some(0.5 + 0.5 * (u.average.density * z[1].atan2(z[0])).sin());
+	}
+	if kind == AverageKind::Triangle as i32 {
+		let a = length([z[0] - c[0], z[1] - c[1]]);
+		let low = (a - length(c)).abs();
+		let high = a + length(c);
+		return if high > low { Some((length(z) - low) / (high - low)) } else { None };
+	}
+	if kind == AverageKind::Curvature as i32 && orbit.visits >= 2 {
+		let (last, before) = (orbit.last_z, orbit.before_last_z);
+		let turn = cdiv([z[0] - last[0], z[1] - last[1]], [last[0] - before[0], last[1] - before[1]]);
+		return Some(turn[1].atan2(turn[0]).abs() / std::f32::consts::PI);
+	}
+	None
+}
+
+/// Called with every `z` of the orbit, `c` being what `iterate` adds.
+pub fn visit(orbit: &mut Orbit, z: Vec2, c: Vec2, i: usize, u: &Uniforms) {
+	if u.coloring.mode == ColoringMode::Trap as i32 {
+		let distance = trap_distance(z, u);
+		if distance < orbit.orbit[0] {
+			orbit.orbit[0] = distance;
+			orbit.orbit[1] = i as f32;
+		}
+	}
+	if u.coloring.interior == InteriorMode::Period as i32 || u.coloring.interior == InteriorMode::Distance as i32 {
+		// The first return is the period, later ones come back after multiples of it.
+		// Every save starts over, so that returns of an orbit still spiraling in get corrected.
+		if !orbit.cycle_returned && length([z[0] - orbit.cycle_z[0], z[1] - orbit.cycle_z[1]]) < PERIOD_EPSILON {
+			orbit.orbit[2] = (i - orbit.cycle_i) as f32;
+			orbit.cycle_returned = true;
+		}
+		if i & (i + 1) == 0 {
+			orbit.cycle_z = z;
+			orbit.cycle_i = i;
+			orbit.cycle_returned = false;
+		}
+	}
+	if u.average.kind != AverageKind::None as i32 {
+		if let Some(term) = average_term(orbit, z, c, u) {
+			orbit.average_last_sum = orbit.average_sum;
+			orbit.average_sum += term;
+			orbit.average_terms += 1;
+		}
+		orbit.before_last_z = orbit.last_z;
+		orbit.last_z = z;
+	}
+	orbit.visits += 1;
+}
+
+/// The last `z` and the distance to the boundary from inside, see `interior` in the shader.
+pub fn interior(z: Vec2, c: Vec2, orbit: &Orbit, u: &Uniforms) -> Vec4 {
+	let period = orbit.orbit[2] as usize;
+	if period == 0 || u.is_julia || (u.family != Family::Mandelbrot as i32 && u.family != Family::Multibrot as i32) {
+		return [0.0, z[0], z[1], 0.0];
+	}
+	let step = |z: Vec2| {
+		let f = formula(z, u);
+		[f[0] + c[0], f[1] + c[1]]
+	};
+	let mut z0 = z;
+	for _ in 0..NEWTON_STEPS {
+		let mut zp = z0;
+		let mut dz = [1.0, 0.0];
+		for _ in 0..period {
+			dz = dformula(zp, dz, u);
+			zp = step(zp);
+		}
+		let delta = cdiv([zp[0] - z0[0], zp[1] - z0[1]], [dz[0] - 1.0, dz[1]]);
+		z0 = [z0[0] - delta[0], z0[1] - delta[1]];
+	}
+	let mut dz = [1.0, 0.0];
+	let mut dc = [0.0, 0.0];
+	let mut dzdz = [0.0, 0.0];
+	let mut dzdc = [0.0, 0.0];
+	for _ in 0..period {
+		let d1 = dformula(z0, [1.0, 0.0], u);
+		let d2 = if u.family == Family::Multibrot as i32 {
+			let p = cpow(z0, u.power - 2.0);
+			[u.power * (u.power - 1.0) * p[0], u.power * (u.power - 1.0) * p[1]]
+		} else {
+			[2.0, 0.0]
+		};
+		let (a, b) = (cmul(d2, cmul(dz, dz)), cmul(d1, dzdz));
+		dzdz = [a[0] + b[0], a[1] + b[1]];
+		let (a, b) = (cmul(d2, cmul(dz, dc)), cmul(d1, dzdc));
+		dzdc = [a[0] + b[0], a[1] + b[1]];
+		let a = cmul(d1, dc);
+		dc = [a[0] + 1.0, a[1]];
+		dz = cmul(d1, dz);
+		z0 = step(z0);
+	}
+	let multiplier = dz[0] * dz[0] + dz[1] * dz[1];
+	if multiplier >= 1.0 {
+		return [0.0, z[0], z[1], 0.0];
+	}
+	let a = cdiv(cmul(dzdz, dc), [1.0 - dz[0], -dz[1]]);
+	let distance = (1.0 - multiplier) / length([dzdc[0] + a[0], dzdc[1] + a[1]]);
+	[0.0, z[0], z[1], distance / u.zoom[0]]
+}
+
+pub fn iterations_julia(c: Vec2, u: &Uniforms, orbit: &mut Orbit) -> Vec4 {
+	let gen = [u.generator[0], u.generator[1]];
+	let mut z = c;
+	let mut dz = [u.zoom[0], 0.0];
+	let mut i = 0;
+	while (i as f32) < u.num_iters {
+		dz = dformula(z, dz, u);
+		z = iterate(z, gen, u);
+		visit(orbit, z, gen, i, u);
+		if length(z) > R {
+			return escaped(i, z, dz, orbit, u);
+		}
+		i += 1;
+	}
+	interior(z, gen, orbit, u)
+}
+
+pub fn iterations_mandelbrot(c: Vec2, u: &Uniforms, orbit: &mut Orbit) -> Vec4 {
+	let mut z = c;
+	let mut dz = [u.zoom[0], 0.0];
+	let mut i = 0;
+	while (i as f32) < u.num_iters {
+		let derivative = dformula(z, dz, u);
+		dz = [derivative[0] + u.zoom[0], derivative[1]];
+		z = iterate(z, c, u);
+		visit(orbit, z, c, i, u);
+		if length(z) > R {
+			return escaped(i, z, dz, orbit, u);
+		}
+		i += 1;
+	}
+	interior(z, c, orbit, u)
+}
+
+pub fn ds_add(a: Vec2, b: Vec2) -> Vec2 {
+	let t1 = a[0] + b[0];
+	let e = t1 - a[0];
+	let t2 = ((b[0] - e) + (a[0] - (t1 - e))) + a[1] + b[1];
+	let hi = t1 + t2;
+	let lo = t2 - (hi - t1);
+	[hi, lo]
+}
+
+pub fn ds_mul(a: Vec2, b: Vec2) -> Vec2 {
+	let split = 4097.0;
+	let cona = a[0] * split;
+	let a1 = cona - (cona - a[0]);
+	let a2 = a[0] - a1;
+	let conb = b[0] * split;
+	let b1 = conb - (conb - b[0]);
+	let b2 = b[0] - b1;
+
+	let c11 = a[0] * b[0];
+	let c21 = a2 * b2 + (a2 * b1 + (a1 * b2 + (a1 * b1 - c11)));
+	let c2 = a[0] * b[1] + a[1] * b[0];
+
+	let t1 = c11 + c2;
+	let e = t1 - c11;
+	let t2 = a[1] * b[1] + ((c2 - e) + (c11 - (t1 - e))) + c21;
+	let hi = t1 + t2;
+	let lo = t2 - (hi - t1);
+	[hi, lo]
+}
+
+fn ds_neg(a: Vec2) -> Vec2 {
+	[-a[0], -a[1]]
+}
+
+pub fn dc_add(a: Vec4, b: Vec4) -> Vec4 {
+	let re = ds_add([a[0], a[1]], [b[0], b[1]]);
+	let im = ds_add([a[2], a[3]], [b[2], b[3]]);
+	[re[0], re[1], im[0], im[1]]
+}
+
+pub fn dc_square(z: Vec4) -> Vec4 {
+	let (z_re, z_im) = ([z[0], z[1]], [z[2], z[3]]);
+	let re = ds_add(ds_mul(z_re, z_re), ds_neg(ds_mul(z_im, z_im)));
+	let im = ds_mul([2.0, 0.0], ds_mul(z_re, z_im));
+	[re[0], re[1], im[0], im[1]]
+}
+
+pub fn dc_mul(a: Vec4, b: Vec4) -> Vec4 {
+	let (a_re, a_im, b_re, b_im) = ([a[0], a[1]], [a[2], a[3]], [b[0], b[1]], [b[2], b[3]]);
+	let re = ds_add(ds_mul(a_re, b_re), ds_neg(ds_mul(a_im, b_im)));
+	let im = ds_add(ds_mul(a_re, b_im), ds_mul(a_im, b_re));
+	[re[0], re[1], im[0], im[1]]
+}
+
+fn ds_abs(a: Vec2) -> Vec2 {
+	if a[0] < 0.0 { ds_neg(a) } else { a }
+}
+
+pub fn dc_formula(z: Vec4, u: &Uniforms) -> Vec4 {
+	if u.family == Family::BurningShip as i32 {
+		let re = ds_abs([z[0], z[1]]);
+		let im = ds_abs([z[2], z[3]]);
+		return dc_square([re[0], re[1], im[0], im[1]]);
+	}
+	if u.family == Family::Tricorn as i32 {
+		return dc_square([z[0], z[1], -z[2], -z[3]]);
+	}
+	if u.family == Family::Multibrot as i32 {
+		if is_multiplied(u.power) {
+			let mut result = z;
+			for _ in 1..u.power as i32 {
+				result = dc_mul(result, z);
+			}
+			return result;
+		}
+		let result = cpow([z[0], z[2]], u.power);
+		return [result[0], 0.0, result[1], 0.0];
+	}
+	dc_square(z)
+}
+
+pub fn dc_length(z: Vec4) -> f32 {
+	length([z[0], z[2]])
+}
+
+pub fn transform_ds(x: Vec2, u: &Uniforms) -> Vec4 {
+	let offset = [x[0] - u.size[0] / 2.0, x[1] - u.size[1] / 2.0];
+	let re = ds_add(ds_mul(u.zoom, [offset[0], 0.0]), ds_neg([u.pos[0], u.pos[2]]));
+	let im = ds_add(ds_mul(u.zoom, [offset[1], 0.0]), ds_neg([u.pos[1], u.pos[3]]));
+	[re[0], re[1], im[0], im[1]]
+}
+
+pub fn iterations_julia_ds(c: Vec4, u: &Uniforms, orbit: &mut Orbit) -> Vec4 {
+	let gen = [u.generator[0], u.generator[2], u.generator[1], u.generator[3]];
+	let mut z = c;
+	let mut dz = [u.zoom[0], 0.0];
+	let mut i = 0;
+	while (i as f32) < u.num_iters {
+		dz = dformula([z[0], z[2]], dz, u);
+		z = dc_add(dc_formula(z, u), gen);
+		visit(orbit, [z[0], z[2]], [gen[0], gen[2]], i, u);
+		if dc_length(z) > R {
+			return escaped(i, [z[0], z[2]], dz, orbit, u);
+		}
+		i += 1;
+	}
+	interior([z[0], z[2]], [gen[0], gen[2]], orbit, u)
+}
+
+pub fn iterations_mandelbrot_ds(c: Vec4, u: &Uniforms, orbit: &mut Orbit) -> Vec4 {
+	let mut z = c;
+	let mut dz = [u.zoom[0], 0.0];
+	let mut i = 0;
+	while (i as f32) < u.num_iters {
+		let derivative = dformula([z[0], z[2]], dz, u);
+		dz = [derivative[0] + u.zoom[0], derivative[1]];
+		z = dc_add(dc_formula(z, u), c);
+		visit(orbit, [z[0], z[2]], [c[0], c[2]], i, u);
+		if dc_length(z) > R {
+			return escaped(i, [z[0], z[2]], dz, orbit, u);
+		}
+		i += 1;
+	}
+	interior([z[0], z[2]], [c[0], c[2]], orbit, u)
+}
+
+/// Returns whether the reference `k` is glitched for this pixel along with the iterations.
+pub fn iterations_perturbed(x: Vec2, k: usize, u: &Uniforms, orbit: &mut Orbit) -> (Vec4, bool) {
+	// The iterations skipped by the series approximation aren't visited.
+	*orbit = begin_orbit();
+	let p = &u.perturbation.uniform;
+	let reference = p.references[k];
+	let start = reference[2] as usize;
+	let len = reference[3] as usize;
+	let orbits = &u.perturbation.orbits;
+	let mut e = p.delta_exponent as i32;
+	let mut dc = [
+		p.delta_mantissa * (x[0] - u.size[0] / 2.0 - reference[0]),
+		p.delta_mantissa * (x[1] - u.size[1] / 2.0 - reference[1]),
+	];
+	let mut d = dc;
+	// Derivatives of `d` and `dc` by the pixel, scaled like them.
+	let mut ddc = [p.delta_mantissa, 0.0];
+	let mut dd = ddc;
+	let mut i = 0;
+	if k == 0 && p.series_c[2] > 0.0 {
+		let dc2 = cmul(dc, dc);
+		let a = cmul([p.series_ab[0], p.series_ab[1]], dc);
+		let b = cmul([p.series_ab[2], p.series_ab[3]], dc2);
+		let c = cmul([p.series_c[0], p.series_c[1]], cmul(dc, dc2));
+		d = [a[0] + b[0] + c[0], a[1] + b[1] + c[1]];
+		let b = cmul([p.series_ab[2], p.series_ab[3]], dc);
+		let c = cmul([p.series_c[0], p.series_c[1]], dc2);
+		dd = [
+			p.delta_mantissa * (p.series_ab[0] + 2.0 * b[0] + 3.0 * c[0]),
+			p.delta_mantissa * (p.series_ab[1] + 2.0 * b[1] + 3.0 * c[1]),
+		];
+		let shift = p.series_c[3] as i32;
+		dc = ldexp(dc, -shift);
+		ddc = ldexp(ddc, -shift);
+		e += shift;
+		i = p.series_c[2] as usize;
+	}
+	let scaled = ldexp(d, e);
+	let mut z = [orbits[start + i][0] + scaled[0], orbits[start + i][1] + scaled[1]];
+	let c = if u.is_julia {
+		[u.generator[0], u.generator[1]]
+	} else {
+		let scaled = ldexp(dc, e);
+		[orbits[start][0] + scaled[0], orbits[start][1] + scaled[1]]
+	};
+	while (i as f32) < u.num_iters {
+		if i + 1 >= len {
+			return ([0.0; 4], true);
+		}
+		let zdd = cmul(z, dd);
+		dd = [2.0 * zdd[0], 2.0 * zdd[1]];
+		let zd = cmul(orbits[start + i], d);
+		let d2 = ldexp(cmul(d, d), e);
+		d = [2.0 * zd[0] + d2[0], 2.0 * zd[1] + d2[1]];
+		if !u.is_julia {
+			d = [d[0] + dc[0], d[1] + dc[1]];
+			dd = [dd[0] + ddc[0], dd[1] + ddc[1]];
+		}
+		let m = d[0].abs().max(d[1].abs());
+		if e < 0 && m > 1.0 {
+			let s = (-e).min(m.log2().floor() as i32);
+			d = ldexp(d, -s);
+			dc = ldexp(dc, -s);
+			dd = ldexp(dd, -s);
+			ddc = ldexp(ddc, -s);
+			e += s;
+		}
+		let z_ref = orbits[start + i + 1];
+		let scaled = ldexp(d, e);
+		z = [z_ref[0] + scaled[0], z_ref[1] + scaled[1]];
+		visit(orbit, z, c, i, u);
+		let len_z = length(z);
+		if len_z > R {
+			return (escaped(i, z, ldexp(dd, e), orbit, u), false);
+		}
+		if len_z < GLITCH_TOLERANCE * length(z_ref) {
+			return ([0.0; 4], true);
+		}
+		i += 1;
+	}
+	(interior(z, c, orbit, u), false)
+}
+
+/// Tries the references in order until one isn't glitched.
+pub fn iterations_perturbation(x: Vec2, u: &Uniforms, orbit: &mut Orbit) -> (Vec4, bool) {
+	let mut result = ([0.0; 4], true);
+	for k in 0..u.perturbation.references() {
+		result = iterations_perturbed(x, k, u, orbit);
+		if !result.1 {
+			break;
+		}
+	}
+	result
+}
+
+/// The shader's `main`, `frag_coord` being `gl_FragCoord.xy`.
+/// Returns what it writes into the iteration, the orbit and the normal texture, which `super::colorize` colors.
+pub fn main(frag_coord: Vec2, u: &Uniforms) -> (Vec4, Vec4, Vec2) {
+	let mut orbit = begin_orbit();
+	let escape = if u.perturbation.enabled() {
+		iterations_perturbation(frag_coord, u, &mut orbit).0
+	} else if u.zoom[0] < DOUBLE_SINGLE_ZOOM as f32 && u.family != Family::Custom as i32 {
+		let transformed = transform_ds(frag_coord, u);
+		if u.is_julia {
+			iterations_julia_ds(transformed, u, &mut orbit)
+		} else {
+			iterations_mandelbrot_ds(transformed, u, &mut orbit)
+		}
+	} else {
+		let transformed = transform(frag_coord, u);
+		if u.is_julia {
+			iterations_julia(transformed, u, &mut orbit)
+		} else {
+			iterations_mandelbrot(transformed, u, &mut orbit)
+		}
+	};
+	(escape, orbit.orbit, orbit.normal)
+}
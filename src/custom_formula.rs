@@ -0,0 +1,449 @@
+//! Formulas typed in by the user, like `z*z*z + c*sin(z)`.
+//!
+//! The text is parsed into an `Expression` of `z`, `c` and complex constants,
+//! which is translated into GLSL and replaces the body of `custom()` in `mandelbrot.frag`,
+//! see `inject`. `crate::cpu::shader::custom` evaluates it the same way on the CPU.
+//!
+//! ```text
+//! expression = term (("+" | "-") term)*
+//! term       = unary (("*" | "/") unary)*
+//! unary      = "-" unary | power
+//! power      = atom ("^" unary)?
+//! atom       = number | "z" | "c" | "i" | "pi" | "e" | function "(" expression ")" | "(" expression ")"
+//! ```
+//!
+//! Numbers are like `2`, `.5` or `1.5e-3`. Nesting deeper than `MAX_DEPTH` is an error rather than a stack overflow.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Ends the line of `mandelbrot.frag` that `inject` replaces.
+pub const MARKER: &str = "// CUSTOM_FORMULA";
+
+/// How deep parentheses, function calls, signs and exponents may nest.
+pub const MAX_DEPTH: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+	Add,
+	Subtract,
+	Multiply,
+	Divide,
+	Power,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Function {
+	Sin,
+	Cos,
+	Tan,
+	Sinh,
+	Cosh,
+	Tanh,
+	Exp,
+	Log,
+	Sqrt,
+	/// Of the real and imaginary parts each, `abs(z)^2 + c` is the Burning Ship.
+	Abs,
+	Conj,
+	Re,
+	Im,
+}
+
+impl Function {
+	const ALL: [Function; 13] = [
+		Function::Sin, Function::Cos, Function::Tan,
+		Function::Sinh, Function::Cosh, Function::Tanh,
+		Function::Exp, Function::Log, Function::Sqrt,
+		Function::Abs, Function::Conj, Function::Re, Function::Im,
+	];
+
+	pub fn name(self) -> &'static str {
+		match self {
+			Function::Sin => "sin",
+			Function::Cos => "cos",
+			Function::Tan => "tan",
+			Function::Sinh => "sinh",
+			Function::Cosh => "cosh",
+			Function::Tanh => "tanh",
+			Function::Exp => "exp",
+			Function::Log => "log",
+			Function::Sqrt => "sqrt",
+			Function::Abs => "abs",
+			Function::Conj => "conj",
+			Function::Re => "re",
+			Function::Im => "im",
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+	Z,
+	C,
+	Constant([f64; 2]),
+	Negate(Box<Expression>),
+	Binary(Operator, Box<Expression>, Box<Expression>),
+	Call(Function, Box<Expression>),
+}
+
+impl Expression {
+	/// GLSL of type `vec2`, using the complex functions of `mandelbrot.frag`.
+	pub fn glsl(&self) -> String {
+		match self {
+			Expression::Z => "z".to_owned(),
+			Expression::C => "c".to_owned(),
+			Expression::Constant(value) => format!("vec2({:?}, {:?})", value[0], value[1]),
+			Expression::Negate(a) => format!("(-{})", a.glsl()),
+			Expression::Binary(operator, a, b) => match operator {
+				Operator::Add => format!("({} + {})", a.glsl(), b.glsl()),
+				Operator::Subtract => format!("({} - {})", a.glsl(), b.glsl()),
+				Operator::Multiply => format!("cmul({}, {})", a.glsl(), b.glsl()),
+				Operator::Divide => format!("cdiv({}, {})", a.glsl(), b.glsl()),
+				// Real exponents go through `cpow`, which multiplies for whole ones.
+				Operator::Power => match **b {
+					Expression::Constant([p, im]) if im == 0.0 => format!("cpow({}, {:?})", a.glsl(), p),
+					_ => format!("cpow_complex({}, {})", a.glsl(), b.glsl()),
+				},
+			},
+			Expression::Call(function, a) => format!("c{}({})", function.name(), a.glsl()),
+		}
+	}
+}
+
+/// Where parsing failed, `position` counting characters from 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+	pub position: usize,
+	pub message: String,
+}
+
+impl fmt::Display for ParseError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "at column {}: {}", self.position, self.message)
+	}
+}
+
+/// The text as typed and what it parsed to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CustomFormula {
+	pub source: String,
+	pub expression: Expression,
+}
+
+impl CustomFormula {
+	/// `shader` with the line ending in `MARKER` returning this formula instead,
+	/// `None` if there is no such line.
+	pub fn inject(&self, shader: &str) -> Option<String> {
+		if !shader.contains(MARKER) {
+			return None;
+		}
+		let lines: Vec<String> = shader.lines()
+			.map(|line| if line.trim_end().ends_with(MARKER) {
+				format!("    return {}; {}", self.expression.glsl(), MARKER)
+			} else {
+				line.to_owned()
+			})
+			.collect();
+		Some(lines.join("\n"))
+	}
+}
+
+impl FromStr for CustomFormula {
+	type Err = ParseError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let mut parser = Parser {
+			chars: s.chars().collect(),
+			position: 0,
+			depth: 0,
+		};
+		let expression = parser.expression()?;
+		parser.skip_whitespace();
+		if let Some(c) = parser.peek() {
+			return Err(parser.error(format!("unexpected {:?}", c)));
+		}
+		Ok(Self {
+			source: s.trim().to_owned(),
+			expression,
+		})
+	}
+}
+
+impl fmt::Display for CustomFormula {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.source)
+	}
+}
+
+struct Parser {
+	chars: Vec<char>,
+	position: usize,
+	/// Of `unary`, which every nesting goes through.
+	depth: usize,
+}
+
+impl Parser {
+	fn error(&self, message: String) -> ParseError {
+		ParseError {
+			position: self.position + 1,
+			message,
+		}
+	}
+
+	fn peek(&self) -> Option<char> {
+		self.chars.get(self.position).cloned()
+	}
+
+	fn skip_whitespace(&mut self) {
+		while self.peek().map_or(false, char::is_whitespace) {
+			self.position += 1;
+		}
+	}
+
+	/// Skips whitespace and then `c` if it is next.
+	fn eat(&mut self, c: char) -> bool {
+		self.skip_whitespace();
+		if self.peek() == Some(c) {
+			self.position += 1;
+			true
+		} else {
+			false
+		}
+	}
+
+	fn expression(&mut self) -> Result<Expression, ParseError> {
+		let mut a = self.term()?;
+		loop {
+			let operator = if self.eat('+') {
+				Operator::Add
+			} else if self.eat('-') {
+				Operator::Subtract
+			} else {
+				return Ok(a);
+			};
+			let b = self.term()?;
+			a = Expression::Binary(operator, Box::new(a), Box::new(b));
+		}
+	}
+
+	fn term(&mut self) -> Result<Expression, ParseError> {
+		let mut a = self.unary()?;
+		loop {
+			let operator = if self.eat('*') {
+				Operator::Multiply
+			} else if self.eat('/') {
+				Operator::Divide
+			} else {
+				return Ok(a);
+			};
+			let b = self.unary()?;
+			a = Expression::Binary(operator, Box::new(a), Box::new(b));
+		}
+	}
+
+	fn unary(&mut self) -> Result<Expression, ParseError> {
+		self.skip_whitespace();
+		if self.depth == MAX_DEPTH {
+			return Err(self.error(format!("nested more than {} deep", MAX_DEPTH)));
+		}
+		self.depth += 1;
+		let unary = if self.eat('-') {
+			self.unary().map(|a| Expression::Negate(Box::new(a)))
+		} else {
+			self.power()
+		};
+		self.depth -= 1;
+		unary
+	}
+
+	fn power(&mut self) -> Result<Expression, ParseError> {
+		let base = self.atom()?;
+		if self.eat('^') {
+			let exponent = self.unary()?;
+			return Ok(Expression::Binary(Operator::Power, Box::new(base), Box::new(exponent)));
+		}
+		Ok(base)
+	}
+
+	fn atom(&mut self) -> Result<Expression, ParseError> {
+		self.skip_whitespace();
+		let start = self.position;
+		match self.peek() {
+			None => Err(self.error("unexpected end of the formula".to_owned())),
+			Some('(') => {
+				self.position += 1;
+				let inner = self.expression()?;
+				if !self.eat(')') {
+					return Err(self.error("expected `)`".to_owned()));
+				}
+				Ok(inner)
+			}
+			Some(c) if c.is_ascii_digit() || c == '.' => {
+				while self.peek().map_or(false, |c| c.is_ascii_digit() || c == '.') {
+					self.position += 1;
+				}
+				// An exponent, as in `1e3`, `1e+3` or `1e-3`.
+				if self.peek() == Some('e') {
+					let sign = match self.chars.get(self.position + 1) {
+						Some('+') | Some('-') => 1,
+						_ => 0,
+					};
+					if self.chars.get(self.position + 1 + sign).map_or(false, char::is_ascii_digit) {
+						self.position += 1 + sign;
+						while self.peek().map_or(false, |c| c.is_ascii_digit()) {
+							self.position += 1;
+						}
+					}
+				}
+				let number: String = self.chars[start..self.position].iter().collect();
+				let value = number.parse::<f64>()
+					.map_err(|e| ParseError {
+						position: start + 1,
+						message: format!("number {:?}: {}", number, e),
+					})?;
+				// The shader only has `float`, which would make `inf` of it.
+				if !(value as f32).is_finite() {
+					return Err(ParseError {
+						position: start + 1,
+						message: format!("number {:?} is too large", number),
+					});
+				}
+				Ok(Expression::Constant([value, 0.0]))
+			}
+			Some(c) if c.is_ascii_alphabetic() => {
+				while self.peek().map_or(false, |c| c.is_ascii_alphanumeric()) {
+					self.position += 1;
+				}
+				let name: String = self.chars[start..self.position].iter().collect();
+				match name.as_str() {
+					"z" => Ok(Expression::Z),
+					"c" => Ok(Expression::C),
+					"i" => Ok(Expression::Constant([0.0, 1.0])),
+					"pi" => Ok(Expression::Constant([std::f64::consts::PI, 0.0])),
+					"e" => Ok(Expression::Constant([std::f64::consts::E, 0.0])),
+					_ => {
+						let function = Function::ALL.iter()
+							.cloned()
+							.find(|function| function.name() == name)
+							.ok_or_else(|| ParseError {
+								position: start + 1,
+								message: format!("unknown name {:?}, expected z, c, i, pi, e or a function", name),
+							})?;
+						if !self.eat('(') {
+							return Err(self.error(format!("expected `(` after {}", name)));
+						}
+						let argument = self.expression()?;
+						if !self.eat(')') {
+							return Err(self.error("expected `)`".to_owned()));
+						}
+						Ok(Expression::Call(function, Box::new(argument)))
+					}
+				}
+			}
+			Some(c) => Err(self.error(format!("unexpected {:?}", c))),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn parse(s: &str) -> Result<Expression, ParseError> {
+		s.parse::<CustomFormula>().map(|custom_formula| custom_formula.expression)
+	}
+
+	fn binary(operator: Operator, a: Expression, b: Expression) -> Expression {
+		Expression::Binary(operator, Box::new(a), Box::new(b))
+	}
+
+	fn error_at(s: &str) -> usize {
+		parse(s).unwrap_err().position
+	}
+
+	#[test]
+	fn precedence() {
+		use self::Expression::{Z, C, Constant};
+		assert_eq!(parse("z*z + c").unwrap(), binary(Operator::Add, binary(Operator::Multiply, Z, Z), C));
+		assert_eq!(parse("z - c / 2").unwrap(), binary(Operator::Subtract, Z, binary(Operator::Divide, C, Constant([2.0, 0.0]))));
+		assert_eq!(parse("-z^2").unwrap(), Expression::Negate(Box::new(binary(Operator::Power, Z, Constant([2.0, 0.0])))));
+		assert_eq!(
+			parse("z^2^3").unwrap(),
+			binary(Operator::Power, Z, binary(Operator::Power, Constant([2.0, 0.0]), Constant([3.0, 0.0])))
+		);
+		assert_eq!(parse(" ( z + c ) * i ").unwrap(), binary(Operator::Multiply, binary(Operator::Add, Z, C), Constant([0.0, 1.0])));
+	}
+
+	#[test]
+	fn numbers_names_and_functions() {
+		use self::Expression::{Z, Constant};
+		assert_eq!(parse("1.5e-3").unwrap(), Constant([1.5e-3, 0.0]));
+		assert_eq!(parse("1e+3").unwrap(), Constant([1e3, 0.0]));
+		assert_eq!(parse(".25").unwrap(), Constant([0.25, 0.0]));
+		assert_eq!(parse("pi").unwrap(), Constant([std::f64::consts::PI, 0.0]));
+		assert_eq!(parse("sin(z)").unwrap(), Expression::Call(Function::Sin, Box::new(Z)));
+		assert_eq!(parse("abs(conj(z))").unwrap(), Expression::Call(Function::Abs, Box::new(Expression::Call(Function::Conj, Box::new(Z)))));
+	}
+
+	#[test]
+	fn glsl() {
+		assert_eq!(parse("z*z*z + c").unwrap().glsl(), "(cmul(cmul(z, z), z) + c)");
+		assert_eq!(parse("z^2 + c").unwrap().glsl(), "(cpow(z, 2.0) + c)");
+		assert_eq!(parse("z^i").unwrap().glsl(), "cpow_complex(z, vec2(0.0, 1.0))");
+		assert_eq!(parse("exp(-z)").unwrap().glsl(), "cexp((-z))");
+	}
+
+	#[test]
+	fn error_positions() {
+		assert_eq!(error_at("z +"), 4);
+		assert_eq!(error_at("z * foo"), 5);
+		assert_eq!(error_at("sin z"), 5);
+		assert_eq!(error_at("(z + c"), 7);
+		assert_eq!(error_at("z $ c"), 3);
+		assert_eq!(error_at("1.2.3"), 1);
+		assert_eq!(error_at("z*z + c)"), 8);
+	}
+
+	#[test]
+	fn exponents_need_digits() {
+		assert_eq!(error_at("2e+z"), 2);
+		assert_eq!(error_at("1e-"), 2);
+		assert_eq!(error_at("1e"), 2);
+	}
+
+	#[test]
+	fn nesting_depth() {
+		let nested = |depth: usize| format!("{}z{}", "(".repeat(depth), ")".repeat(depth));
+		assert!(parse(&nested(MAX_DEPTH - 1)).is_ok());
+		assert_eq!(parse(&nested(MAX_DEPTH)).unwrap_err(), ParseError {
+			position: MAX_DEPTH + 1,
+			message: format!("nested more than {} deep", MAX_DEPTH),
+		});
+		// Far too deep for the stack, if it got that far.
+		assert_eq!(error_at(&"-".repeat(1_000_000)), MAX_DEPTH + 1);
+		// At the argument of the last `sin` allowed.
+		assert_eq!(error_at(&format!("z + {}", "sin(".repeat(1_000_000))), 5 + 4 * MAX_DEPTH);
+	}
+
+	#[test]
+	fn literals_too_large_for_the_shader() {
+		assert_eq!(parse("1e999 + z").unwrap_err(), ParseError {
+			position: 1,
+			message: "number \"1e999\" is too large".to_owned(),
+		});
+		assert_eq!(error_at("z + 1e39"), 5);
+		assert!(parse("z + 1e38").is_ok());
+	}
+
+	#[test]
+	fn inject() {
+		let custom_formula: CustomFormula = "z*z + c".parse().unwrap();
+		let shader = format!("vec2 custom(vec2 z, vec2 c) {{\n    return z; {}\n}}", MARKER);
+		assert_eq!(
+			custom_formula.inject(&shader).unwrap(),
+			format!("vec2 custom(vec2 z, vec2 c) {{\n    return (cmul(z, z) + c); {}\n}}", MARKER)
+		);
+		assert_eq!(custom_formula.inject("void main() {}"), None);
+	}
+}
@@ -0,0 +1,119 @@
+//! Renders frames without opening a window and saves them as PNG files.
+//!
+//! Uses the same `mandelbrot.frag` pipeline as the windowed views, only the
+//! render target is an offscreen texture that gets copied back to the CPU.
+
+use std::path::Path;
+use std::sync::{mpsc, Arc, Mutex};
+
+use crate::utils::{AtomicDevice, FractalParams};
+use crate::views::{OffscreenView, FractalViewable};
+
+/// Format the render pipelines are created with.
+const RENDER_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Bgra8UnormSrgb;
+/// `copy_texture_to_buffer` requires every row to be a multiple of this many bytes.
+const ROW_PITCH_ALIGNMENT: u32 = 256;
+const BYTES_PER_PIXEL: u32 = 4;
+
+/// Gets a GPU device from `wgpu` without a surface to present to.
+pub fn request_device() -> (wgpu::Device, wgpu::Queue) {
+	let adapter = wgpu::Adapter::request(
+		&wgpu::RequestAdapterOptions {
+			power_preference: wgpu::PowerPreference::Default,
+			backends: wgpu::BackendBit::PRIMARY,
+		},
+	).expect("No suitable GPU adapter found");
+
+	adapter.request_device(&wgpu::DeviceDescriptor {
+		extensions: wgpu::Extensions {
+			anisotropic_filtering: true,
+		},
+		limits: wgpu::Limits::default(),
+	})
+}
+
+/// Renders `params` into a `width` x `height` image.
+///
+/// Returns tightly packed RGBA pixels, top row first, or why the custom formula doesn't compile.
+pub fn render(params: &FractalParams, width: u32, height: u32) -> Result<Vec<u8>, String> {
+	let (device, mut queue) = request_device();
+	let device: AtomicDevice = Arc::new(Mutex::new(device));
+
+	let size = winit::dpi::LogicalSize::new(f64::from(width), f64::from(height));
+	let mut view = OffscreenView::new(&device.lock().unwrap(), size);
+	// `set_params` would go on with the formula from before.
+	view.set_custom_formula(&device, params.custom_formula.clone())?;
+	let params_buf = view.set_params(&device, params);
+
+	let extent = wgpu::Extent3d {
+		width,
+		height,
+		depth: 1,
+	};
+	let texture = device.lock().unwrap().create_texture(&wgpu::TextureDescriptor {
+		size: extent,
+		array_layer_count: 1,
+		mip_level_count: 1,
+		sample_count: 1,
+		dimension: wgpu::TextureDimension::D2,
+		format: RENDER_FORMAT,
+		usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT | wgpu::TextureUsage::COPY_SRC,
+	});
+	let texture_view = texture.create_default_view();
+
+	let render_buf = view.render(&device, &texture_view);
+
+	let row_pitch = {
+		let unpadded = width * BYTES_PER_PIXEL;
+		(unpadded + ROW_PITCH_ALIGNMENT - 1) / ROW_PITCH_ALIGNMENT * ROW_PITCH_ALIGNMENT
+	};
+	let output_size = wgpu::BufferAddress::from(row_pitch * height);
+	let output_buf = device.lock().unwrap().create_buffer(&wgpu::BufferDescriptor {
+		size: output_size,
+		usage: wgpu::BufferUsage::MAP_READ | wgpu::BufferUsage::COPY_DST,
+	});
+
+	let copy_buf = {
+		let mut encoder =
+			device.lock().unwrap().create_command_encoder(&wgpu::CommandEncoderDescriptor { todo: 0 });
+		encoder.copy_texture_to_buffer(
+			wgpu::TextureCopyView {
+				texture: &texture,
+				mip_level: 0,
+				array_layer: 0,
+				origin: wgpu::Origin3d { x: 0.0, y: 0.0, z: 0.0 },
+			},
+			wgpu::BufferCopyView {
+				buffer: &output_buf,
+				offset: 0,
+				row_pitch,
+				image_height: height,
+			},
+			extent
+		);
+		encoder.finish()
+	};
+
+	queue.submit(&[params_buf, render_buf, copy_buf]);
+
+	let (tx, rx) = mpsc::channel();
+	output_buf.map_read_async(0, output_size, move |result: wgpu::BufferMapAsyncResult<&[u8]>| {
+		tx.send(result.map(|mapping| mapping.data.to_vec())).unwrap();
+	});
+	device.lock().unwrap().poll(true);
+	let padded = rx.recv().unwrap().expect("Error reading back offscreen texture");
+
+	let mut rgba = Vec::with_capacity((width * height * BYTES_PER_PIXEL) as usize);
+	for row in padded.chunks(row_pitch as usize) {
+		for bgra in row[..(width * BYTES_PER_PIXEL) as usize].chunks(BYTES_PER_PIXEL as usize) {
+			rgba.extend_from_slice(&[bgra[2], bgra[1], bgra[0], bgra[3]]);
+		}
+	}
+	Ok(rgba)
+}
+
+/// Saves tightly packed RGBA `pixels` as a PNG at `path`.
+pub fn save_png(pixels: &[u8], width: u32, height: u32, path: &Path) -> std::io::Result<()> {
+	log::info!("Saving {}x{} image to {:?}", width, height, path);
+	image::save_buffer(path, pixels, width, height, image::ColorType::RGBA(8))
+}
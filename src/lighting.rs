@@ -0,0 +1,71 @@
+//! Lighting of the outside of the set as if it were a surface, composited over the coloring by `colorize.frag`.
+//!
+//! `escaped` in `mandelbrot.frag` leaves the direction of `z / dz`, which is the direction
+//! the distance to the set grows in, as the slope of the surface at every escaped pixel.
+
+use zerocopy::{AsBytes, FromBytes};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Lighting {
+	pub enabled: bool,
+	/// Degrees counterclockwise from the right edge of the window, in the plane of the screen.
+	pub azimuth: f32,
+	/// Degrees above the screen, 90 being straight out of it.
+	pub elevation: f32,
+	/// How steep the slopes are, higher makes deeper shadows.
+	pub height: f32,
+	/// Strength of the highlights.
+	pub specular: f32,
+}
+
+/// `Lighting` as the shader sees it, with the light direction as a unit vector in the pixel coordinates
+/// of `gl_FragCoord`, which point down.
+#[repr(C)]
+#[derive(Clone, Copy, AsBytes, FromBytes)]
+pub struct LightingUniform {
+	pub direction: [f32; 3],
+	pub enabled: i32,
+	pub height: f32,
+	pub specular: f32,
+}
+
+impl Lighting {
+	pub fn uniform(&self) -> LightingUniform {
+		let (azimuth, elevation) = (self.azimuth.to_radians(), self.elevation.to_radians());
+		LightingUniform {
+			direction: [
+				elevation.cos() * azimuth.cos(),
+				-elevation.cos() * azimuth.sin(),
+				elevation.sin(),
+			],
+			enabled: self.enabled as i32,
+			height: self.height,
+			specular: self.specular,
+		}
+	}
+
+	/// Moves the light over the point `offset` pixels from the middle of a window of `size`:
+	/// straight above it in the middle, down to the horizon at the nearer edge.
+	pub fn towards(self, offset: [f64; 2], size: [f32; 2]) -> Self {
+		let radius = f64::from(size[0].min(size[1])) / 2.0;
+		let distance = (offset[0].hypot(offset[1]) / radius).min(1.0);
+		Self {
+			azimuth: (-offset[1]).atan2(offset[0]).to_degrees() as f32,
+			elevation: (90.0 * (1.0 - distance)) as f32,
+			..self
+		}
+	}
+}
+
+/// Light from the top left, like most user interfaces assume.
+impl Default for Lighting {
+	fn default() -> Self {
+		Self {
+			enabled: false,
+			azimuth: 135.0,
+			elevation: 45.0,
+			height: 1.5,
+			specular: 0.5,
+		}
+	}
+}
@@ -0,0 +1,413 @@
+//! Saving and loading of interesting spots as TOML files.
+//!
+//! ```toml
+//! view = "julia"
+//! center = ["-0.745", "0.1"]
+//! zoom = 0.00002
+//! iterations = 400.0
+//! auto_iterations = false
+//! julia = true
+//! formula = "mandelbrot"
+//! power = 3.0
+//! custom_formula = "z*z*z + c*sin(z)"
+//! generator = [-0.8, 0.156]
+//! roots = [[1.0, 0.0], [-0.5, 0.866], [-0.5, -0.866]]
+//! sequence = "AABAB"
+//! warmup = 50
+//! stable_color = "#ffc800"
+//! chaotic_color = "#283ca0"
+//! palette = "hsl"
+//! palette_offset = 0.0
+//! palette_scale = 1.0
+//! coloring = "histogram"
+//! interior = "period"
+//! trap = "circle"
+//! trap_center = [0.0, 0.0]
+//! trap_size = 0.5
+//! average = "stripe"
+//! stripe_density = 5.0
+//! average_mix = 0.5
+//! lighting = true
+//! light_azimuth = 135.0
+//! light_elevation = 45.0
+//! light_height = 1.5
+//! light_specular = 0.5
+//! ```
+//!
+//! The center is written as strings so that no digits get lost on deep zooms,
+//! plain numbers are read as well.
+
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+
+use crate::cli::View;
+use crate::bigfloat::BigFloat;
+use crate::polynomial::Polynomial;
+use crate::lyapunov::Lyapunov;
+use crate::palette::{Palette, Color, DEFAULT_PALETTE};
+use crate::coloring::{Coloring, ColoringMode, InteriorMode};
+use crate::trap::{Trap, TrapShape};
+use crate::average::{Average, AverageKind};
+use crate::lighting::Lighting;
+use crate::custom_formula::CustomFormula;
+use crate::utils::{FractalParams, Position, Center, Zoom, Iterations, Julia, Formula, Family, DEFAULT_POWER, ABSOLUTE_PATH};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Location {
+	pub view: View,
+	/// Point of the complex plane in the middle of the window.
+	pub center: [BigFloat; 2],
+	pub zoom: f64,
+	pub iterations: f32,
+	/// Whether the iterations follow the zoom, which `iterations` was the last value of.
+	#[serde(default)]
+	pub auto_iterations: bool,
+	pub julia: bool,
+	#[serde(default)]
+	pub formula: Family,
+	#[serde(default = "default_power")]
+	pub power: f32,
+	/// As typed in, used by `formula = "custom"`.
+	#[serde(default)]
+	pub custom_formula: Option<String>,
+	pub generator: [f64; 2],
+	/// Of the polynomial of the Newton view.
+	#[serde(default = "default_roots")]
+	pub roots: Vec<[f64; 2]>,
+	/// Of the Lyapunov view, like `AABAB`.
+	#[serde(default = "default_sequence")]
+	pub sequence: String,
+	#[serde(default = "default_warmup")]
+	pub warmup: u32,
+	#[serde(default = "default_stable_color")]
+	pub stable_color: Color,
+	#[serde(default = "default_chaotic_color")]
+	pub chaotic_color: Color,
+	/// `hsl` or the palette file, see `Palette::load`. Relative paths are relative to the working directory.
+	#[serde(default = "default_palette")]
+	pub palette: String,
+	#[serde(default)]
+	pub palette_offset: f32,
+	#[serde(default = "default_palette_scale")]
+	pub palette_scale: f32,
+	#[serde(default)]
+	pub coloring: ColoringMode,
+	#[serde(default)]
+	pub interior: InteriorMode,
+	#[serde(default)]
+	pub trap: TrapShape,
+	#[serde(default)]
+	pub trap_center: [f64; 2],
+	#[serde(default = "default_trap_size")]
+	pub trap_size: f32,
+	#[serde(default)]
+	pub average: AverageKind,
+	#[serde(default = "default_stripe_density")]
+	pub stripe_density: f32,
+	#[serde(default = "default_average_mix")]
+	pub average_mix: f32,
+	#[serde(default)]
+	pub lighting: bool,
+	#[serde(default = "default_light_azimuth")]
+	pub light_azimuth: f32,
+	#[serde(default = "default_light_elevation")]
+	pub light_elevation: f32,
+	#[serde(default = "default_light_height")]
+	pub light_height: f32,
+	#[serde(default = "default_light_specular")]
+	pub light_specular: f32,
+}
+
+fn default_palette() -> String {
+	DEFAULT_PALETTE.to_owned()
+}
+
+fn default_palette_scale() -> f32 {
+	Palette::default().scale
+}
+
+fn default_trap_size() -> f32 {
+	Trap::default().size
+}
+
+fn default_stripe_density() -> f32 {
+	Average::default().density
+}
+
+fn default_average_mix() -> f32 {
+	Average::default().mix
+}
+
+fn default_light_azimuth() -> f32 {
+	Lighting::default().azimuth
+}
+
+fn default_light_elevation() -> f32 {
+	Lighting::default().elevation
+}
+
+fn default_light_height() -> f32 {
+	Lighting::default().height
+}
+
+fn default_light_specular() -> f32 {
+	Lighting::default().specular
+}
+
+fn default_power() -> f32 {
+	DEFAULT_POWER
+}
+
+fn default_roots() -> Vec<[f64; 2]> {
+	Polynomial::default().roots
+}
+
+fn default_sequence() -> String {
+	Lyapunov::default().sequence
+}
+
+fn default_warmup() -> u32 {
+	Lyapunov::default().warmup
+}
+
+fn default_stable_color() -> Color {
+	Lyapunov::default().stable_color
+}
+
+fn default_chaotic_color() -> Color {
+	Lyapunov::default().chaotic_color
+}
+
+impl Location {
+	pub fn new(view: View, params: &FractalParams) -> Self {
+		Self {
+			view,
+			center: [params.center.re.clone(), params.center.im.clone()],
+			zoom: params.zoom.zoom,
+			iterations: params.iterations.iterations,
+			auto_iterations: params.auto_iterations,
+			julia: params.julia.is_julia,
+			formula: params.formula.family,
+			power: params.formula.power,
+			custom_formula: params.custom_formula.as_ref().map(CustomFormula::to_string),
+			generator: params.generator.pos,
+			roots: params.polynomial.roots.clone(),
+			sequence: params.lyapunov.sequence.clone(),
+			warmup: params.lyapunov.warmup,
+			stable_color: params.lyapunov.stable_color,
+			chaotic_color: params.lyapunov.chaotic_color,
+			palette: params.palette.source.clone(),
+			palette_offset: params.palette.offset,
+			palette_scale: params.palette.scale,
+			coloring: params.coloring.mode,
+			interior: params.coloring.interior,
+			trap: params.trap.shape,
+			trap_center: params.trap.center,
+			trap_size: params.trap.size,
+			average: params.average.kind,
+			stripe_density: params.average.density,
+			average_mix: params.average.mix,
+			lighting: params.lighting.enabled,
+			light_azimuth: params.lighting.azimuth,
+			light_elevation: params.lighting.elevation,
+			light_height: params.lighting.height,
+			light_specular: params.lighting.specular,
+		}
+	}
+
+	pub fn params(&self) -> FractalParams {
+		FractalParams {
+			center: Center {
+				re: self.center[0].clone(),
+				im: self.center[1].clone(),
+			},
+			zoom: Zoom { zoom: self.zoom },
+			iterations: Iterations { iterations: self.iterations },
+			auto_iterations: self.auto_iterations,
+			julia: Julia { is_julia: self.julia },
+			formula: Formula {
+				family: self.formula,
+				power: self.power,
+			},
+			// Checked to parse in `load`.
+			custom_formula: self.custom_formula.as_ref().and_then(|source| source.parse().ok()),
+			generator: Position { pos: self.generator },
+			polynomial: Polynomial { roots: self.roots.clone() },
+			lyapunov: Lyapunov {
+				sequence: self.sequence.clone(),
+				warmup: self.warmup,
+				stable_color: self.stable_color,
+				chaotic_color: self.chaotic_color,
+			},
+			palette: Palette {
+				offset: self.palette_offset,
+				scale: self.palette_scale,
+				// Checked to load in `load`, but the file may have changed since.
+				..Palette::load(&self.palette).unwrap_or_else(|e| {
+					log::error!("{}", e);
+					Palette::default()
+				})
+			},
+			coloring: Coloring {
+				mode: self.coloring,
+				interior: self.interior,
+			},
+			trap: Trap {
+				shape: self.trap,
+				center: self.trap_center,
+				size: self.trap_size,
+			},
+			average: Average {
+				kind: self.average,
+				density: self.stripe_density,
+				mix: self.average_mix,
+			},
+			lighting: Lighting {
+				enabled: self.lighting,
+				azimuth: self.light_azimuth,
+				elevation: self.light_elevation,
+				height: self.light_height,
+				specular: self.light_specular,
+			},
+		}
+	}
+
+	pub fn load(path: &Path) -> Result<Self, String> {
+		log::info!("Loading location from {:?}", path);
+		let text = std::fs::read_to_string(path)
+			.map_err(|e| format!("Error reading {:?}: {}", path, e))?;
+
+		let mut location: Self = toml::from_str(&text)
+			.map_err(|e| format!("Error parsing {:?}: {}", path, e))?;
+		Polynomial::from_roots(location.roots.clone())
+			.map_err(|e| format!("Error in roots of {:?}: {}", path, e))?;
+		if let Some(source) = &location.custom_formula {
+			source.parse::<CustomFormula>()
+				.map_err(|e| format!("Error in custom formula of {:?}: {}", path, e))?;
+		}
+		location.sequence = Lyapunov::parse_sequence(&location.sequence)
+			.map_err(|e| format!("Error in sequence of {:?}: {}", path, e))?;
+		Palette::load(&location.palette)
+			.map_err(|e| format!("Error in palette of {:?}: {}", path, e))?;
+
+		Ok(location)
+	}
+
+	pub fn save(&self, path: &Path) -> Result<(), String> {
+		log::info!("Saving location to {:?}", path);
+		let text = toml::to_string(self)
+			.map_err(|e| format!("Error serializing location: {}", e))?;
+
+		std::fs::write(path, text)
+			.map_err(|e| format!("Error writing {:?}: {}", path, e))
+	}
+
+	/// Saves into the working directory, named after the current time.
+	pub fn save_new(&self) -> Result<PathBuf, String> {
+		let timestamp = SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.map(|duration| duration.as_secs())
+			.unwrap_or(0);
+
+		let mut path = ABSOLUTE_PATH.clone();
+		path.push(format!("location_{}.toml", timestamp));
+		self.save(&path)?;
+
+		Ok(path)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Every field away from its default, so that none of them can get lost on the way.
+	fn params(palette: &str) -> FractalParams {
+		FractalParams {
+			center: Center {
+				re: "-1.7490812690237011111111111111111111".parse().unwrap(),
+				im: "-0.0000000000000000000000000000123456789".parse().unwrap(),
+			},
+			zoom: Zoom { zoom: 1.5e-30 },
+			iterations: Iterations { iterations: 1234.0 },
+			auto_iterations: true,
+			julia: Julia { is_julia: true },
+			formula: Formula {
+				family: Family::Custom,
+				power: 3.5,
+			},
+			custom_formula: Some("z*z*z + c*sin(z)".parse().unwrap()),
+			generator: Position { pos: [-0.8, 0.156] },
+			polynomial: Polynomial { roots: vec![[2.0, 0.0], [-1.0, 0.5]] },
+			lyapunov: Lyapunov {
+				sequence: "AABAB".to_owned(),
+				warmup: 77,
+				stable_color: Color([1, 2, 3]),
+				chaotic_color: Color([4, 5, 6]),
+			},
+			palette: Palette {
+				offset: 0.25,
+				scale: 3.0,
+				..Palette::load(palette).unwrap()
+			},
+			coloring: Coloring {
+				mode: ColoringMode::Histogram,
+				interior: InteriorMode::Period,
+			},
+			trap: Trap {
+				shape: TrapShape::Circle,
+				center: [0.5, -0.25],
+				size: 0.75,
+			},
+			average: Average {
+				kind: AverageKind::Curvature,
+				density: 7.0,
+				mix: 0.3,
+			},
+			lighting: Lighting {
+				enabled: true,
+				azimuth: 12.0,
+				elevation: 34.0,
+				height: 5.6,
+				specular: 0.7,
+			},
+		}
+	}
+
+	#[test]
+	fn round_trips_every_parameter() {
+		let dir = std::env::temp_dir().join(format!("mandelbrot-location-{}", std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		let palette = dir.join("palette.map");
+		std::fs::write(&palette, "0 0 0\n255 128 7\n").unwrap();
+		let params = params(palette.to_str().unwrap());
+		assert_ne!(params.palette.stops, Palette::default().stops);
+
+		let path = dir.join("location.toml");
+		Location::new(View::Julia, &params).save(&path).unwrap();
+		// `params` loads the palette again, before the file is gone.
+		let loaded = Location::load(&path).map(|location| (location.view, location.params()));
+		std::fs::remove_dir_all(&dir).unwrap();
+
+		let (view, loaded) = loaded.unwrap();
+		assert_eq!(view, View::Julia);
+		assert_eq!(loaded, params);
+	}
+
+	#[test]
+	fn reads_the_example() {
+		let example = include_str!("location.rs")
+			.lines()
+			.skip_while(|line| *line != "//! ```toml")
+			.skip(1)
+			.take_while(|line| *line != "//! ```")
+			.map(|line| line.trim_start_matches("//! "))
+			.collect::<Vec<_>>()
+			.join("\n");
+		let location: Location = toml::from_str(&example).unwrap();
+		assert_eq!(location.view, View::Julia);
+		assert_eq!(location.params().center, Center { re: "-0.745".parse().unwrap(), im: "0.1".parse().unwrap() });
+		assert_eq!(location.params().custom_formula, Some("z*z*z + c*sin(z)".parse().unwrap()));
+	}
+}
@@ -0,0 +1,80 @@
+//! Parameters of the Lyapunov view.
+//!
+//! Every pixel is a pair `(a, b)` of growth rates of the logistic map `x -> r x (1 - x)`,
+//! with `r` being `a` or `b` in the order of the letters of the sequence, over and over.
+//! The Lyapunov exponent `(1 / n) * sum(ln |r (1 - 2x)|)` is negative where the map
+//! settles down and positive where it is chaotic, see `lyapunov.frag`.
+
+use zerocopy::{AsBytes, FromBytes};
+use crate::palette::Color;
+
+/// The sequence is passed to the shader as the bits of a `uint`.
+pub const MAX_SEQUENCE_LENGTH: usize = 32;
+
+/// Where the growth rates are the most interesting, both between 2 and 4.
+pub const DEFAULT_CENTER: [f64; 2] = [3.0, 3.0];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lyapunov {
+	/// Of `A`s and `B`s, like `AABAB`.
+	pub sequence: String,
+	/// Iterations of the map thrown away before the exponent is summed up.
+	pub warmup: u32,
+	/// Of where the exponent is negative, the brighter the more negative.
+	pub stable_color: Color,
+	/// Of where the exponent is positive, the brighter the more positive.
+	pub chaotic_color: Color,
+}
+
+/// `Lyapunov` as the shader sees it.
+#[repr(C)]
+#[derive(Clone, Copy, AsBytes, FromBytes)]
+pub struct LyapunovUniform {
+	pub stable_color: [f32; 4],
+	pub chaotic_color: [f32; 4],
+	/// Bit `i` is set where the `i`th letter is `B`.
+	pub sequence: u32,
+	pub length: f32,
+	pub warmup: f32,
+}
+
+impl Lyapunov {
+	/// Checks that `sequence` is made of 1 to `MAX_SEQUENCE_LENGTH` letters `A` and `B`,
+	/// either case, and makes them upper case.
+	pub fn parse_sequence(sequence: &str) -> Result<String, String> {
+		let sequence = sequence.trim().to_ascii_uppercase();
+		if sequence.is_empty() || sequence.len() > MAX_SEQUENCE_LENGTH {
+			return Err(format!("expected 1 to {} letters, got {}", MAX_SEQUENCE_LENGTH, sequence.len()));
+		}
+		if let Some(letter) = sequence.chars().find(|&letter| letter != 'A' && letter != 'B') {
+			return Err(format!("expected only `A` and `B` in the sequence, got {:?}", letter));
+		}
+		Ok(sequence)
+	}
+
+	pub fn uniform(&self) -> LyapunovUniform {
+		let sequence = self.sequence.chars()
+			.enumerate()
+			.filter(|&(_, letter)| letter == 'B')
+			.fold(0, |bits, (i, _)| bits | 1 << i);
+		LyapunovUniform {
+			stable_color: self.stable_color.linear(),
+			chaotic_color: self.chaotic_color.linear(),
+			sequence,
+			length: self.sequence.len() as f32,
+			warmup: self.warmup as f32,
+		}
+	}
+}
+
+/// `AB` in gold and navy.
+impl Default for Lyapunov {
+	fn default() -> Self {
+		Self {
+			sequence: "AB".to_owned(),
+			warmup: 50,
+			stable_color: Color([255, 200, 0]),
+			chaotic_color: Color([40, 60, 160]),
+		}
+	}
+}
@@ -11,6 +11,12 @@ extern crate notify;
 extern crate byteorder;
 extern crate glsl_to_spirv;
 extern crate zerocopy;
+extern crate image;
+extern crate structopt;
+extern crate serde;
+extern crate toml;
+extern crate num_bigint;
+extern crate num_traits;
 
 use winit::{
 	event::{self, VirtualKeyCode},
@@ -24,19 +30,55 @@ use std::time::Instant;
 use std::thread;
 
 mod views;
-
-use crate::views::{MandelbrotViewManager, DoubleViewManager, SwitchableViewManager, FractalViewManager, FRAG_SHADER_PATH};
+mod headless;
+mod cpu;
+mod cli;
+mod location;
+mod bigfloat;
+mod perturbation;
+mod polynomial;
+mod lyapunov;
+mod custom_formula;
+mod palette;
+mod coloring;
+mod trap;
+mod average;
+mod lighting;
+
+use crate::views::{MandelbrotViewManager, DoubleViewManager, NewtonViewManager, BuddhabrotViewManager, LyapunovViewManager, SwitchableViewManager, FractalViewManager, FRAG_SHADER_PATH};
 
 pub mod utils;
 
-use crate::utils::{ABSOLUTE_PATH, WindowSize, Changed, create_watcher, CurrentView};
-use utils::fps_command;
+use crate::utils::{ABSOLUTE_PATH, WindowSize, Changed, create_watcher, PaletteWatcher, CurrentView, Formula, Family};
+use crate::custom_formula::CustomFormula;
+use crate::palette::Palette;
+use crate::average::Average;
+use crate::cli::{Options, Command, View};
+use crate::location::Location;
+use structopt::StructOpt;
+use utils::{fps_command, Hud};
 use wgpu::CommandBuffer;
 use std::sync::atomic::{AtomicBool, Ordering};
 
 /// Window title
 pub const TITLE: &str = "Ah shit here we go again";
 pub const ZOOM_SENSITIVITY: f32 = 0.9;
+/// Lengths of the palette that `,` and `.` move it by.
+const PALETTE_OFFSET_STEP: f32 = 0.05;
+/// Factor that `-` and `=` change the palette scale by.
+const PALETTE_SCALE_STEP: f32 = 1.25;
+/// Factor that Ctrl and the mouse wheel change the size of the trap by per step.
+const TRAP_SIZE_STEP: f32 = 1.1;
+/// Steps of `;` and `'` through the mix of the average.
+const AVERAGE_MIX_STEP: f32 = 0.1;
+/// Stripes that `;` and `'` with Shift add or remove.
+const STRIPE_DENSITY_STEP: f32 = 1.0;
+/// Factor that Alt and the mouse wheel change the iterations by per step.
+const ITERATIONS_STEP: f32 = 0.99;
+/// The same with Shift, so that `MAX_ITERATIONS` is a few steps away rather than hundreds.
+const ITERATIONS_FAST_STEP: f32 = 0.5;
+/// Lengths of the palette per second that C cycles it by.
+const PALETTE_CYCLE_SPEED: f32 = 0.1;
 
 lazy_static! {
 	/// Gta San Andreas Icon. CJ Cameo
@@ -53,6 +95,11 @@ lazy_static! {
 	};
 }
 
+/// The title bar only shows one line, compiler errors go on for many.
+fn first_line(text: &str) -> &str {
+	text.lines().next().unwrap_or("")
+}
+
 /// Main function.
 ///
 /// 1. Creates a window, a surface to draw on.
@@ -62,9 +109,45 @@ lazy_static! {
 ///    fragment shader while the program is running.
 /// 5. Starts the event loop, in which it passes user input events
 ///    to the current view using the `SwitchableViewManager`.
+///
+/// The `render` subcommand skips all of the above and renders
+/// a single frame offscreen instead, see `headless` and `cpu`.
 fn main() {
 	env_logger::init();
 
+	let options = Options::from_args();
+	let params = options.params();
+
+	if let Some(Command::Render { output, cpu, samples }) = &options.command {
+		let view = options.view();
+		if view == View::Double || view == View::Newton || view == View::Lyapunov {
+			eprintln!(
+				"The {:?} view can only be shown in a window, render --view single or julia, or buddhabrot or nebulabrot with --cpu",
+				view
+			);
+			std::process::exit(1);
+		}
+		let buddhabrot = view == View::Buddhabrot || view == View::Nebulabrot;
+		if buddhabrot && !*cpu {
+			eprintln!("The {:?} view renders headless only with --cpu", view);
+			std::process::exit(1);
+		}
+		let (width, height) = options.size.unwrap_or(cli::DEFAULT_RENDER_SIZE);
+		let pixels = if buddhabrot {
+			cpu::buddhabrot::render(&params, width, height, view == View::Nebulabrot, *samples)
+		} else if *cpu {
+			cpu::render(&params, width, height)
+		} else {
+			headless::render(&params, width, height).unwrap_or_else(|err| {
+				eprintln!("{}", err);
+				std::process::exit(1);
+			})
+		};
+		headless::save_png(&pixels, width, height, output)
+			.expect("Error saving rendered image");
+		return;
+	}
+
 	let event_loop = EventLoop::new();
 
 	let icon: winit::window::Icon = (*ICON).clone();
@@ -76,7 +159,11 @@ fn main() {
 
 	#[cfg(not(feature = "gl"))]
 	let (window, mut hidpi_factor, lsize, psize, surface) = {
-		let window = winit::window::Window::new(&event_loop).unwrap();
+		let mut builder = winit::window::WindowBuilder::new();
+		if let Some((width, height)) = options.size {
+			builder = builder.with_inner_size(winit::dpi::LogicalSize::new(f64::from(width), f64::from(height)));
+		}
+		let window = builder.build(&event_loop).unwrap();
 		init_window(&window);
 		let hidpi_factor = window.hidpi_factor();
 		let lsize = window.inner_size();
@@ -132,9 +219,14 @@ fn main() {
 		present_mode: wgpu::PresentMode::Vsync,
 	};
 	let (_watcher, frag_file_change_receiver) = create_watcher(&*FRAG_SHADER_PATH);
+	let (mut palette_watcher, palette_file_change_receiver) = PaletteWatcher::new();
+	palette_watcher.watch(&params.palette);
 
 	let single_view = MandelbrotViewManager::new(&device, lsize);
 	let double_view = DoubleViewManager::new(&device, lsize);
+	let newton_view = NewtonViewManager::new(&device, lsize);
+	let buddhabrot_view = BuddhabrotViewManager::new(&device, lsize);
+	let lyapunov_view = LyapunovViewManager::new(&device, lsize);
 
 	let swap_chain = device.create_swap_chain(
 		&surface,
@@ -148,6 +240,7 @@ fn main() {
 	};
 
 	let mut is_left_button_pressed = false;
+	let mut is_right_button_pressed = false;
 	let mut is_cursor_on_screen = false;
 
 	let mut window_size = WindowSize {
@@ -156,19 +249,35 @@ fn main() {
 
 	window.set_title(TITLE);
 	let mut past = Instant::now();
-	let mut is_full_screen = false;
+	let mut series_approximation = true;
+	// What has been typed so far after pressing Enter, see `custom_formula.rs`.
+	let mut formula_input: Option<String> = None;
+	// When the palette was last moved along, while C keeps cycling it.
+	let mut palette_cycle: Option<Instant> = None;
+	let mut is_full_screen = options.fullscreen;
+	if is_full_screen {
+		let video_mode = window.current_monitor().video_modes().next().unwrap();
+		window.set_fullscreen(Some(Fullscreen::Exclusive(video_mode)));
+	}
 
 	let single_view = Arc::new(Mutex::new(single_view));
 	let double_view = Arc::new(Mutex::new(double_view));
+	let newton_view = Arc::new(Mutex::new(newton_view));
+	let buddhabrot_view = Arc::new(Mutex::new(buddhabrot_view));
+	let lyapunov_view = Arc::new(Mutex::new(lyapunov_view));
 	let current_view = Arc::new(Mutex::new(SwitchableViewManager::init(
 		single_view,
 		double_view,
-		CurrentView::Single
+		newton_view,
+		buddhabrot_view,
+		lyapunov_view,
+		options.view()
 	)));
 
 	let device = Arc::new(Mutex::new(device));
 	let swap_chain = Arc::new(Mutex::new(swap_chain));
 	let queue = Arc::new(Mutex::new(queue));
+	queue.lock().unwrap().submit(&current_view.lock().unwrap().set_params(&device, &params));
 	let window: Arc<Mutex<winit::window::Window>> = Arc::new(Mutex::new(window));
 	let changed = Arc::new(Mutex::new(Changed { 0: true }));
 	let please_set_title_back = Arc::new(AtomicBool::new(false));
@@ -186,15 +295,58 @@ fn main() {
 				if let Ok(notify::DebouncedEvent::Write(..)) = frag_file_change_receiver.recv() {
 					log::info!("Write event in fragment shader");
 					window.lock().unwrap().set_title("Loading fragment shader...");
-					view.lock().unwrap().reload_fs(&device);
+					match view.lock().unwrap().reload_fs(&device) {
+						Ok(()) => please_set_title_back.store(true, Ordering::SeqCst),
+						// Stays in the title until the shader compiles again.
+						Err(err) => {
+							log::error!("{}", err);
+							window.lock().unwrap().set_title(&format!("Shader error: {}", first_line(&err)));
+						}
+					}
 					changed.lock().unwrap().set(true, "Write to shader");
-					please_set_title_back.store(true, Ordering::SeqCst);
 					log::info!("Requesting redraw");
 					window.lock().unwrap().request_redraw();
 				}
 			}
 		});
 	}
+	{
+		let device = Arc::clone(&device);
+		let queue = Arc::clone(&queue);
+		let window = Arc::clone(&window);
+		let view = Arc::clone(&current_view);
+		let changed = Arc::clone(&changed);
+
+		thread::spawn(move || {
+			log::info!("Palette watcher thread spawned");
+			loop {
+				match palette_file_change_receiver.recv() {
+					// Some editors save by replacing the file.
+					Ok(notify::DebouncedEvent::Write(..)) | Ok(notify::DebouncedEvent::Create(..)) => {
+						log::info!("Write event in palette");
+						let mut view = view.lock().unwrap();
+						let current = view.params().palette;
+						match Palette::load(&current.source) {
+							Ok(palette) => {
+								let palette = Palette {
+									offset: current.offset,
+									scale: current.scale,
+									..palette
+								};
+								let command_buf = view.set_palette(&device, palette);
+								queue.lock().unwrap().submit(&command_buf);
+								changed.lock().unwrap().set(true, "Write to palette");
+								window.lock().unwrap().request_redraw();
+							}
+							Err(err) => log::error!("{}", err)
+						}
+					}
+					Ok(_) => (),
+					Err(_) => break
+				}
+			}
+		});
+	}
 	#[allow(clippy::cognitive_complexity)] // Hard to break up
 	event_loop.run(move |event, _, control_flow| {
 		*control_flow = if cfg!(feature = "metal-auto-capture") {
@@ -216,14 +368,24 @@ fn main() {
 					}
 					let mut swap_chain = swap_chain.lock().unwrap();
 					let frame = swap_chain.get_next_texture();
-					let bufs = current_view.lock().unwrap().render(&device, &frame);
+					let (bufs, skipped, params, progress) = {
+						let mut current_view = current_view.lock().unwrap();
+						let bufs = current_view.render(&device, &frame.view);
+						(bufs, current_view.skipped_iterations(), current_view.params(), current_view.progress())
+					};
 
 					let fps_buf = fps_command(
 						&device,
 						&mut fps_glyph_brush,
 						&psize,
-						&frame,
-						&mut past
+						Hud {
+							frame: &frame,
+							past: &mut past,
+							skipped,
+							iterations: params.iterations,
+							auto_iterations: params.auto_iterations,
+							progress,
+						}
 					);
 					let mut queue = queue.lock().unwrap();
 					queue.submit(&bufs);
@@ -279,6 +441,9 @@ fn main() {
 						if current_view.current == CurrentView::Double {
 							changed.lock().unwrap().set(true, "Updated julia generator");
 						}
+						if is_right_button_pressed {
+							changed.lock().unwrap().set(true, "selection moved");
+						}
 					}
 					if let Some(command_buf) = command_buf {
 						queue.lock().unwrap().submit(&command_buf);
@@ -287,6 +452,7 @@ fn main() {
 				event::WindowEvent::MouseInput {
 					button,
 					state,
+					modifiers,
 					..
 				} => {
 					log::info!("Mouse input");
@@ -297,7 +463,15 @@ fn main() {
 							is_left_button_pressed = false;
 						}
 					}
-					current_view.lock().unwrap().mouse_input(button, state);
+					if button == winit::event::MouseButton::Right {
+						is_right_button_pressed = state == winit::event::ElementState::Pressed;
+						changed.lock().unwrap().set(true, "selection");
+					}
+					let command_buf = current_view.lock().unwrap().mouse_input(&device, button, state, modifiers);
+					if let Some(command_buf) = command_buf {
+						queue.lock().unwrap().submit(&command_buf);
+						changed.lock().unwrap().set(true, "mouse input");
+					}
 				}
 				event::WindowEvent::MouseWheel {
 					delta,
@@ -318,8 +492,14 @@ fn main() {
 					log::info!("MouseWheel moved delta: {:?}", y_delta);
 					let command_buf: Vec<CommandBuffer>;
 					if modifiers.alt {
-						command_buf = current_view.lock().unwrap().iterations(&device, y_delta);
+						let step = if modifiers.shift { ITERATIONS_FAST_STEP } else { ITERATIONS_STEP };
+						command_buf = current_view.lock().unwrap().iterations(&device, step.powf(y_delta.signum()));
 						changed.lock().unwrap().set(true, "iterations");
+					} else if modifiers.ctrl {
+						let mut current_view = current_view.lock().unwrap();
+						let trap = current_view.params().trap.scale(TRAP_SIZE_STEP.powf(y_delta));
+						command_buf = current_view.set_trap(&device, trap);
+						changed.lock().unwrap().set(true, "trap");
 					} else {
 						// https://github.com/danyshaanan/mandelbrot/blob/master/docs/glsl/index.htm#L149
 						command_buf = current_view.lock().unwrap().zoom(&device, y_delta);
@@ -328,6 +508,64 @@ fn main() {
 
 					queue.lock().unwrap().submit(&command_buf);
 				}
+				event::WindowEvent::ReceivedCharacter(character) => {
+					if let Some(input) = &mut formula_input {
+						if !character.is_control() {
+							input.push(character);
+							window.lock().unwrap().set_title(&format!("Formula: {}_", input));
+						}
+					}
+				}
+				// While typing a formula the keys go to it instead, Enter applies it and Escape gives up.
+				event::WindowEvent::KeyboardInput {
+					input: event::KeyboardInput {
+						virtual_keycode: Some(key),
+						state: event::ElementState::Pressed,
+						..
+					},
+					..
+				} if formula_input.is_some() => {
+					match key {
+						VirtualKeyCode::Back => {
+							let input = formula_input.as_mut().unwrap();
+							input.pop();
+							window.lock().unwrap().set_title(&format!("Formula: {}_", input));
+						}
+						VirtualKeyCode::Escape => {
+							formula_input = None;
+							window.lock().unwrap().set_title(TITLE);
+						}
+						VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter => {
+							let input = formula_input.take().unwrap();
+							match input.parse::<CustomFormula>() {
+								Ok(custom_formula) => {
+									let mut current_view = current_view.lock().unwrap();
+									window.lock().unwrap().set_title("Compiling formula...");
+									match current_view.set_custom_formula(&device, Some(custom_formula.clone())) {
+										Ok(()) => {
+											let formula = Formula { family: Family::Custom, ..current_view.params().formula };
+											let command_buf = current_view.set_formula(&device, formula);
+											queue.lock().unwrap().submit(&command_buf);
+											window.lock().unwrap().set_title(&custom_formula.to_string());
+											please_set_title_back.store(true, Ordering::SeqCst);
+											changed.lock().unwrap().set(true, "custom formula");
+										}
+										Err(err) => {
+											log::error!("{}", err);
+											window.lock().unwrap().set_title(&format!("Formula: {}_ (error {})", input, first_line(&err)));
+											formula_input = Some(input);
+										}
+									}
+								}
+								Err(err) => {
+									window.lock().unwrap().set_title(&format!("Formula: {}_ (error {})", input, err));
+									formula_input = Some(input);
+								}
+							}
+						}
+						_ => ()
+					}
+				}
 				event::WindowEvent::KeyboardInput {
 					input:
 					event::KeyboardInput {
@@ -344,11 +582,159 @@ fn main() {
 					input: event::KeyboardInput {
 						virtual_keycode: Some(key),
 						state: event::ElementState::Pressed,
+						modifiers,
 						..
 					},
 					..
 				} => {
 					match key {
+						VirtualKeyCode::S if modifiers.ctrl => {
+							let location = {
+								let mut current_view = current_view.lock().unwrap();
+								Location::new(current_view.view(), &current_view.params())
+							};
+							match location.save_new() {
+								Ok(path) => {
+									window.lock().unwrap().set_title(&format!("Saved {}", path.display()));
+									please_set_title_back.store(true, Ordering::SeqCst);
+								}
+								Err(err) => log::error!("{}", err)
+							}
+						},
+						VirtualKeyCode::Z if modifiers.ctrl => {
+							let command_buf = if modifiers.shift {
+								current_view.lock().unwrap().redo(&device)
+							} else {
+								current_view.lock().unwrap().undo(&device)
+							};
+							if let Some(command_buf) = command_buf {
+								queue.lock().unwrap().submit(&command_buf);
+								changed.lock().unwrap().set(true, "history");
+							}
+						},
+						VirtualKeyCode::F | VirtualKeyCode::LBracket | VirtualKeyCode::RBracket => {
+							let mut current_view = current_view.lock().unwrap();
+							let formula = current_view.params().formula;
+							// Shift takes smaller steps, for exponents that aren't whole.
+							let step = if modifiers.shift { 0.1 } else { 1.0 };
+							let formula = match key {
+								VirtualKeyCode::F => Formula { family: formula.family.next(), ..formula },
+								VirtualKeyCode::LBracket => formula.add_power(-step),
+								_ => formula.add_power(step),
+							};
+							let command_buf = current_view.set_formula(&device, formula);
+							queue.lock().unwrap().submit(&command_buf);
+							window.lock().unwrap().set_title(&formula.to_string());
+							please_set_title_back.store(true, Ordering::SeqCst);
+							changed.lock().unwrap().set(true, "formula");
+						},
+						VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter => {
+							let input = current_view.lock().unwrap().params().custom_formula
+								.map_or_else(String::new, |custom_formula| custom_formula.to_string());
+							window.lock().unwrap().set_title(&format!("Formula: {}_", input));
+							formula_input = Some(input);
+						},
+						VirtualKeyCode::Comma | VirtualKeyCode::Period | VirtualKeyCode::Minus | VirtualKeyCode::Equals => {
+							let mut current_view = current_view.lock().unwrap();
+							let mut palette = current_view.params().palette;
+							match key {
+								VirtualKeyCode::Comma => palette.offset -= PALETTE_OFFSET_STEP,
+								VirtualKeyCode::Period => palette.offset += PALETTE_OFFSET_STEP,
+								VirtualKeyCode::Minus => palette.scale /= PALETTE_SCALE_STEP,
+								_ => palette.scale *= PALETTE_SCALE_STEP,
+							}
+							// Keeps the offset small, the palette repeats anyway.
+							palette.offset -= palette.offset.floor();
+							window.lock().unwrap().set_title(&format!("Palette offset {:.2}, scale {:.2}", palette.offset, palette.scale));
+							please_set_title_back.store(true, Ordering::SeqCst);
+							let command_buf = current_view.set_palette(&device, palette);
+							queue.lock().unwrap().submit(&command_buf);
+							changed.lock().unwrap().set(true, "palette");
+						},
+						VirtualKeyCode::M => {
+							let mut current_view = current_view.lock().unwrap();
+							let mut coloring = current_view.params().coloring;
+							coloring.mode = coloring.mode.next();
+							window.lock().unwrap().set_title(&coloring.mode.to_string());
+							please_set_title_back.store(true, Ordering::SeqCst);
+							let command_buf = current_view.set_coloring(&device, coloring);
+							queue.lock().unwrap().submit(&command_buf);
+							changed.lock().unwrap().set(true, "coloring");
+						},
+						VirtualKeyCode::I => {
+							let mut current_view = current_view.lock().unwrap();
+							let mut coloring = current_view.params().coloring;
+							coloring.interior = coloring.interior.next();
+							window.lock().unwrap().set_title(&coloring.interior.to_string());
+							please_set_title_back.store(true, Ordering::SeqCst);
+							let command_buf = current_view.set_coloring(&device, coloring);
+							queue.lock().unwrap().submit(&command_buf);
+							changed.lock().unwrap().set(true, "interior");
+						},
+						VirtualKeyCode::V | VirtualKeyCode::Semicolon | VirtualKeyCode::Apostrophe => {
+							let mut current_view = current_view.lock().unwrap();
+							let average = current_view.params().average;
+							let sign = if key == VirtualKeyCode::Semicolon { -1.0 } else { 1.0 };
+							let (average, title) = match key {
+								VirtualKeyCode::V => {
+									let average = Average { kind: average.kind.next(), ..average };
+									(average, average.kind.to_string())
+								}
+								_ if modifiers.shift => {
+									let average = average.add_density(sign * STRIPE_DENSITY_STEP);
+									(average, format!("Stripe density {}", average.density))
+								}
+								_ => {
+									let average = average.add_mix(sign * AVERAGE_MIX_STEP);
+									(average, format!("Average mix {:.1}", average.mix))
+								}
+							};
+							window.lock().unwrap().set_title(&title);
+							please_set_title_back.store(true, Ordering::SeqCst);
+							let command_buf = current_view.set_average(&device, average);
+							queue.lock().unwrap().submit(&command_buf);
+							changed.lock().unwrap().set(true, "average");
+						},
+						VirtualKeyCode::L => {
+							let mut current_view = current_view.lock().unwrap();
+							let mut lighting = current_view.params().lighting;
+							lighting.enabled = !lighting.enabled;
+							window.lock().unwrap().set_title(if lighting.enabled { "Lighting on" } else { "Lighting off" });
+							please_set_title_back.store(true, Ordering::SeqCst);
+							let command_buf = current_view.set_lighting(&device, lighting);
+							queue.lock().unwrap().submit(&command_buf);
+							changed.lock().unwrap().set(true, "lighting");
+						},
+						VirtualKeyCode::N => {
+							let mut current_view = current_view.lock().unwrap();
+							let auto_iterations = !current_view.params().auto_iterations;
+							window.lock().unwrap().set_title(if auto_iterations { "Auto iterations on" } else { "Auto iterations off" });
+							please_set_title_back.store(true, Ordering::SeqCst);
+							current_view.set_auto_iterations(auto_iterations);
+							changed.lock().unwrap().set(true, "auto iterations");
+						},
+						VirtualKeyCode::T => {
+							let mut current_view = current_view.lock().unwrap();
+							let mut trap = current_view.params().trap;
+							trap.shape = trap.shape.next();
+							window.lock().unwrap().set_title(&trap.shape.to_string());
+							please_set_title_back.store(true, Ordering::SeqCst);
+							let command_buf = current_view.set_trap(&device, trap);
+							queue.lock().unwrap().submit(&command_buf);
+							changed.lock().unwrap().set(true, "trap");
+						},
+						VirtualKeyCode::C => {
+							palette_cycle = match palette_cycle {
+								Some(_) => None,
+								None => Some(Instant::now()),
+							};
+							log::info!("Palette cycling: {:?}", palette_cycle.is_some());
+						},
+						VirtualKeyCode::A => {
+							series_approximation = !series_approximation;
+							current_view.lock().unwrap().set_series_approximation(series_approximation);
+							changed.lock().unwrap().set(true, "series approximation");
+						},
 						VirtualKeyCode::F11 => {
 							is_full_screen = !is_full_screen;
 							let video_mode = window.lock().unwrap().current_monitor().video_modes().next().unwrap();
@@ -376,6 +762,22 @@ fn main() {
 									current_view.current = CurrentView::Double;
 									window.lock().unwrap().request_redraw();
 								}
+								VirtualKeyCode::Numpad4 | VirtualKeyCode::Key4 => {
+									current_view.current = CurrentView::Newton;
+									window.lock().unwrap().request_redraw();
+								}
+								VirtualKeyCode::Numpad5 | VirtualKeyCode::Key5 => {
+									current_view.set_view(View::Buddhabrot);
+									window.lock().unwrap().request_redraw();
+								}
+								VirtualKeyCode::Numpad6 | VirtualKeyCode::Key6 => {
+									current_view.set_view(View::Nebulabrot);
+									window.lock().unwrap().request_redraw();
+								}
+								VirtualKeyCode::Numpad7 | VirtualKeyCode::Key7 => {
+									current_view.current = CurrentView::Lyapunov;
+									window.lock().unwrap().request_redraw();
+								}
 								_ => ()
 							};
 							if let Some(cmd_buf) = command_buf {
@@ -385,13 +787,38 @@ fn main() {
 						}
 					}
 				}
+				event::WindowEvent::DroppedFile(path) => {
+					match Location::load(&path) {
+						Ok(location) => {
+							let mut current_view = current_view.lock().unwrap();
+							current_view.set_view(location.view);
+							let command_buf = current_view.set_params(&device, &location.params());
+							queue.lock().unwrap().submit(&command_buf);
+							palette_watcher.watch(&current_view.params().palette);
+							changed.lock().unwrap().set(true, "location loaded");
+						}
+						Err(err) => log::error!("{}", err)
+					}
+				}
 				event::WindowEvent::HiDpiFactorChanged(hdpif) => {
 					hidpi_factor = hdpif;
 				}
 				_ => {}
 			},
 			event::Event::EventsCleared => {
-				if changed.lock().unwrap().0 {
+				if let Some(last) = palette_cycle {
+					let now = Instant::now();
+					let elapsed = now.duration_since(last).as_secs_f32();
+					let mut current_view = current_view.lock().unwrap();
+					let mut palette = current_view.params().palette;
+					palette.offset += elapsed * PALETTE_CYCLE_SPEED;
+					palette.offset -= palette.offset.floor();
+					let command_buf = current_view.set_palette(&device, palette);
+					queue.lock().unwrap().submit(&command_buf);
+					changed.lock().unwrap().set(true, "palette cycling");
+					palette_cycle = Some(now);
+				}
+				if changed.lock().unwrap().0 || current_view.lock().unwrap().is_refining() {
 					window.lock().unwrap().request_redraw();
 				}
 			}
@@ -0,0 +1,426 @@
+//! Gradients the escape time is colored with.
+//!
+//! A gradient is a list of color stops between 0 and 1 that wraps around.
+//! It is baked into `PALETTE_SIZE` texels of a 1D texture, which `color()` in
+//! `colorize.frag` samples at `it / PALETTE_PERIOD * scale + offset`.
+//!
+//! Besides the built in `hsl` hue ramp gradients are read from
+//!
+//! * `.toml` files of our own, `stops = [{ position = 0.0, color = "#000764" }, ...]`,
+//! * Fractint `.map` files, a line of `red green blue` per color,
+//! * UltraFractal `.ugr` files, of which `gradients.ugr#name` picks the gradient called `name`
+//!   and `gradients.ugr` the first one.
+//!
+//! The colors between the stops are blended in sRGB, the way Fractint and UltraFractal do,
+//! unless the palette asks for linear light, see `Interpolation`.
+
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::convert::TryFrom;
+use serde::{Serialize, Deserialize};
+
+/// Texels of the palette texture.
+pub const PALETTE_SIZE: u32 = 256;
+
+/// Iterations per length of the gradient at a scale of 1. Keep in sync with `colorize.frag`.
+pub const PALETTE_PERIOD: f32 = 240.0;
+
+/// Name of the built in hue ramp, which used to be the only palette there was.
+pub const DEFAULT_PALETTE: &str = "hsl";
+
+/// UltraFractal gradients have this many indices.
+const UGR_INDICES: f32 = 400.0;
+
+/// Converts an sRGB channel between 0 and 1 to linear light, the way sampling a `*Srgb` texture does.
+pub fn srgb_to_linear(srgb: f32) -> f32 {
+	if srgb <= 0.04045 {
+		srgb / 12.92
+	} else {
+		((srgb + 0.055) / 1.055).powf(2.4)
+	}
+}
+
+/// Converts a linear channel the way writing to a `*Srgb` render target does.
+pub fn linear_to_srgb(linear: f32) -> u8 {
+	let linear = linear.max(0.0).min(1.0);
+	let srgb = if linear <= 0.003_130_8 {
+		linear * 12.92
+	} else {
+		1.055 * linear.powf(1.0 / 2.4) - 0.055
+	};
+	(srgb * 255.0).round() as u8
+}
+
+/// A color given as `rrggbb` in hex, with or without a leading `#`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Color(pub [u8; 3]);
+
+impl Color {
+	/// Linear RGB, which the sRGB render target expects the shader to write.
+	pub fn linear(self) -> [f32; 4] {
+		let channel = |value: u8| srgb_to_linear(f32::from(value) / 255.0);
+		[channel(self.0[0]), channel(self.0[1]), channel(self.0[2]), 1.0]
+	}
+
+	/// The channels between 0 and 1, as they are stored.
+	fn srgb(self) -> [f32; 4] {
+		let channel = |value: u8| f32::from(value) / 255.0;
+		[channel(self.0[0]), channel(self.0[1]), channel(self.0[2]), 1.0]
+	}
+}
+
+impl FromStr for Color {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let hex = s.trim_start_matches('#');
+		if hex.len() != 6 || !hex.is_ascii() {
+			return Err(format!("expected a color as `rrggbb`, got {:?}", s));
+		}
+		let mut color = [0; 3];
+		for (i, channel) in color.iter_mut().enumerate() {
+			*channel = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16)
+				.map_err(|e| format!("color {:?}: {}", s, e))?;
+		}
+		Ok(Color(color))
+	}
+}
+
+impl fmt::Display for Color {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "#{:02x}{:02x}{:02x}", self.0[0], self.0[1], self.0[2])
+	}
+}
+
+impl TryFrom<String> for Color {
+	type Error = String;
+
+	fn try_from(s: String) -> Result<Self, Self::Error> {
+		s.parse()
+	}
+}
+
+impl From<Color> for String {
+	fn from(color: Color) -> Self {
+		color.to_string()
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Stop {
+	/// Between 0 and 1.
+	pub position: f32,
+	pub color: Color,
+}
+
+/// How the colors between two stops are blended.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Interpolation {
+	/// Between the stored values, the way Fractint and UltraFractal blend their gradients.
+	Srgb,
+	/// Between the amounts of light, which keeps blends of saturated colors as bright as the colors themselves.
+	Linear,
+}
+
+impl Default for Interpolation {
+	fn default() -> Self {
+		Interpolation::Srgb
+	}
+}
+
+/// Layout of our own palette files.
+#[derive(Deserialize)]
+struct PaletteFile {
+	stops: Vec<Stop>,
+	#[serde(default)]
+	interpolation: Interpolation,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+	/// `hsl` or the file the stops were read from, see `Palette::load`.
+	pub source: String,
+	/// Sorted by position.
+	pub stops: Vec<Stop>,
+	pub interpolation: Interpolation,
+	/// Moves the gradient along, by whole lengths of it.
+	pub offset: f32,
+	/// Lengths of the gradient per `PALETTE_PERIOD` iterations.
+	pub scale: f32,
+}
+
+impl Palette {
+	/// Reads the stops of `source`, which is `hsl` or the path of a `.toml`, `.map` or `.ugr` file.
+	pub fn load(source: &str) -> Result<Self, String> {
+		if source == DEFAULT_PALETTE {
+			return Ok(Self::default());
+		}
+		let (stops, interpolation) = {
+			log::info!("Loading palette from {:?}", source);
+			let (path, name) = split_source(source);
+			let text = std::fs::read_to_string(&path)
+				.map_err(|e| format!("Error reading {:?}: {}", path, e))?;
+			let extension = path.extension()
+				.and_then(|extension| extension.to_str())
+				.map(str::to_ascii_lowercase);
+			match extension.as_ref().map(String::as_str) {
+				Some("map") => parse_map(&text).map(|stops| (stops, Interpolation::Srgb)),
+				Some("ugr") => parse_ugr(&text, name).map(|stops| (stops, Interpolation::Srgb)),
+				Some("toml") => toml::from_str::<PaletteFile>(&text)
+					.map(|file| (file.stops, file.interpolation))
+					.map_err(|e| e.to_string()),
+				_ => Err("expected a .toml, .map or .ugr file".to_owned())
+			}.map_err(|e| format!("Error in palette {:?}: {}", source, e))?
+		};
+		Ok(Self {
+			interpolation,
+			..Self::from_stops(source, stops)?
+		})
+	}
+
+	pub fn from_stops(source: &str, stops: Vec<Stop>) -> Result<Self, String> {
+		if stops.is_empty() {
+			return Err(format!("Palette {:?} has no colors", source));
+		}
+		let mut stops = stops;
+		for stop in &mut stops {
+			stop.position = stop.position.max(0.0).min(1.0);
+		}
+		stops.sort_by(|a, b| a.position.partial_cmp(&b.position).unwrap());
+		Ok(Self {
+			source: source.to_owned(),
+			stops,
+			interpolation: Interpolation::default(),
+			offset: 0.0,
+			scale: 1.0,
+		})
+	}
+
+	/// The file to watch for changes, `None` for the built in palette.
+	pub fn path(&self) -> Option<PathBuf> {
+		if self.source == DEFAULT_PALETTE {
+			None
+		} else {
+			Some(split_source(&self.source).0)
+		}
+	}
+
+	/// Linear color at `position`, wrapping around from the last stop to the first.
+	pub fn at(&self, position: f32) -> [f32; 4] {
+		let position = position - position.floor();
+		let next = self.stops.iter()
+			.position(|stop| stop.position > position)
+			.unwrap_or(0);
+		let previous = if next == 0 { self.stops.len() - 1 } else { next - 1 };
+		let (a, b) = (self.stops[previous], self.stops[next]);
+
+		let mut length = b.position - a.position;
+		let mut distance = position - a.position;
+		if length <= 0.0 {
+			length += 1.0;
+		}
+		if distance < 0.0 {
+			distance += 1.0;
+		}
+		let t = if length > 0.0 { distance / length } else { 0.0 };
+
+		let mix = |a: [f32; 4], b: [f32; 4]| [
+			a[0] + (b[0] - a[0]) * t,
+			a[1] + (b[1] - a[1]) * t,
+			a[2] + (b[2] - a[2]) * t,
+			1.0,
+		];
+		match self.interpolation {
+			Interpolation::Linear => mix(a.color.linear(), b.color.linear()),
+			Interpolation::Srgb => {
+				let color = mix(a.color.srgb(), b.color.srgb());
+				[srgb_to_linear(color[0]), srgb_to_linear(color[1]), srgb_to_linear(color[2]), 1.0]
+			}
+		}
+	}
+
+	/// The texture, `PALETTE_SIZE` sRGB texels with the gradient interpolated as `interpolation` says.
+	pub fn texels(&self) -> Vec<u8> {
+		(0..PALETTE_SIZE)
+			.flat_map(|i| {
+				let color = self.at((i as f32 + 0.5) / PALETTE_SIZE as f32);
+				vec![linear_to_srgb(color[0]), linear_to_srgb(color[1]), linear_to_srgb(color[2]), 255]
+			})
+			.collect()
+	}
+}
+
+/// The hue ramp in linear light, which is what `hslToRgba` writing to the sRGB render target used to give.
+impl Default for Palette {
+	fn default() -> Self {
+		Self {
+			interpolation: Interpolation::Linear,
+			..Self::from_stops(DEFAULT_PALETTE, hsl_stops()).unwrap()
+		}
+	}
+}
+
+/// Red, yellow, green, cyan, blue and magenta, the hue ramp the escape time used to be colored with.
+fn hsl_stops() -> Vec<Stop> {
+	[[255, 0, 0], [255, 255, 0], [0, 255, 0], [0, 255, 255], [0, 0, 255], [255, 0, 255]].iter()
+		.enumerate()
+		.map(|(i, color)| Stop {
+			position: i as f32 / 6.0,
+			color: Color(*color),
+		})
+		.collect()
+}
+
+/// `path#name` into the path and the name of the gradient.
+fn split_source(source: &str) -> (PathBuf, Option<&str>) {
+	match source.rfind('#') {
+		Some(index) => (PathBuf::from(&source[..index]), Some(&source[index + 1..])),
+		None => (PathBuf::from(source), None),
+	}
+}
+
+/// A line of three numbers per color, anything after them being a comment.
+fn parse_map(text: &str) -> Result<Vec<Stop>, String> {
+	let colors: Vec<Color> = text.lines()
+		.filter_map(|line| {
+			let numbers: Vec<Option<u8>> = line.split_whitespace()
+				.take(3)
+				.map(|number| number.parse().ok())
+				.collect();
+			if let [Some(red), Some(green), Some(blue)] = numbers[..] {
+				Some(Color([red, green, blue]))
+			} else {
+				None
+			}
+		})
+		.collect();
+	Ok(colors.iter()
+		.enumerate()
+		.map(|(i, color)| Stop {
+			position: i as f32 / colors.len() as f32,
+			color: *color,
+		})
+		.collect())
+}
+
+/// Blocks of `name { gradient: ... index=0 color=8716288 ... }`, the color being `0xBBGGRR`.
+fn parse_ugr(text: &str, name: Option<&str>) -> Result<Vec<Stop>, String> {
+	let mut rest = text;
+	while let Some(open) = rest.find('{') {
+		let block_name = rest[..open].lines().last().unwrap_or("").trim();
+		let close = rest[open..].find('}')
+			.map(|close| open + close)
+			.ok_or_else(|| format!("gradient {:?} has no closing `}}`", block_name))?;
+		let block = &rest[open + 1..close];
+		rest = &rest[close + 1..];
+
+		let title = block.split("title=\"").nth(1)
+			.and_then(|title| title.split('"').next());
+		if name.map_or(false, |name| name != block_name && Some(name) != title) {
+			continue;
+		}
+
+		// Only the `gradient:` section, not `opacity:` that may follow it.
+		let gradient = block.split("opacity:").next().unwrap_or(block);
+		let mut stops = Vec::new();
+		let mut index = None;
+		for pair in gradient.split_whitespace() {
+			let mut parts = pair.splitn(2, '=');
+			match (parts.next(), parts.next()) {
+				(Some("index"), Some(value)) => {
+					index = Some(value.parse::<f32>().map_err(|e| format!("index {:?}: {}", value, e))?);
+				}
+				(Some("color"), Some(value)) => {
+					let color = value.parse::<u32>().map_err(|e| format!("color {:?}: {}", value, e))?;
+					let index = index.take().ok_or_else(|| format!("color {} without an index", value))?;
+					stops.push(Stop {
+						position: index.rem_euclid(UGR_INDICES) / UGR_INDICES,
+						color: Color([color as u8, (color >> 8) as u8, (color >> 16) as u8]),
+					});
+				}
+				_ => ()
+			}
+		}
+		return Ok(stops);
+	}
+	match name {
+		Some(name) => Err(format!("no gradient called {:?}", name)),
+		None => Err("no gradients".to_owned()),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn map_has_a_stop_per_line_of_three_numbers() {
+		let stops = parse_map("0 0 0 black\n255 128 7\n\n; a comment\n12 34\n").unwrap();
+		assert_eq!(stops, vec![
+			Stop { position: 0.0, color: Color([0, 0, 0]) },
+			Stop { position: 0.5, color: Color([255, 128, 7]) },
+		]);
+	}
+
+	const UGR: &str = "\
+first {
+gradient:
+  title=\"The first\" smooth=no
+  index=0 color=8716288
+  index=200 color=255
+opacity:
+  index=100 color=65280
+}
+
+second {
+gradient:
+  title=\"Sunset\"
+  index=-100 color=16777215
+}
+";
+
+	#[test]
+	fn ugr_picks_the_gradient_by_name_or_title() {
+		let first = vec![
+			Stop { position: 0.0, color: Color([0x00, 0x00, 0x85]) },
+			Stop { position: 0.5, color: Color([0xff, 0x00, 0x00]) },
+		];
+		let second = vec![Stop { position: 0.75, color: Color([0xff, 0xff, 0xff]) }];
+		assert_eq!(parse_ugr(UGR, None).unwrap(), first);
+		assert_eq!(parse_ugr(UGR, Some("first")).unwrap(), first);
+		assert_eq!(parse_ugr(UGR, Some("second")).unwrap(), second);
+		assert_eq!(parse_ugr(UGR, Some("Sunset")).unwrap(), second);
+	}
+
+	#[test]
+	fn ugr_errors() {
+		assert_eq!(parse_ugr(UGR, Some("third")), Err("no gradient called \"third\"".to_owned()));
+		assert_eq!(parse_ugr("", None), Err("no gradients".to_owned()));
+		assert!(parse_ugr("open {\n index=0 color=0\n", None).is_err());
+		assert!(parse_ugr("colorless {\n color=0\n}", None).is_err());
+	}
+
+	/// Halfway from red to yellow `hslToRgba` gave `(1, 0.5, 0)`, which the render target took as linear.
+	#[test]
+	fn hsl_matches_the_old_hue_ramp() {
+		let color = Palette::default().at(1.0 / 12.0);
+		assert!((color[0] - 1.0).abs() < 1e-6);
+		assert!((color[1] - 0.5).abs() < 1e-6);
+		assert!(color[2].abs() < 1e-6);
+	}
+
+	#[test]
+	fn srgb_interpolation_halves_the_stored_values() {
+		let stops = vec![
+			Stop { position: 0.0, color: Color([0, 0, 0]) },
+			Stop { position: 0.5, color: Color([255, 255, 255]) },
+		];
+		let srgb = Palette::from_stops("test", stops).unwrap();
+		let linear = Palette { interpolation: Interpolation::Linear, ..srgb.clone() };
+		assert_eq!(linear_to_srgb(srgb.at(0.25)[0]), 128);
+		assert_eq!(linear_to_srgb(linear.at(0.25)[0]), 188);
+	}
+}
@@ -0,0 +1,438 @@
+//! Perturbation for zooms deeper than double-single can go.
+//!
+//! The orbit of a reference point is iterated on the CPU with `BigFloat`s,
+//! every pixel then only iterates its difference from the reference in `float`,
+//! see `iterations_perturbed` in `mandelbrot.frag`. Where that difference
+//! loses too much precision (Pauldelbrot's criterion) the pixel is glitched
+//! and falls back to another reference, placed by probing a grid of pixels
+//! on the CPU with the port in `crate::cpu::shader`.
+
+use zerocopy::{AsBytes, FromBytes};
+
+use crate::bigfloat::BigFloat;
+use crate::cpu::shader::{self, Uniforms};
+use crate::utils::{FractalParams, Family};
+
+/// Below this zoom the shader iterates perturbations, above it double-single is enough.
+pub const PERTURBATION_ZOOM: f64 = 1e-12;
+
+/// Keep in sync with `MAX_REFERENCES` in `mandelbrot.frag`.
+pub const MAX_REFERENCES: usize = 8;
+
+/// Points kept of each reference orbit, which caps the iterations for deep zooms.
+pub const MAX_REFERENCE_LENGTH: usize = 1 << 16;
+
+/// Probes per side of the grid searched for glitches.
+const PROBES: usize = 24;
+
+/// The cubic term of the series may only be this big compared to the linear one.
+const SERIES_TOLERANCE: f64 = 1e-6;
+
+/// How far off in iterations a probe may end up with the series approximation.
+const SERIES_PROBE_TOLERANCE: f32 = 1e-2;
+
+lazy_static! {
+	pub static ref PERTURBATION_SIZE: wgpu::BufferAddress = std::mem::size_of::<PerturbationUniform>() as wgpu::BufferAddress;
+	pub static ref ORBITS_SIZE: wgpu::BufferAddress = (MAX_REFERENCES * MAX_REFERENCE_LENGTH * std::mem::size_of::<[f32; 2]>()) as wgpu::BufferAddress;
+}
+
+/// The `Perturbation` uniform of `mandelbrot.frag`.
+#[repr(C)]
+#[derive(Clone, Copy, AsBytes, FromBytes)]
+pub struct PerturbationUniform {
+	/// Offset in pixels from the middle of the window, start in the orbits and length of each reference.
+	pub references: [[f32; 4]; MAX_REFERENCES],
+	pub reference_count: f32,
+	/// The zoom as `delta_mantissa * 2^delta_exponent`, deltas are scaled by the exponent
+	/// for as long as they are too small for a `float`.
+	pub delta_mantissa: f32,
+	pub delta_exponent: f32,
+	pub enabled: f32,
+	/// Series approximation for reference 0, `a` in xy and `b` in zw.
+	pub series_ab: [f32; 4],
+	/// `c` in xy, iterations skipped in z and the exponent of the coefficients in w.
+	pub series_c: [f32; 4],
+}
+
+impl PerturbationUniform {
+	pub fn enabled(&self) -> bool {
+		self.enabled > 0.5
+	}
+
+	pub fn references(&self) -> usize {
+		self.reference_count as usize
+	}
+
+	/// Iterations every pixel of reference 0 starts at.
+	pub fn skipped(&self) -> usize {
+		self.series_c[2] as usize
+	}
+}
+
+#[derive(Clone)]
+pub struct Perturbation {
+	pub uniform: PerturbationUniform,
+	/// The orbits of every reference one after another.
+	pub orbits: Vec<[f32; 2]>,
+}
+
+impl Perturbation {
+	pub fn disabled() -> Self {
+		Self {
+			uniform: PerturbationUniform {
+				references: [[0.0; 4]; MAX_REFERENCES],
+				reference_count: 0.0,
+				delta_mantissa: 0.0,
+				delta_exponent: 0.0,
+				enabled: 0.0,
+				series_ab: [0.0; 4],
+				series_c: [0.0; 4],
+			},
+			orbits: vec![],
+		}
+	}
+
+	pub fn enabled(&self) -> bool {
+		self.uniform.enabled()
+	}
+
+	pub fn references(&self) -> usize {
+		self.uniform.references()
+	}
+
+	/// The uniform of these references, computed for `from`, for drawing `to` until it has references of its own.
+	/// They stay at their points of the plane while the view moves and zooms around them, only the series
+	/// approximation is left out, as it only holds near where it was computed for.
+	/// `None` where the orbits themselves would be different.
+	pub fn rebased(&self, from: &FractalParams, to: &FractalParams) -> Option<PerturbationUniform> {
+		if !self.enabled() || !is_needed(to) || from.julia != to.julia || from.formula != to.formula
+			|| (to.julia.is_julia && from.generator != to.generator) {
+			return None;
+		}
+		let mut uniform = self.uniform;
+		if from.center == to.center && from.zoom == to.zoom {
+			return Some(uniform);
+		}
+
+		let zoom = to.zoom.zoom;
+		let (mantissa, exponent) = frexp(zoom);
+		uniform.delta_mantissa = mantissa as f32;
+		uniform.delta_exponent = exponent as f32;
+		for reference in uniform.references.iter_mut().take(self.references()) {
+			let point = from.center.offset([f64::from(reference[0]), f64::from(reference[1])], from.zoom.zoom);
+			reference[0] = ((&point.re - &to.center.re).to_f64() / zoom) as f32;
+			reference[1] = ((&point.im - &to.center.im).to_f64() / zoom) as f32;
+		}
+		uniform.series_c[2] = 0.0;
+		Some(uniform)
+	}
+
+	fn add_reference(&mut self, params: &FractalParams, offset: [f32; 2]) {
+		let orbit = reference_orbit(params, offset);
+		log::info!("Reference {} at {:?} escapes after {} iterations", self.references(), offset, orbit.len());
+
+		let index = self.references();
+		self.uniform.references[index] = [offset[0], offset[1], self.orbits.len() as f32, orbit.len() as f32];
+		self.uniform.reference_count += 1.0;
+		self.orbits.extend(orbit);
+	}
+}
+
+/// Whether `params` are zoomed in deep enough to need references, and of a formula that has them.
+pub fn is_needed(params: &FractalParams) -> bool {
+	// The other formulas aren't analytic or need their own delta iteration,
+	// they stay on double-single.
+	params.zoom.zoom < PERTURBATION_ZOOM && params.formula.family == Family::Mandelbrot
+}
+
+/// References for `params` drawn into the `viewport` (left, top, right, bottom in pixels)
+/// of a window of `size`. Disabled when `is_needed` says so.
+/// With `series` the pixels of the first reference skip as many iterations as
+/// `series_approximation` allows.
+///
+/// Takes up to `MAX_REFERENCES` orbits of up to `MAX_REFERENCE_LENGTH` iterations in `BigFloat`,
+/// each followed by `PROBES` x `PROBES` pixels iterated on the CPU to look for glitches.
+/// The windowed views run it on a thread of their own, see `crate::views::perturber`.
+pub fn compute(params: &FractalParams, size: [f32; 2], viewport: [f32; 4], series: bool) -> Perturbation {
+	if !is_needed(params) {
+		return Perturbation::disabled();
+	}
+
+	let mut uniforms = Uniforms::new(params, size);
+	let (mantissa, exponent) = frexp(params.zoom.zoom);
+	uniforms.perturbation.uniform.delta_mantissa = mantissa as f32;
+	uniforms.perturbation.uniform.delta_exponent = exponent as f32;
+	uniforms.perturbation.uniform.enabled = 1.0;
+
+	// The first reference goes in the middle of the viewport, every next one
+	// into a spot that all the previous ones are glitched for.
+	let mut reference = [
+		(viewport[0] + viewport[2] - size[0]) / 2.0,
+		(viewport[1] + viewport[3] - size[1]) / 2.0,
+	];
+	uniforms.perturbation.add_reference(params, reference);
+	if series {
+		series_approximation(&mut uniforms, params.julia.is_julia, viewport);
+	}
+	loop {
+		if uniforms.perturbation.references() == MAX_REFERENCES {
+			log::warn!("Glitches left after {} references", MAX_REFERENCES);
+			break;
+		}
+		match find_glitch(&uniforms, viewport) {
+			Some(glitch) => reference = [glitch[0] - size[0] / 2.0, glitch[1] - size[1] / 2.0],
+			None => break,
+		}
+		uniforms.perturbation.add_reference(params, reference);
+	}
+
+	uniforms.perturbation
+}
+
+/// Orbit of the pixel `offset` away from the middle of the window. It starts at the
+/// pixel's point itself and ends once it escapes, just like the shader iterates.
+fn reference_orbit(params: &FractalParams, offset: [f32; 2]) -> Vec<[f32; 2]> {
+	let c = params.center.offset([f64::from(offset[0]), f64::from(offset[1])], params.zoom.zoom);
+	let (add_re, add_im) = if params.julia.is_julia {
+		let bits = c.re.bits();
+		(
+			BigFloat::from_f64(params.generator.pos[0], bits),
+			BigFloat::from_f64(params.generator.pos[1], bits),
+		)
+	} else {
+		(c.re.clone(), c.im.clone())
+	};
+
+	let length = (params.iterations.iterations.ceil() as usize + 1).min(MAX_REFERENCE_LENGTH);
+	let mut orbit = Vec::with_capacity(length);
+	let (mut re, mut im) = (c.re, c.im);
+	while orbit.len() < length {
+		let z = [re.to_f64() as f32, im.to_f64() as f32];
+		orbit.push(z);
+		if shader::length(z) > shader::R {
+			break;
+		}
+
+		let re_im = &re * &im;
+		let next_re = &(&re.square() - &im.square()) + &add_re;
+		im = &(&re_im + &re_im) + &add_im;
+		re = next_re;
+	}
+
+	orbit
+}
+
+type Complex = [f64; 2];
+
+fn complex_mul(a: Complex, b: Complex) -> Complex {
+	[a[0] * b[0] - a[1] * b[1], a[0] * b[1] + a[1] * b[0]]
+}
+
+fn complex_length(z: Complex) -> f64 {
+	z[0].hypot(z[1])
+}
+
+/// Bivariate series approximation of reference 0. The delta of a pixel after `n` iterations is
+/// `A_n dz + B_n dz^2 + C_n dz^3` in its starting delta `dz` and its parameter delta `dc`, with
+/// `A_{n+1} = 2 Z_n A_n + dc/dz`, `B_{n+1} = 2 Z_n B_n + A_n^2` and `C_{n+1} = 2 Z_n C_n + 2 A_n B_n`.
+/// Here the orbit starts at `c` itself, so `dz` is `dc` for the Mandelbrot set and `dc` is 0
+/// for Julia sets, which leaves a single variable.
+///
+/// Skips for as long as the cubic term stays negligible at the corners of the `viewport`,
+/// then halves that until probes along its edges agree with plain perturbation.
+fn series_approximation(uniforms: &mut Uniforms, is_julia: bool, viewport: [f32; 4]) {
+	let p = uniforms.perturbation.uniform;
+	let reference = p.references[0];
+	let orbit = &uniforms.perturbation.orbits[..reference[3] as usize];
+	let middle = [uniforms.size[0] / 2.0 + reference[0], uniforms.size[1] / 2.0 + reference[1]];
+	// Farthest any pixel is from the reference, in the units of the scaled deltas.
+	let radius = f64::from(p.delta_mantissa) * f64::from(
+		(viewport[0] - middle[0]).abs().max((viewport[2] - middle[0]).abs())
+			.hypot((viewport[1] - middle[1]).abs().max((viewport[3] - middle[1]).abs()))
+	);
+	// `B` and `C` are kept multiplied by `2^delta_exponent` and `2^(2 delta_exponent)`
+	// so that all coefficients apply to the scaled deltas.
+	let scale = 2f64.powi(p.delta_exponent as i32);
+
+	// `series[n]` are the coefficients after `n` iterations.
+	let mut series: Vec<(Complex, Complex, Complex)> = vec![([1.0, 0.0], [0.0, 0.0], [0.0, 0.0])];
+	for z in orbit.iter().take(orbit.len().saturating_sub(2)) {
+		let (a, b, c) = series[series.len() - 1];
+		let z2 = [2.0 * f64::from(z[0]), 2.0 * f64::from(z[1])];
+		let za = complex_mul(z2, a);
+		let zb = complex_mul(z2, b);
+		let zc = complex_mul(z2, c);
+		let a2 = complex_mul(a, a);
+		let ab = complex_mul(a, b);
+		let next = (
+			if is_julia { za } else { [za[0] + 1.0, za[1]] },
+			[zb[0] + a2[0] * scale, zb[1] + a2[1] * scale],
+			[zc[0] + 2.0 * ab[0] * scale, zc[1] + 2.0 * ab[1] * scale],
+		);
+
+		let (a, _, c) = next;
+		if !(complex_length(c) * radius * radius <= SERIES_TOLERANCE * complex_length(a)) {
+			break;
+		}
+		series.push(next);
+	}
+
+	let probes = [
+		[viewport[0] + 0.5, viewport[1] + 0.5],
+		[viewport[2] - 0.5, viewport[1] + 0.5],
+		[viewport[0] + 0.5, viewport[3] - 0.5],
+		[viewport[2] - 0.5, viewport[3] - 0.5],
+		[(viewport[0] + viewport[2]) / 2.0, viewport[1] + 0.5],
+		[(viewport[0] + viewport[2]) / 2.0, viewport[3] - 0.5],
+		[viewport[0] + 0.5, (viewport[1] + viewport[3]) / 2.0],
+		[viewport[2] - 0.5, (viewport[1] + viewport[3]) / 2.0],
+	];
+	let expected: Vec<(shader::Vec4, bool)> = probes.iter()
+		.map(|probe| shader::iterations_perturbed(*probe, 0, uniforms, &mut shader::begin_orbit()))
+		.collect();
+
+	let mut skipped = series.len() - 1;
+	while skipped > 0 {
+		set_series(&mut uniforms.perturbation.uniform, skipped, series[skipped]);
+		let agrees = probes.iter().zip(&expected).all(|(probe, expected)| {
+			let (iterations, glitched) = shader::iterations_perturbed(*probe, 0, uniforms, &mut shader::begin_orbit());
+			glitched == expected.1 && (iterations[0] - expected.0[0]).abs() <= SERIES_PROBE_TOLERANCE
+		});
+		if agrees {
+			break;
+		}
+		skipped /= 2;
+	}
+	set_series(&mut uniforms.perturbation.uniform, skipped, series[skipped]);
+	log::info!("Series approximation skips {} iterations", skipped);
+}
+
+/// Uploads the coefficients with a shared exponent, so that `a` fits into a `float`.
+fn set_series(uniform: &mut PerturbationUniform, skipped: usize, (a, b, c): (Complex, Complex, Complex)) {
+	let shift = (complex_length(a).log2().floor() as i32).max(0).min(-uniform.delta_exponent as i32);
+	let scale = 2f64.powi(-shift);
+	uniform.series_ab = [
+		(a[0] * scale) as f32, (a[1] * scale) as f32,
+		(b[0] * scale) as f32, (b[1] * scale) as f32,
+	];
+	uniform.series_c = [
+		(c[0] * scale) as f32, (c[1] * scale) as f32,
+		skipped as f32, shift as f32,
+	];
+}
+
+/// A probe of a grid over the `viewport` that all references so far are glitched for.
+fn find_glitch(uniforms: &Uniforms, viewport: [f32; 4]) -> Option<[f32; 2]> {
+	let step = [
+		(viewport[2] - viewport[0]) / PROBES as f32,
+		(viewport[3] - viewport[1]) / PROBES as f32,
+	];
+	for j in 0..PROBES {
+		for i in 0..PROBES {
+			// At the middle of a pixel, like `gl_FragCoord`.
+			let probe = [
+				(viewport[0] + (i as f32 + 0.5) * step[0]).floor() + 0.5,
+				(viewport[1] + (j as f32 + 0.5) * step[1]).floor() + 0.5,
+			];
+			let (_, glitched) = shader::iterations_perturbation(probe, uniforms, &mut shader::begin_orbit());
+			if glitched {
+				return Some(probe);
+			}
+		}
+	}
+
+	None
+}
+
+/// Splits `value` into `mantissa * 2^exponent` with the mantissa in `[1, 2)`.
+fn frexp(value: f64) -> (f64, i32) {
+	let exponent = value.log2().floor() as i32;
+	// In two steps, `2^-exponent` alone overflows for the smallest zooms.
+	let mantissa = value * 2f64.powi(-exponent / 2) * 2f64.powi(-exponent + exponent / 2);
+
+	(mantissa, exponent)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::utils::Center;
+
+	/// The orbit `reference_orbit` should give, iterated in plain `f64` from `z` with `c` added.
+	fn f64_orbit(mut z: Complex, c: Complex, length: usize) -> Vec<Complex> {
+		let mut orbit = Vec::new();
+		while orbit.len() < length {
+			orbit.push(z);
+			if complex_length(z) > f64::from(shader::R) {
+				break;
+			}
+			let square = complex_mul(z, z);
+			z = [square[0] + c[0], square[1] + c[1]];
+		}
+		orbit
+	}
+
+	fn assert_close(orbit: &[[f32; 2]], expected: &[Complex]) {
+		assert_eq!(orbit.len(), expected.len());
+		for (z, e) in orbit.iter().zip(expected) {
+			let scale = complex_length(*e).max(1.0);
+			assert!((f64::from(z[0]) - e[0]).abs() < 1e-5 * scale && (f64::from(z[1]) - e[1]).abs() < 1e-5 * scale,
+				"{:?} isn't {:?}", z, e);
+		}
+	}
+
+	#[test]
+	fn reference_orbits_match_f64_at_shallow_zoom() {
+		let mut params = FractalParams::default();
+		params.iterations.iterations = 100.0;
+		// Inside of the cardioid, where the orbit runs to the end, and outside, where it escapes.
+		for &center in &[[-0.1, 0.2], [0.5, 0.5], [-0.75, 0.1]] {
+			params.center = Center::new(center);
+			assert_close(&reference_orbit(&params, [0.0, 0.0]), &f64_orbit(center, center, 101));
+
+			let offset = [12.0, -7.0];
+			let c = [center[0] + 12.0 * params.zoom.zoom, center[1] - 7.0 * params.zoom.zoom];
+			assert_close(&reference_orbit(&params, offset), &f64_orbit(c, c, 101));
+		}
+		params.center = Center::new([-0.1, 0.2]);
+		assert_eq!(reference_orbit(&params, [0.0, 0.0]).len(), 101);
+		params.center = Center::new([0.5, 0.5]);
+		assert!(reference_orbit(&params, [0.0, 0.0]).len() < 101);
+	}
+
+	#[test]
+	fn rebased_references_stay_at_their_points() {
+		let mut from = FractalParams::default();
+		from.center = Center::new([-0.743643887037158, 0.131825904205311]);
+		from.zoom.zoom = 1e-13;
+		from.iterations.iterations = 200.0;
+		let perturbation = compute(&from, [64.0, 48.0], [0.0, 0.0, 64.0, 48.0], true);
+		assert!(perturbation.enabled());
+		assert_eq!(perturbation.rebased(&from, &from).unwrap().references, perturbation.uniform.references);
+
+		// Moved 10 pixels to the right and zoomed in twice.
+		let mut to = from.clone();
+		to.center = from.center.offset([10.0, 0.0], from.zoom.zoom);
+		to.zoom.zoom = from.zoom.zoom / 2.0;
+		let uniform = perturbation.rebased(&from, &to).unwrap();
+		for (old, new) in perturbation.uniform.references.iter().zip(&uniform.references).take(perturbation.references()) {
+			assert!((new[0] - (old[0] - 10.0) * 2.0).abs() < 1e-3 && (new[1] - old[1] * 2.0).abs() < 1e-3, "{:?} {:?}", old, new);
+			assert_eq!([new[2], new[3]], [old[2], old[3]]);
+		}
+		assert_eq!(uniform.delta_exponent, perturbation.uniform.delta_exponent - 1.0);
+		assert_eq!(uniform.skipped(), 0);
+
+		to.julia.is_julia = true;
+		assert!(perturbation.rebased(&from, &to).is_none());
+	}
+
+	#[test]
+	fn julia_reference_orbits_add_the_generator() {
+		let mut params = FractalParams::default();
+		params.iterations.iterations = 50.0;
+		params.julia.is_julia = true;
+		params.generator.pos = [-0.8, 0.156];
+		params.center = Center::new([0.1, -0.2]);
+		assert_close(&reference_orbit(&params, [0.0, 0.0]), &f64_orbit([0.1, -0.2], [-0.8, 0.156], 51));
+	}
+}
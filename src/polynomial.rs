@@ -0,0 +1,210 @@
+//! Polynomials of the Newton view, kept as their roots.
+//!
+//! Newton's method only needs `p(z) / p'(z)`, which for `p(z) = (z - r_1)...(z - r_n)`
+//! is `1 / (1 / (z - r_1) + ... + 1 / (z - r_n))`, see `newton.frag`.
+//! Polynomials given by their coefficients get their roots found first.
+
+use std::str::FromStr;
+
+use zerocopy::{AsBytes, FromBytes};
+
+/// Keep in sync with `MAX_ROOTS` in `newton.frag`.
+pub const MAX_ROOTS: usize = 8;
+
+/// Durand-Kerner stops once no root moves further than this.
+const ROOT_TOLERANCE: f64 = 1e-12;
+const MAX_ROOT_ITERATIONS: usize = 1000;
+
+type Complex = [f64; 2];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polynomial {
+	pub roots: Vec<Complex>,
+}
+
+/// `Polynomial` as the shader sees it, the roots in `xy` of each `vec4`.
+#[repr(C)]
+#[derive(Clone, Copy, AsBytes, FromBytes)]
+pub struct RootsUniform {
+	pub roots: [[f32; 4]; MAX_ROOTS],
+	pub count: f32,
+}
+
+impl Polynomial {
+	pub fn from_roots(roots: Vec<Complex>) -> Result<Self, String> {
+		if roots.is_empty() || roots.len() > MAX_ROOTS {
+			return Err(format!("expected 1 to {} roots, got {}", MAX_ROOTS, roots.len()));
+		}
+		Ok(Self { roots })
+	}
+
+	/// Takes the coefficients from the highest power down, `1;0;0;-1` being `z^3 - 1`.
+	pub fn from_coefficients(coefficients: &[Complex]) -> Result<Self, String> {
+		let coefficients: Vec<Complex> = coefficients.iter()
+			.cloned()
+			.skip_while(|c| *c == [0.0, 0.0])
+			.collect();
+		if coefficients.len() < 2 {
+			return Err("the polynomial needs to be of degree 1 at least".to_owned());
+		}
+		let leading = coefficients[0];
+		let monic: Vec<Complex> = coefficients.iter()
+			.map(|c| complex_div(*c, leading))
+			.collect();
+
+		Self::from_roots(durand_kerner(&monic))
+	}
+
+	pub fn uniform(&self) -> RootsUniform {
+		let mut roots = [[0.0; 4]; MAX_ROOTS];
+		for (uniform, root) in roots.iter_mut().zip(&self.roots) {
+			uniform[0] = root[0] as f32;
+			uniform[1] = root[1] as f32;
+		}
+		RootsUniform {
+			roots,
+			count: self.roots.len() as f32,
+		}
+	}
+}
+
+/// `z^3 - 1`, the classic.
+impl Default for Polynomial {
+	fn default() -> Self {
+		let third = 2.0 * std::f64::consts::PI / 3.0;
+		Self {
+			roots: (0..3)
+				.map(|k| [(third * f64::from(k)).cos(), (third * f64::from(k)).sin()])
+				.collect()
+		}
+	}
+}
+
+/// Parses `re,im;re,im;...`, which is how both roots and coefficients are written.
+pub fn parse_complex_list(s: &str) -> Result<Vec<Complex>, String> {
+	s.split(';')
+		.map(|number| {
+			let mut parts = number.split(',');
+			match (parts.next(), parts.next(), parts.next()) {
+				(Some(re), Some(im), None) => {
+					let re = re.trim().parse::<f64>().map_err(|e| format!("real part {:?}: {}", re, e))?;
+					let im = im.trim().parse::<f64>().map_err(|e| format!("imaginary part {:?}: {}", im, e))?;
+					Ok([re, im])
+				}
+				(Some(re), None, None) => re.trim().parse::<f64>()
+					.map(|re| [re, 0.0])
+					.map_err(|e| format!("{:?}: {}", re, e)),
+				_ => Err(format!("expected `re,im`, got {:?}", number))
+			}
+		})
+		.collect()
+}
+
+impl FromStr for Polynomial {
+	type Err = String;
+
+	/// Roots, see `parse_complex_list`.
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Self::from_roots(parse_complex_list(s)?)
+	}
+}
+
+fn complex_mul(a: Complex, b: Complex) -> Complex {
+	[a[0] * b[0] - a[1] * b[1], a[0] * b[1] + a[1] * b[0]]
+}
+
+fn complex_div(a: Complex, b: Complex) -> Complex {
+	let denominator = b[0] * b[0] + b[1] * b[1];
+	[
+		(a[0] * b[0] + a[1] * b[1]) / denominator,
+		(a[1] * b[0] - a[0] * b[1]) / denominator,
+	]
+}
+
+/// All roots of the monic polynomial with `coefficients` at once.
+fn durand_kerner(coefficients: &[Complex]) -> Vec<Complex> {
+	let degree = coefficients.len() - 1;
+	let evaluate = |z: Complex| coefficients.iter()
+		.fold([0.0, 0.0], |value, c| {
+			let value = complex_mul(value, z);
+			[value[0] + c[0], value[1] + c[1]]
+		});
+
+	// Powers of a number that is neither real nor a root of unity, the usual start.
+	let seed = [0.4, 0.9];
+	let mut roots: Vec<Complex> = Vec::with_capacity(degree);
+	let mut power = [1.0, 0.0];
+	for _ in 0..degree {
+		roots.push(power);
+		power = complex_mul(power, seed);
+	}
+
+	for _ in 0..MAX_ROOT_ITERATIONS {
+		let mut moved: f64 = 0.0;
+		for i in 0..degree {
+			let denominator = (0..degree)
+				.filter(|&j| j != i)
+				.fold([1.0, 0.0], |product, j| {
+					complex_mul(product, [roots[i][0] - roots[j][0], roots[i][1] - roots[j][1]])
+				});
+			let step = complex_div(evaluate(roots[i]), denominator);
+			roots[i] = [roots[i][0] - step[0], roots[i][1] - step[1]];
+			moved = moved.max(step[0].hypot(step[1]));
+		}
+		if moved < ROOT_TOLERANCE {
+			break;
+		}
+	}
+	roots
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Whether every root of `expected` is close to one of `roots`, in any order.
+	fn same_roots(roots: &[Complex], expected: &[Complex]) -> bool {
+		roots.len() == expected.len() && expected.iter().all(|e| {
+			roots.iter().any(|r| (r[0] - e[0]).hypot(r[1] - e[1]) < 1e-9)
+		})
+	}
+
+	#[test]
+	fn complex_lists() {
+		assert_eq!(parse_complex_list("1;0;0;-1").unwrap(), vec![[1.0, 0.0], [0.0, 0.0], [0.0, 0.0], [-1.0, 0.0]]);
+		assert_eq!(parse_complex_list("1,0; -1 , 0;0,1").unwrap(), vec![[1.0, 0.0], [-1.0, 0.0], [0.0, 1.0]]);
+		assert!(parse_complex_list("1,2,3").is_err());
+		assert!(parse_complex_list("1,x").is_err());
+		assert!(parse_complex_list("").is_err());
+	}
+
+	#[test]
+	fn cube_roots_of_unity() {
+		let polynomial = Polynomial::from_coefficients(&parse_complex_list("1;0;0;-1").unwrap()).unwrap();
+		assert!(same_roots(&polynomial.roots, &Polynomial::default().roots));
+	}
+
+	#[test]
+	fn complex_coefficients() {
+		// (z - i)(z + 2) = z^2 + (2 - i)z - 2i, leading zeros and a leading coefficient of 3 aside.
+		let coefficients = [[0.0, 0.0], [3.0, 0.0], [6.0, -3.0], [0.0, -6.0]];
+		let polynomial = Polynomial::from_coefficients(&coefficients).unwrap();
+		assert!(same_roots(&polynomial.roots, &[[0.0, 1.0], [-2.0, 0.0]]));
+	}
+
+	#[test]
+	fn durand_kerner_of_monic() {
+		assert!(same_roots(&durand_kerner(&[[1.0, 0.0], [0.0, 0.0], [-1.0, 0.0]]), &[[1.0, 0.0], [-1.0, 0.0]]));
+		assert!(same_roots(&durand_kerner(&[[1.0, 0.0], [-5.0, 0.0]]), &[[5.0, 0.0]]));
+	}
+
+	#[test]
+	fn degrees_out_of_range() {
+		assert!(Polynomial::from_coefficients(&[[0.0, 0.0], [5.0, 0.0]]).is_err());
+		let mut coefficients = vec![[1.0, 0.0]];
+		coefficients.extend(vec![[0.0, 0.0]; MAX_ROOTS]);
+		coefficients.push([-1.0, 0.0]);
+		assert!(Polynomial::from_coefficients(&coefficients).is_err());
+		assert!("".parse::<Polynomial>().is_err());
+	}
+}
@@ -0,0 +1,117 @@
+//! Orbit traps, shapes in the complex plane that the orbits of `mandelbrot.frag` are measured against.
+//!
+//! Every pixel keeps how close its orbit came to the trap, in sizes of the trap,
+//! which `ColoringMode::Trap` colors with the palette, see `colorize.frag`.
+
+use std::fmt;
+use std::str::FromStr;
+use serde::{Serialize, Deserialize};
+use zerocopy::{AsBytes, FromBytes};
+
+/// Keep the order in sync with the constants in `mandelbrot.frag` and `colorize.frag`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TrapShape {
+	/// Distance from the center.
+	Point,
+	/// Distance from the horizontal line through the center.
+	Line,
+	/// Distance from the nearer of the horizontal and the vertical line through the center.
+	Cross,
+	/// Distance from the circle around the center, with the size as its radius.
+	Circle,
+	/// Pickover stalks, `Cross` drawn over the smooth coloring where the orbit came within the size of it.
+	Stalks,
+}
+
+impl TrapShape {
+	pub fn next(self) -> Self {
+		match self {
+			TrapShape::Point => TrapShape::Line,
+			TrapShape::Line => TrapShape::Cross,
+			TrapShape::Cross => TrapShape::Circle,
+			TrapShape::Circle => TrapShape::Stalks,
+			TrapShape::Stalks => TrapShape::Point,
+		}
+	}
+}
+
+impl Default for TrapShape {
+	fn default() -> Self {
+		TrapShape::Point
+	}
+}
+
+impl FromStr for TrapShape {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"point" => Ok(TrapShape::Point),
+			"line" => Ok(TrapShape::Line),
+			"cross" => Ok(TrapShape::Cross),
+			"circle" => Ok(TrapShape::Circle),
+			"stalks" => Ok(TrapShape::Stalks),
+			_ => Err(format!("unknown trap {:?}, expected point, line, cross, circle or stalks", s))
+		}
+	}
+}
+
+impl fmt::Display for TrapShape {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			TrapShape::Point => write!(f, "Point trap"),
+			TrapShape::Line => write!(f, "Line trap"),
+			TrapShape::Cross => write!(f, "Cross trap"),
+			TrapShape::Circle => write!(f, "Circle trap"),
+			TrapShape::Stalks => write!(f, "Pickover stalks"),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trap {
+	pub shape: TrapShape,
+	/// Point of the complex plane the shape is placed at.
+	pub center: [f64; 2],
+	/// Distance that counts as 1, the radius of `TrapShape::Circle`.
+	pub size: f32,
+}
+
+/// `Trap` as the shader sees it.
+#[repr(C)]
+#[derive(Clone, Copy, AsBytes, FromBytes)]
+pub struct TrapUniform {
+	pub center: [f32; 2],
+	pub size: f32,
+	pub shape: i32,
+}
+
+impl Trap {
+	pub fn uniform(&self) -> TrapUniform {
+		TrapUniform {
+			center: [self.center[0] as f32, self.center[1] as f32],
+			size: self.size,
+			shape: self.shape as i32,
+		}
+	}
+
+	/// Multiplies the size by `factor`.
+	pub fn scale(self, factor: f32) -> Self {
+		Self {
+			size: self.size * factor,
+			..self
+		}
+	}
+}
+
+/// A point at the origin, which the orbits of the main cardioid circle around.
+impl Default for Trap {
+	fn default() -> Self {
+		Self {
+			shape: TrapShape::default(),
+			center: [0.0, 0.0],
+			size: 0.5,
+		}
+	}
+}
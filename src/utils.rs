@@ -1,19 +1,40 @@
 //! Utility constants, structs and functions global for both `views` and the main function.
 
+use std::fmt;
 use std::path::PathBuf;
+use std::str::FromStr;
 use std::time::Instant;
+use serde::{Serialize, Deserialize};
 use wgpu_glyph::{Section, Scale};
 use std::sync::{mpsc, Arc, Mutex};
 use zerocopy::{AsBytes, FromBytes};
 
+use crate::bigfloat::{BigFloat, MIN_BITS};
+use crate::polynomial::{Polynomial, RootsUniform};
+use crate::lyapunov::{Lyapunov, LyapunovUniform};
+use crate::palette::Palette;
+use crate::coloring::{Coloring, ColoringUniform, ColoringMode, InteriorMode};
+use crate::trap::{Trap, TrapUniform};
+use crate::average::{Average, AverageUniform};
+use crate::lighting::{Lighting, LightingUniform};
+use crate::custom_formula::CustomFormula;
+use crate::perturbation::MAX_REFERENCE_LENGTH;
+
 lazy_static! {
 	pub static ref ABSOLUTE_PATH: PathBuf = std::env::current_dir().unwrap();
 	pub static ref WINDOW_SIZE_SIZE: wgpu::BufferAddress = std::mem::size_of::<WindowSize>() as wgpu::BufferAddress;
-	pub static ref ZOOM_SIZE: wgpu::BufferAddress = std::mem::size_of::<Zoom>() as wgpu::BufferAddress;
-	pub static ref POSITION_SIZE: wgpu::BufferAddress = std::mem::size_of::<Position>() as wgpu::BufferAddress;
+	pub static ref ZOOM_SIZE: wgpu::BufferAddress = std::mem::size_of::<ZoomUniform>() as wgpu::BufferAddress;
+	pub static ref POSITION_SIZE: wgpu::BufferAddress = std::mem::size_of::<PositionUniform>() as wgpu::BufferAddress;
 	pub static ref ITERATIONS_SIZE: wgpu::BufferAddress = std::mem::size_of::<Iterations>() as wgpu::BufferAddress;
 	pub static ref VERTEX_SIZE: wgpu::BufferAddress = std::mem::size_of::<Vertex>() as wgpu::BufferAddress;
 	pub static ref JULIA_SIZE: wgpu::BufferAddress = std::mem::size_of::<Julia>() as wgpu::BufferAddress;
+	pub static ref FORMULA_SIZE: wgpu::BufferAddress = std::mem::size_of::<FormulaUniform>() as wgpu::BufferAddress;
+	pub static ref ROOTS_SIZE: wgpu::BufferAddress = std::mem::size_of::<RootsUniform>() as wgpu::BufferAddress;
+	pub static ref LYAPUNOV_SIZE: wgpu::BufferAddress = std::mem::size_of::<LyapunovUniform>() as wgpu::BufferAddress;
+	pub static ref COLORING_SIZE: wgpu::BufferAddress = std::mem::size_of::<ColoringUniform>() as wgpu::BufferAddress;
+	pub static ref TRAP_SIZE: wgpu::BufferAddress = std::mem::size_of::<TrapUniform>() as wgpu::BufferAddress;
+	pub static ref AVERAGE_SIZE: wgpu::BufferAddress = std::mem::size_of::<AverageUniform>() as wgpu::BufferAddress;
+	pub static ref LIGHTING_SIZE: wgpu::BufferAddress = std::mem::size_of::<LightingUniform>() as wgpu::BufferAddress;
 }
 
 pub type AtomicDevice = Arc<Mutex<wgpu::Device>>;
@@ -24,20 +45,153 @@ pub struct WindowSize {
 	pub size: [f32; 2]
 }
 
+/// Below this zoom the shader switches to double-single arithmetic.
+/// It does on every adapter, `wgpu::Extensions` has no `shaderFloat64` to use `double` with where there is one.
+/// Keep in sync with `DOUBLE_SINGLE_ZOOM` in `mandelbrot.frag`.
+pub const DOUBLE_SINGLE_ZOOM: f64 = 5e-6;
+
+/// Splits `value` into a double-single: a high `f32` with the low `f32` remainder.
+pub fn split(value: f64) -> [f32; 2] {
+	let hi = value as f32;
+	let lo = (value - f64::from(hi)) as f32;
+	[hi, lo]
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Zoom {
+	pub zoom: f64
+}
+
+impl Zoom {
+	pub fn uniform(self) -> ZoomUniform {
+		ZoomUniform {
+			zoom: split(self.zoom)
+		}
+	}
+}
+
+/// `Zoom` as the shader sees it, `[hi, lo]`.
 #[repr(C)]
 #[derive(Clone, Copy, AsBytes, FromBytes)]
-pub struct Zoom {
-	pub zoom: f32
+pub struct ZoomUniform {
+	pub zoom: [f32; 2]
 }
 
 #[repr(C)]
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Julia {
 	pub is_julia: bool
 }
 
+/// What gets iterated, the Julia flag decides whether `c` is the pixel or the generator.
+/// Keep the order in sync with the constants in `mandelbrot.frag`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Family {
+	/// `z^2 + c`
+	Mandelbrot,
+	/// `(|re z| + i|im z|)^2 + c`
+	BurningShip,
+	/// `conj(z)^2 + c`, also known as the Mandelbar.
+	Tricorn,
+	/// `z^power + c`
+	Multibrot,
+	/// Typed in by the user, see `FractalParams::custom_formula`.
+	Custom,
+}
+
+impl Family {
+	pub fn next(self) -> Self {
+		match self {
+			Family::Mandelbrot => Family::BurningShip,
+			Family::BurningShip => Family::Tricorn,
+			Family::Tricorn => Family::Multibrot,
+			// Custom formulas are only picked by typing one in.
+			Family::Multibrot | Family::Custom => Family::Mandelbrot,
+		}
+	}
+}
+
+impl Default for Family {
+	fn default() -> Self {
+		Family::Mandelbrot
+	}
+}
+
+impl FromStr for Family {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"mandelbrot" => Ok(Family::Mandelbrot),
+			"burning-ship" => Ok(Family::BurningShip),
+			"tricorn" => Ok(Family::Tricorn),
+			"multibrot" => Ok(Family::Multibrot),
+			_ => Err(format!("unknown formula {:?}, expected mandelbrot, burning-ship, tricorn or multibrot", s))
+		}
+	}
+}
+
+/// Exponent of the Multibrot unless given otherwise.
+pub const DEFAULT_POWER: f32 = 3.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Formula {
+	pub family: Family,
+	/// Only used by `Family::Multibrot`, doesn't have to be whole.
+	pub power: f32,
+}
+
+impl Formula {
+	pub fn uniform(self) -> FormulaUniform {
+		FormulaUniform {
+			family: self.family as i32,
+			power: self.power,
+		}
+	}
+
+	/// Changes the exponent by `delta`, switching to the Multibrot if it isn't already.
+	pub fn add_power(self, delta: f32) -> Self {
+		// Rounded so that repeated steps of 0.1 don't drift away from whole numbers.
+		let power = ((self.power + delta) * 10.0).round() / 10.0;
+		Self {
+			family: Family::Multibrot,
+			power: power.max(1.0),
+		}
+	}
+}
+
+impl Default for Formula {
+	fn default() -> Self {
+		Self {
+			family: Family::default(),
+			power: DEFAULT_POWER,
+		}
+	}
+}
+
+impl fmt::Display for Formula {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self.family {
+			Family::Mandelbrot => write!(f, "z^2 + c"),
+			Family::BurningShip => write!(f, "Burning Ship"),
+			Family::Tricorn => write!(f, "Tricorn"),
+			Family::Multibrot => write!(f, "z^{} + c", self.power),
+			Family::Custom => write!(f, "Custom formula"),
+		}
+	}
+}
+
+/// `Formula` as the shader sees it.
+#[repr(C)]
+#[derive(Clone, Copy, AsBytes, FromBytes)]
+pub struct FormulaUniform {
+	pub family: i32,
+	pub power: f32,
+}
+
 #[repr(C)]
-#[derive(Clone, Copy, Debug, AsBytes, FromBytes)]
+#[derive(Clone, Copy, Debug, PartialEq, AsBytes, FromBytes)]
 pub struct Iterations {
 	pub iterations: f32
 }
@@ -50,6 +204,25 @@ impl Default for Iterations {
 	}
 }
 
+/// The most iterations there can be, any more and the reference orbits of deep zooms would stop short.
+pub const MAX_ITERATIONS: f32 = (MAX_REFERENCE_LENGTH - 1) as f32;
+
+/// How fast `Iterations::auto` grows with the decades zoomed in.
+const AUTO_ITERATIONS_POWER: f32 = 1.5;
+
+impl Iterations {
+	/// Enough iterations for `zoom`: the default at the default zoom,
+	/// times the decades zoomed in plus one to the power of `AUTO_ITERATIONS_POWER`.
+	pub fn auto(zoom: Zoom) -> Self {
+		let decades = (Zoom::default().zoom / zoom.zoom).log10().max(0.0) as f32;
+		Self {
+			iterations: (Self::default().iterations * (1.0 + decades).powf(AUTO_ITERATIONS_POWER))
+				.round()
+				.min(MAX_ITERATIONS)
+		}
+	}
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, AsBytes, FromBytes)]
 pub struct Vertex {
@@ -64,27 +237,152 @@ impl Default for Zoom {
 	}
 }
 
-#[repr(C)]
-#[derive(Debug, Clone, Copy, Default, AsBytes, FromBytes)]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
 pub struct Position {
-	pub pos: [f32; 2]
+	pub pos: [f64; 2]
 }
 
-trait DigitsCountable {
-	fn count_digits(self) -> usize;
+impl Position {
+	pub fn uniform(self) -> PositionUniform {
+		let [x_hi, x_lo] = split(self.pos[0]);
+		let [y_hi, y_lo] = split(self.pos[1]);
+		PositionUniform {
+			hi: [x_hi, y_hi],
+			lo: [x_lo, y_lo],
+		}
+	}
+}
+
+/// `Position` as the shader sees it, a `vec4` of the high parts followed by the low parts.
+#[repr(C)]
+#[derive(Clone, Copy, AsBytes, FromBytes)]
+pub struct PositionUniform {
+	pub hi: [f32; 2],
+	pub lo: [f32; 2],
 }
 
-impl DigitsCountable for usize {
-	fn count_digits(self) -> usize {
-		let mut number = self;
-		let mut count: usize = 0;
+/// Arbitrary precision point in the complex plane, `Position` runs out of
+/// digits at a zoom of around `1e-16`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Center {
+	pub re: BigFloat,
+	pub im: BigFloat,
+}
 
-		while number > 0 {
-			number /= 10;
-			count += 1;
+impl Center {
+	pub fn new(center: [f64; 2]) -> Self {
+		Self {
+			re: BigFloat::from_f64(center[0], MIN_BITS),
+			im: BigFloat::from_f64(center[1], MIN_BITS),
 		}
+	}
+
+	/// Bits needed to tell apart neighbouring pixels at `zoom`, with some to spare.
+	pub fn precision(zoom: f64) -> u32 {
+		MIN_BITS.max((-zoom.log2()).ceil().max(0.0) as u32 + MIN_BITS)
+	}
+
+	/// Moved by `delta` pixels, keeping enough bits for `zoom`.
+	pub fn offset(&self, delta: [f64; 2], zoom: f64) -> Self {
+		let bits = Self::precision(zoom).max(self.re.bits());
+		Self {
+			re: self.re.with_bits(bits).add_f64(delta[0] * zoom),
+			im: self.im.with_bits(bits).add_f64(delta[1] * zoom),
+		}
+	}
+
+	/// Zoomed from `zoom` to `new_zoom` around the pixel `offset` from the middle, which keeps pointing at the same place.
+	pub fn zoomed(&self, offset: [f64; 2], zoom: f64, new_zoom: f64) -> Self {
+		// The pixel points at `center + zoom * offset`, before and after.
+		let keep = 1.0 - new_zoom / zoom;
+		self.offset([offset[0] * keep, offset[1] * keep], zoom)
+	}
+
+	/// The shader's `pos`, which it subtracts. See `transform` in `mandelbrot.frag`.
+	pub fn position(&self) -> Position {
+		Position {
+			pos: [-self.re.to_f64(), -self.im.to_f64()]
+		}
+	}
+}
+
+impl Default for Center {
+	fn default() -> Self {
+		Self::new([0.0, 0.0])
+	}
+}
 
-		count + 1
+/// Everything the fragment shader needs to draw a frame,
+/// independent of any window or input state.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FractalParams {
+	pub center: Center,
+	pub zoom: Zoom,
+	pub iterations: Iterations,
+	/// Whether the iterations follow the zoom, see `Iterations::auto`.
+	pub auto_iterations: bool,
+	pub julia: Julia,
+	pub formula: Formula,
+	/// Only used by `Family::Custom`.
+	pub custom_formula: Option<CustomFormula>,
+	pub generator: Position,
+	/// Only used by the Newton view.
+	pub polynomial: Polynomial,
+	/// Only used by the Lyapunov view.
+	pub lyapunov: Lyapunov,
+	/// Colors the escape time, the views that don't show one ignore it.
+	pub palette: Palette,
+	/// How the palette is applied, also ignored by the views without an escape time.
+	pub coloring: Coloring,
+	/// Measured along the orbits for `ColoringMode::Trap`.
+	pub trap: Trap,
+	/// Averaged along the orbits and blended into the coloring.
+	pub average: Average,
+	/// Shades the outside of the set like a lit surface.
+	pub lighting: Lighting,
+}
+
+impl Default for FractalParams {
+	fn default() -> Self {
+		Self {
+			center: Center::default(),
+			zoom: Zoom::default(),
+			iterations: Iterations::default(),
+			auto_iterations: false,
+			julia: Julia { is_julia: false },
+			formula: Formula::default(),
+			custom_formula: None,
+			generator: Position::default(),
+			polynomial: Polynomial::default(),
+			lyapunov: Lyapunov::default(),
+			palette: Palette::default(),
+			coloring: Coloring::default(),
+			trap: Trap::default(),
+			average: Average::default(),
+			lighting: Lighting::default(),
+		}
+	}
+}
+
+impl FractalParams {
+	/// Without the palette, the coloring, the mix of the average and the lighting, which only the colorize pass reads.
+	/// Two frames with the same of these have the same iterations, see `Colorizer::iterated`.
+	/// Of the coloring only whether the trap and the cycles are tracked is kept, and the trap only when it is.
+	pub fn without_coloring(&self) -> Self {
+		Self {
+			palette: Palette::default(),
+			coloring: Coloring {
+				mode: if self.coloring.traps() { ColoringMode::Trap } else { ColoringMode::default() },
+				interior: if self.coloring.detects_cycles() { InteriorMode::Period } else { InteriorMode::default() },
+			},
+			trap: if self.coloring.traps() { self.trap } else { Trap::default() },
+			average: Average {
+				mix: 0.0,
+				..self.average
+			},
+			lighting: Lighting::default(),
+			..self.clone()
+		}
 	}
 }
 
@@ -97,57 +395,74 @@ impl Changed {
 	}
 }
 
+/// What the HUD in the top left corner shows, see `fps_command`.
+pub struct Hud<'a> {
+	pub frame: &'a wgpu::SwapChainOutput<'a>,
+	/// When the frame before was drawn, moved on to now.
+	pub past: &'a mut Instant,
+	/// Iterations skipped by the series approximation, only there on deep zooms.
+	pub skipped: Option<usize>,
+	pub iterations: Iterations,
+	pub auto_iterations: bool,
+	/// How far the picture got, only there while it keeps getting better.
+	pub progress: Option<f32>,
+}
+
+/// Queues `text` in white with a black outline, `row` lines down from the top left corner of a window of `size`.
+fn queue_line(glyph_brush: &mut wgpu_glyph::GlyphBrush<()>, size: &winit::dpi::PhysicalSize, text: &str, row: usize) {
+	let section = Section {
+		text,
+		screen_position: (size.width as f32 / 100.0, size.height as f32 / 100.0 + 40.0 * row as f32),
+		scale: Scale::uniform(32.0),
+		color: [1.0f32, 1.0f32, 1.0f32, 1.0f32],
+		..Section::default()
+	};
+
+	let mut outline = section;
+	outline.color = [0.0f32, 0.0f32, 0.0f32, 1.0f32];
+	outline.scale = Scale::uniform(38.0);
+	outline.screen_position.0 -= 3.0f32;
+	outline.screen_position.1 -= 3.0f32;
+
+	glyph_brush.queue(outline);
+	glyph_brush.queue(section);
+}
+
 pub fn fps_command(
 	device: &AtomicDevice,
 	glyph_brush: &mut wgpu_glyph::GlyphBrush<()>,
 	size: &winit::dpi::PhysicalSize,
-	frame: &wgpu::SwapChainOutput,
-	past: &mut Instant
+	hud: Hud
 ) -> wgpu::CommandBuffer {
 	let mut encoder =
 		device.lock().unwrap().create_command_encoder(&wgpu::CommandEncoderDescriptor { todo: 0 });
 	let now = Instant::now();
-	let time = now - *past;
-	*past = now;
+	let time = now - *hud.past;
+	*hud.past = now;
 	let fps = (1.0 / time.as_secs_f32()).round() as usize;
 
-	let number_section = Section {
-		text: &format!("{}", fps),
-		screen_position: (size.width as f32 / 100.0, size.height as f32 / 100.0),
-		scale: Scale::uniform(32.0),
-		color: [1.0f32, 1.0f32, 1.0f32, 1.0f32],
-		..Section::default() // color, position, etc
-	};
-
-	let mut number_section_outline = number_section;
-	number_section_outline.color = [0.0f32, 0.0f32, 0.0f32, 1.0f32];
-	number_section_outline.scale = Scale::uniform(38.0);
-	number_section_outline.screen_position.0 -= 3.0f32;
-	number_section_outline.screen_position.1 -= 3.0f32;
-
-	let fps_section = Section {
-		text: "fps",
-		screen_position: (size.width as f32 / 100.0 + 16.0 * fps.count_digits() as f32, size.height as f32 / 100.0),
-		scale: Scale::uniform(32.0),
-		color: [1.0f32, 1.0f32, 1.0f32, 1.0f32],
-		..Section::default() // color, position, etc
+	let fps_text = match hud.skipped {
+		Some(skipped) => format!("{} fps  skipped {}", fps, skipped),
+		None => format!("{} fps", fps),
 	};
+	queue_line(glyph_brush, size, &fps_text, 0);
 
-	let mut fps_section_outline = fps_section;
-	fps_section_outline.color = [0.0f32, 0.0f32, 0.0f32, 1.0f32];
-	fps_section_outline.scale = Scale::uniform(38.0);
-	fps_section_outline.screen_position.0 -= 3.0f32;
-	fps_section_outline.screen_position.1 -= 3.0f32;
+	// Marked when they follow the zoom.
+	let iterations_text = format!(
+		"{} iterations{}",
+		hud.iterations.iterations.round(),
+		if hud.auto_iterations { " (auto)" } else { "" }
+	);
+	queue_line(glyph_brush, size, &iterations_text, 1);
 
-	glyph_brush.queue(fps_section_outline);
-	glyph_brush.queue(fps_section);
-	glyph_brush.queue(number_section_outline);
-	glyph_brush.queue(number_section);
+	if let Some(progress) = hud.progress {
+		queue_line(glyph_brush, size, &format!("refining {}%", (progress * 100.0).floor()), 2);
+	}
 
 	glyph_brush.draw_queued(
 		&mut device.lock().unwrap(),
 		&mut encoder,
-		&frame.view,
+		&hud.frame.view,
 		size.width.round() as u32,
 		size.height.round() as u32,
 	).expect("error drawing text");
@@ -167,8 +482,98 @@ pub fn create_watcher(path: &PathBuf) -> (RecommendedWatcher, mpsc::Receiver<not
 	(watcher, rx)
 }
 
+/// Watches the file of the current palette, so that it can be reloaded when it changes.
+pub struct PaletteWatcher {
+	watcher: RecommendedWatcher,
+	path: Option<PathBuf>,
+}
+
+impl PaletteWatcher {
+	/// Watches nothing until `watch` is called, the events of the file come from the receiver.
+	pub fn new() -> (Self, mpsc::Receiver<notify::DebouncedEvent>) {
+		let (tx, rx) = mpsc::channel();
+		let watcher = Self {
+			watcher: Watcher::new(tx, Duration::from_millis(500)).unwrap(),
+			path: None,
+		};
+		(watcher, rx)
+	}
+
+	/// Watches the file of `palette` instead of the one before, if it is another one.
+	pub fn watch(&mut self, palette: &Palette) {
+		let path = palette.path();
+		if path == self.path {
+			return;
+		}
+		if let Some(old) = self.path.take() {
+			if let Err(err) = self.watcher.unwatch(&old) {
+				log::error!("Error unwatching {:?}: {}", old, err);
+			}
+		}
+		if let Some(path) = &path {
+			if let Err(err) = self.watcher.watch(path, RecursiveMode::NonRecursive) {
+				log::error!("Error watching {:?}: {}", path, err);
+				return;
+			}
+			log::info!("Starting watcher on {:?}", path);
+		}
+		self.path = path;
+	}
+}
+
 #[derive(PartialEq)]
 pub enum CurrentView {
 	Single,
-	Double
+	Double,
+	Newton,
+	Buddhabrot,
+	Lyapunov
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Where the pixel `offset` from the middle points at.
+	fn point(center: &Center, zoom: f64, offset: [f64; 2]) -> [f64; 2] {
+		let position = center.offset(offset, zoom).position().pos;
+		[-position[0], -position[1]]
+	}
+
+	#[test]
+	fn zooming_keeps_the_point_under_the_cursor() {
+		let center = Center::new([-0.75, 0.1]);
+		let cursor = [123.0, -45.0];
+		for &(zoom, new_zoom) in &[(0.01, 0.009), (0.009, 0.01), (1e-10, 0.8e-10), (4.0, 40.0)] {
+			let zoomed = center.zoomed(cursor, zoom, new_zoom);
+			let (before, after) = (point(&center, zoom, cursor), point(&zoomed, new_zoom, cursor));
+			let tolerance = zoom * 1e-6;
+			assert!((before[0] - after[0]).abs() < tolerance && (before[1] - after[1]).abs() < tolerance,
+				"{:?} moved to {:?} zooming from {} to {}", before, after, zoom, new_zoom);
+		}
+	}
+
+	#[test]
+	fn zooming_around_the_middle_keeps_the_center() {
+		let center = Center::new([0.3, -0.2]);
+		assert_eq!(center.zoomed([0.0, 0.0], 0.01, 0.005).position(), center.position());
+	}
+
+	#[test]
+	fn auto_iterations_grow_with_the_zoom_up_to_the_maximum() {
+		assert_eq!(Iterations::auto(Zoom::default()).iterations, Iterations::default().iterations);
+		// Zoomed out there is nothing to gain.
+		assert_eq!(Iterations::auto(Zoom { zoom: 1.0 }).iterations, Iterations::default().iterations);
+
+		let mut previous = 0.0;
+		let mut zoom = Zoom::default().zoom * 10.0;
+		while zoom > 1e-300 {
+			let iterations = Iterations::auto(Zoom { zoom }).iterations;
+			assert!(iterations >= previous, "{} iterations at zoom {}, {} before", iterations, zoom, previous);
+			assert!(iterations <= MAX_ITERATIONS);
+			previous = iterations;
+			zoom /= 3.0;
+		}
+		assert_eq!(previous, MAX_ITERATIONS);
+	}
 }
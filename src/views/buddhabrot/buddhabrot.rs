@@ -0,0 +1,467 @@
+use crate::views::prelude::*;
+use crate::views::utils::{new, copy_to_buffer};
+use crate::cpu::buddhabrot::{self, BuddhabrotUniform, WORKGROUP_SIZE, SAMPLES_PER_INVOCATION};
+use wgpu::{Device, TextureView, CommandBuffer};
+use winit::event::{MouseButton, ElementState, ModifiersState};
+use winit::dpi::LogicalSize;
+
+/// Workgroups dispatched every frame.
+const WORKGROUPS: u32 = 256;
+
+/// After this many samples the picture doesn't get any better, so frames stop being drawn.
+const MAX_SAMPLES: u64 = 1 << 28;
+
+/// Space for `max_count` in front of the counts, see `buddhabrot.comp`.
+const HISTOGRAM_HEADER: wgpu::BufferAddress = 4 * 4;
+
+/// Buddhabrot, or with `set_nebulabrot` the Nebulabrot, refined frame by frame while the view stays put.
+pub struct BuddhabrotViewManager {
+	view: BuddhabrotView
+}
+
+impl BuddhabrotViewManager {
+	pub fn set_nebulabrot(&mut self, state: bool) {
+		log::info!("Setting nebulabrot to: {:?}", state);
+		self.view.nebulabrot = state;
+	}
+
+	pub fn is_nebulabrot(&self) -> bool {
+		self.view.nebulabrot
+	}
+}
+
+impl FractalViewManager for BuddhabrotViewManager {
+	fn new(device: &Device, size: LogicalSize) -> Self {
+		Self {
+			view: BuddhabrotView::new(device, size)
+		}
+	}
+
+	fn render(&mut self, device: &Arc<Mutex<Device>>, target: &TextureView) -> Vec<CommandBuffer> {
+		vec![self.view.render(device, target)]
+	}
+
+	fn resized(&mut self, device: &Arc<Mutex<Device>>, window_size: &WindowSize) -> Vec<CommandBuffer> {
+		vec![self.view.resized(device, window_size)]
+	}
+
+	fn mouse_input(&mut self, device: &Arc<Mutex<Device>>, button: MouseButton, state: ElementState, modifiers: ModifiersState) -> Option<Vec<CommandBuffer>> {
+		self.view.mouse_input(device, button, state, modifiers).map(|buf| vec![buf])
+	}
+
+	fn iterations(&mut self, device: &Arc<Mutex<Device>>, factor: f32) -> Vec<CommandBuffer> {
+		vec![self.view.iterations(device, factor)]
+	}
+
+	fn set_julia(&mut self, _device: &Arc<Mutex<Device>>, _state: bool) -> Option<Vec<CommandBuffer>> {
+		None
+	}
+
+	/// Only `z^2 + c` is sampled, the formula is kept for when switching back.
+	fn set_formula(&mut self, device: &Arc<Mutex<Device>>, formula: Formula) -> Vec<CommandBuffer> {
+		vec![self.view.set_formula(device, formula)]
+	}
+
+	fn set_custom_formula(&mut self, device: &Arc<Mutex<Device>>, custom_formula: Option<CustomFormula>) -> Result<(), String> {
+		self.view.set_custom_formula(device, custom_formula)
+	}
+
+	fn set_palette(&mut self, device: &Arc<Mutex<Device>>, palette: Palette) -> Vec<CommandBuffer> {
+		vec![self.view.set_palette(device, palette)]
+	}
+
+	fn set_coloring(&mut self, device: &Arc<Mutex<Device>>, coloring: Coloring) -> Vec<CommandBuffer> {
+		vec![self.view.set_coloring(device, coloring)]
+	}
+
+	fn set_trap(&mut self, device: &Arc<Mutex<Device>>, trap: Trap) -> Vec<CommandBuffer> {
+		vec![self.view.set_trap(device, trap)]
+	}
+
+	fn set_average(&mut self, device: &Arc<Mutex<Device>>, average: Average) -> Vec<CommandBuffer> {
+		vec![self.view.set_average(device, average)]
+	}
+
+	fn set_lighting(&mut self, device: &Arc<Mutex<Device>>, lighting: Lighting) -> Vec<CommandBuffer> {
+		vec![self.view.set_lighting(device, lighting)]
+	}
+
+	fn zoom(&mut self, device: &Arc<Mutex<Device>>, y_delta: f32) -> Vec<CommandBuffer> {
+		vec![self.view.zoom(device, y_delta)]
+	}
+
+	fn params(&mut self) -> FractalParams {
+		self.view.params()
+	}
+
+	fn set_params(&mut self, device: &Arc<Mutex<Device>>, params: &FractalParams) -> Vec<CommandBuffer> {
+		vec![self.view.set_params(device, params)]
+	}
+
+	fn new_position(&mut self, device: &Arc<Mutex<Device>>, x: f32, y: f32, active: bool) -> Option<Vec<CommandBuffer>> {
+		self.view.new_position(device, x, y, active).map(|buf| vec![buf])
+	}
+
+	fn undo(&mut self, device: &Arc<Mutex<Device>>) -> Option<Vec<CommandBuffer>> {
+		self.view.undo(device).map(|buf| vec![buf])
+	}
+
+	fn redo(&mut self, device: &Arc<Mutex<Device>>) -> Option<Vec<CommandBuffer>> {
+		self.view.redo(device).map(|buf| vec![buf])
+	}
+
+	fn set_series_approximation(&mut self, _state: bool) {}
+
+	fn set_auto_iterations(&mut self, state: bool) {
+		self.view.set_auto_iterations(state)
+	}
+
+	fn skipped_iterations(&mut self) -> Option<usize> {
+		None
+	}
+
+	fn is_refining(&mut self) -> bool {
+		self.view.samples < MAX_SAMPLES
+	}
+
+	fn progress(&mut self) -> Option<f32> {
+		if self.is_refining() {
+			Some(self.view.samples as f32 / MAX_SAMPLES as f32)
+		} else {
+			None
+		}
+	}
+
+	fn create_render_pipeline(&mut self, device: &Device) {
+		self.view.create_render_pipeline(device)
+	}
+
+	fn reload_fs(&mut self, device: &Arc<Mutex<Device>>) -> Result<(), String> {
+		self.view.reload_fs(device)
+	}
+}
+
+struct BuddhabrotView {
+	data: FractalViewData,
+	nebulabrot: bool,
+
+	uniform: wgpu::Buffer,
+	histogram: wgpu::Buffer,
+	/// Window size the histogram has a count for every pixel of.
+	histogram_size: [f32; 2],
+	bind_group_layout: wgpu::BindGroupLayout,
+	bind_group: wgpu::BindGroup,
+	pipeline_layout: wgpu::PipelineLayout,
+	sample_pipeline: wgpu::ComputePipeline,
+	clear_pipeline: wgpu::ComputePipeline,
+	tone_map_module: wgpu::ShaderModule,
+	tone_map_pipeline: wgpu::RenderPipeline,
+
+	/// What the histogram holds the orbits of, it starts over when any of it changes.
+	accumulated: Option<(FractalParams, [f32; 2], bool)>,
+	samples: u64,
+	frame: u32,
+}
+
+impl BuddhabrotView {
+	fn histogram_counts(size: [f32; 2]) -> wgpu::BufferAddress {
+		size[0] as wgpu::BufferAddress * size[1] as wgpu::BufferAddress * 3
+	}
+
+	fn create_histogram(device: &wgpu::Device, size: [f32; 2]) -> wgpu::Buffer {
+		device.create_buffer(&wgpu::BufferDescriptor {
+			size: HISTOGRAM_HEADER + Self::histogram_counts(size) * 4,
+			usage: wgpu::BufferUsage::STORAGE
+		})
+	}
+
+	fn create_bind_group(
+		device: &wgpu::Device,
+		layout: &wgpu::BindGroupLayout,
+		data: &FractalViewData,
+		uniform: &wgpu::Buffer,
+		histogram: &wgpu::Buffer
+	) -> wgpu::BindGroup {
+		let histogram_size = HISTOGRAM_HEADER + Self::histogram_counts(data.window_size.size) * 4;
+		device.create_bind_group(&wgpu::BindGroupDescriptor {
+			layout,
+			bindings: &[
+				wgpu::Binding {
+					binding: 0,
+					resource: wgpu::BindingResource::Buffer {
+						buffer: &data.bufs.window_size,
+						range: 0..*WINDOW_SIZE_SIZE
+					}
+				},
+				wgpu::Binding {
+					binding: 1,
+					resource: wgpu::BindingResource::Buffer {
+						buffer: &data.bufs.zoom,
+						range: 0..*ZOOM_SIZE
+					}
+				},
+				wgpu::Binding {
+					binding: 2,
+					resource: wgpu::BindingResource::Buffer {
+						buffer: &data.bufs.position,
+						range: 0..*POSITION_SIZE
+					}
+				},
+				wgpu::Binding {
+					binding: 3,
+					resource: wgpu::BindingResource::Buffer {
+						buffer: uniform,
+						range: 0..std::mem::size_of::<BuddhabrotUniform>() as wgpu::BufferAddress
+					}
+				},
+				wgpu::Binding {
+					binding: 4,
+					resource: wgpu::BindingResource::Buffer {
+						buffer: histogram,
+						range: 0..histogram_size
+					}
+				},
+			],
+		})
+	}
+
+	fn create_compute_pipeline(device: &wgpu::Device, layout: &wgpu::PipelineLayout, shader: &[u32]) -> wgpu::ComputePipeline {
+		let module = device.create_shader_module(shader);
+		device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+			layout,
+			compute_stage: wgpu::ProgrammableStageDescriptor {
+				module: &module,
+				entry_point: "main",
+			},
+		})
+	}
+
+	fn create_tone_map_pipeline(
+		device: &wgpu::Device,
+		layout: &wgpu::PipelineLayout,
+		vs_module: &wgpu::ShaderModule,
+		fs_module: &wgpu::ShaderModule
+	) -> wgpu::RenderPipeline {
+		log::info!("Creating Buddhabrot render pipeline");
+		device.create_render_pipeline(
+			&wgpu::RenderPipelineDescriptor {
+				layout,
+				vertex_stage: wgpu::ProgrammableStageDescriptor {
+					module: vs_module,
+					entry_point: "main",
+				},
+				fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+					module: fs_module,
+					entry_point: "main",
+				}),
+				rasterization_state: Some(wgpu::RasterizationStateDescriptor {
+					front_face: wgpu::FrontFace::Ccw,
+					cull_mode: wgpu::CullMode::None,
+					depth_bias: 0,
+					depth_bias_slope_scale: 0.0,
+					depth_bias_clamp: 0.0,
+				}),
+				primitive_topology: wgpu::PrimitiveTopology::TriangleStrip,
+				color_states: &[wgpu::ColorStateDescriptor {
+					format: wgpu::TextureFormat::Bgra8UnormSrgb,
+					color_blend: wgpu::BlendDescriptor::REPLACE,
+					alpha_blend: wgpu::BlendDescriptor::REPLACE,
+					write_mask: wgpu::ColorWrite::ALL,
+				}],
+				depth_stencil_state: None,
+				index_format: wgpu::IndexFormat::Uint32,
+				vertex_buffers: &[wgpu::VertexBufferDescriptor {
+					stride: *VERTEX_SIZE,
+					step_mode: wgpu::InputStepMode::Vertex,
+					attributes: &[wgpu::VertexAttributeDescriptor {
+						format: wgpu::VertexFormat::Float2,
+						offset: 0,
+						shader_location: 0,
+					}],
+				}],
+				sample_count: 1,
+				sample_mask: !0,
+				alpha_to_coverage_enabled: false,
+			}
+		)
+	}
+
+	/// Empties the histogram if it isn't of what is to be drawn now.
+	fn restart_if_moved(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
+		let size = self.data.window_size.size;
+		if self.histogram_size != size {
+			log::info!("Resizing the Buddhabrot histogram to {:?}", size);
+			self.histogram = Self::create_histogram(device, size);
+			self.histogram_size = size;
+			self.bind_group = Self::create_bind_group(device, &self.bind_group_layout, &self.data, &self.uniform, &self.histogram);
+			self.accumulated = None;
+		}
+
+		let state = (self.params(), size, self.nebulabrot);
+		if self.accumulated.as_ref() == Some(&state) {
+			return;
+		}
+		log::info!("Restarting the Buddhabrot after {} samples", self.samples);
+		let counts = HISTOGRAM_HEADER / 4 + Self::histogram_counts(size);
+		let workgroups = (counts + wgpu::BufferAddress::from(WORKGROUP_SIZE) - 1) / wgpu::BufferAddress::from(WORKGROUP_SIZE);
+		{
+			let mut cpass = encoder.begin_compute_pass();
+			cpass.set_pipeline(&self.clear_pipeline);
+			cpass.set_bind_group(0, &self.bind_group, &[]);
+			cpass.dispatch(workgroups as u32, 1, 1);
+		}
+		self.samples = 0;
+		self.accumulated = Some(state);
+	}
+}
+
+impl FractalViewable for BuddhabrotView {
+	fn new(device: &wgpu::Device, size: dpi::LogicalSize) -> Self {
+		let data
+			= new(device, size, false, (*WHOLE_VERTICES).clone());
+
+		let uniform = create_buffer(device, BuddhabrotUniform {
+			limits: [0.0; 4],
+			seed: 0.0,
+			samples: 0.0,
+		});
+		let histogram_size = data.window_size.size;
+		let histogram = Self::create_histogram(device, histogram_size);
+
+		let visibility = wgpu::ShaderStage::COMPUTE | wgpu::ShaderStage::FRAGMENT;
+		let uniform_binding = |binding| wgpu::BindGroupLayoutBinding {
+			binding,
+			visibility,
+			ty: wgpu::BindingType::UniformBuffer {
+				dynamic: false
+			}
+		};
+		let bind_group_layout = device.create_bind_group_layout(
+			&wgpu::BindGroupLayoutDescriptor {
+				bindings: &[
+					uniform_binding(0),
+					uniform_binding(1),
+					uniform_binding(2),
+					uniform_binding(3),
+					wgpu::BindGroupLayoutBinding {
+						binding: 4,
+						visibility,
+						ty: wgpu::BindingType::StorageBuffer {
+							dynamic: false,
+							readonly: false
+						}
+					},
+				]
+			}
+		);
+		let bind_group = Self::create_bind_group(device, &bind_group_layout, &data, &uniform, &histogram);
+		let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			bind_group_layouts: &[&bind_group_layout],
+		});
+
+		let sample_pipeline = Self::create_compute_pipeline(device, &pipeline_layout, &*BUDDHABROT_COMP_SHADER);
+		let clear_pipeline = Self::create_compute_pipeline(device, &pipeline_layout, &*BUDDHABROT_CLEAR_SHADER);
+		let tone_map_module = device.create_shader_module(&*BUDDHABROT_FRAG_SHADER);
+		let tone_map_pipeline = Self::create_tone_map_pipeline(device, &pipeline_layout, &data.vs_module, &tone_map_module);
+
+		Self {
+			data,
+			nebulabrot: false,
+			uniform,
+			histogram,
+			histogram_size,
+			bind_group_layout,
+			bind_group,
+			pipeline_layout,
+			sample_pipeline,
+			clear_pipeline,
+			tone_map_module,
+			tone_map_pipeline,
+			accumulated: None,
+			samples: 0,
+			frame: 0,
+		}
+	}
+
+	fn data(&mut self) -> &mut FractalViewData {
+		&mut self.data
+	}
+
+	/// Adds another batch of samples to the histogram, unless there are enough already,
+	/// and draws it tone mapped.
+	fn render(
+		&mut self,
+		device: &AtomicDevice,
+		target: &wgpu::TextureView,
+	) -> wgpu::CommandBuffer {
+		let device = device.lock().unwrap();
+		let mut encoder =
+			device.create_command_encoder(&wgpu::CommandEncoderDescriptor { todo: 0 });
+		self.update_iterations(&device, &mut encoder);
+		self.restart_if_moved(&device, &mut encoder);
+
+		if self.samples < MAX_SAMPLES {
+			let params = self.params();
+			let uniform = BuddhabrotUniform {
+				limits: buddhabrot::limits(&params, self.nebulabrot),
+				seed: self.frame as f32,
+				samples: SAMPLES_PER_INVOCATION as f32,
+			};
+			copy_to_buffer(&device, &mut encoder, uniform, &self.uniform);
+			{
+				let mut cpass = encoder.begin_compute_pass();
+				cpass.set_pipeline(&self.sample_pipeline);
+				cpass.set_bind_group(0, &self.bind_group, &[]);
+				cpass.dispatch(WORKGROUPS, 1, 1);
+			}
+			self.samples += u64::from(WORKGROUPS * WORKGROUP_SIZE * SAMPLES_PER_INVOCATION);
+			// Wraps around long before `seed` stops being exact in a float.
+			self.frame = (self.frame + 1) % (1 << 24);
+			if self.samples >= MAX_SAMPLES {
+				log::info!("Buddhabrot done after {} samples", self.samples);
+			}
+		}
+
+		{
+			let mut rpass = encoder.begin_render_pass(
+				&wgpu::RenderPassDescriptor {
+					color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+						attachment: target,
+						resolve_target: None,
+						load_op: wgpu::LoadOp::Load,
+						store_op: wgpu::StoreOp::Store,
+						clear_color: wgpu::Color::BLACK
+					}],
+					depth_stencil_attachment: None,
+				}
+			);
+			rpass.set_pipeline(&self.tone_map_pipeline);
+			rpass.set_bind_group(0, &self.bind_group, &[]);
+			rpass.set_vertex_buffers(0, &[(&self.data.bufs.vertex, 0)]);
+			rpass.draw(0..4, 0..1);
+		}
+		self.data.selection.render(&mut encoder, target);
+
+		encoder.finish()
+	}
+
+	fn frag_shader_path(&self) -> &'static Path {
+		&*BUDDHABROT_FRAG_SHADER_PATH
+	}
+
+	fn is_perturbable(&self) -> bool {
+		false
+	}
+
+	fn create_render_pipeline(&mut self, device: &wgpu::Device) {
+		self.tone_map_pipeline = Self::create_tone_map_pipeline(device, &self.pipeline_layout, &self.data.vs_module, &self.tone_map_module);
+	}
+
+	fn reload_fs(&mut self, device: &AtomicDevice) -> Result<(), String> {
+		let fs = Self::load_fs(self.frag_shader_path(), None)?;
+		log::info!("Setting Buddhabrot fs");
+		self.tone_map_module = device.lock().unwrap().create_shader_module(&fs);
+		self.create_render_pipeline(&device.lock().unwrap());
+		Ok(())
+	}
+}
@@ -0,0 +1,4 @@
+//! Home of the Buddhabrot view, which unlike the others accumulates over many frames.
+
+mod buddhabrot;
+pub use self::buddhabrot::BuddhabrotViewManager;
@@ -0,0 +1,415 @@
+//! Second pass of the views drawn by `mandelbrot.frag`, which leaves the iteration counts
+//! and what it measured along the orbits in float textures for `colorize.frag` to color, see `crate::coloring`.
+//!
+//! With `ColoringMode::Histogram` two compute passes count the pixels by their
+//! iterations in between, `histogram.comp` and `histogram_cdf.comp`.
+
+use super::prelude::*;
+use super::utils::{create_render_pipeline, ITERATIONS_SIZE, COLORING_SIZE, TRAP_SIZE, AVERAGE_SIZE, LIGHTING_SIZE};
+use super::progress::{Progress, PROGRESS_SIZE};
+use crate::coloring::{ColoringMode, HISTOGRAM_BINS};
+use crate::utils::FractalParams;
+
+/// What `mandelbrot.frag` draws into: the smooth iteration count, the final `z` and the distance estimate,
+/// see `escaped` there.
+pub const ITERATION_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba32Float;
+
+/// What `mandelbrot.frag` measures along the orbits, see `visit` there.
+pub const ORBIT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba32Float;
+
+/// The direction of the surface that `crate::lighting` lights, see `escaped` in `mandelbrot.frag`.
+pub const NORMAL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg32Float;
+
+/// The render targets of `mandelbrot.frag`, in the order of its outputs.
+pub const ITERATION_FORMATS: [wgpu::TextureFormat; 3] = [ITERATION_FORMAT, ORBIT_FORMAT, NORMAL_FORMAT];
+
+/// Of `histogram.comp`, in both directions.
+const WORKGROUP_SIZE: u32 = 8;
+
+/// The counts and the cumulative fractions of `colorize.frag`'s `Histogram`.
+const HISTOGRAM_SIZE: wgpu::BufferAddress = HISTOGRAM_BINS as wgpu::BufferAddress * 4 * 2;
+
+pub struct Colorizer {
+	/// Window size the iteration texture is of.
+	size: [f32; 2],
+	pub iteration_texture: wgpu::Texture,
+	/// Render target of `mandelbrot.frag`.
+	pub iteration_view: wgpu::TextureView,
+	pub orbit_texture: wgpu::Texture,
+	/// The second render target of `mandelbrot.frag`.
+	pub orbit_view: wgpu::TextureView,
+	pub normal_texture: wgpu::Texture,
+	/// The third render target of `mandelbrot.frag`.
+	pub normal_view: wgpu::TextureView,
+	/// What the iteration texture was last drawn for, along with the viewport.
+	/// Until that changes only the colorize pass runs, `None` draws it again.
+	pub iterated: Option<(FractalParams, [f32; 4])>,
+	/// How far drawing the iteration texture for `iterated` got.
+	pub progress: Progress,
+	/// Whether the iteration texture may take more than one frame, see `super::progress`.
+	pub progressive: bool,
+	iteration_sampler: wgpu::Sampler,
+	palette_sampler: wgpu::Sampler,
+	histogram: wgpu::Buffer,
+	bind_group_layout: wgpu::BindGroupLayout,
+	bind_group: wgpu::BindGroup,
+	histogram_pipeline: wgpu::ComputePipeline,
+	cdf_pipeline: wgpu::ComputePipeline,
+	pipeline: wgpu::RenderPipeline,
+}
+
+impl Colorizer {
+	pub fn new(device: &wgpu::Device, data: &FractalViewData) -> Self {
+		let size = data.window_size.size;
+		let (iteration_texture, iteration_view) = Self::create_target(device, size, ITERATION_FORMAT);
+		let (orbit_texture, orbit_view) = Self::create_target(device, size, ORBIT_FORMAT);
+		let (normal_texture, normal_view) = Self::create_target(device, size, NORMAL_FORMAT);
+		let sampler = |filter| device.create_sampler(&wgpu::SamplerDescriptor {
+			address_mode_u: wgpu::AddressMode::Repeat,
+			address_mode_v: wgpu::AddressMode::Repeat,
+			address_mode_w: wgpu::AddressMode::Repeat,
+			mag_filter: filter,
+			min_filter: filter,
+			mipmap_filter: wgpu::FilterMode::Nearest,
+			lod_min_clamp: 0.0,
+			lod_max_clamp: 0.0,
+			compare_function: wgpu::CompareFunction::Always,
+		});
+		// Float textures can't be filtered, they are only ever fetched from anyway.
+		let iteration_sampler = sampler(wgpu::FilterMode::Nearest);
+		let palette_sampler = sampler(wgpu::FilterMode::Linear);
+		// Zeroed, `histogram_cdf.comp` empties it again after every frame.
+		let histogram = device.create_buffer_mapped(
+			HISTOGRAM_SIZE as usize,
+			wgpu::BufferUsage::STORAGE
+		).fill_from_slice(&vec![0u8; HISTOGRAM_SIZE as usize]);
+
+		let visibility = wgpu::ShaderStage::COMPUTE | wgpu::ShaderStage::FRAGMENT;
+		let bind_group_layout = device.create_bind_group_layout(
+			&wgpu::BindGroupLayoutDescriptor {
+				bindings: &[
+					wgpu::BindGroupLayoutBinding {
+						binding: 0,
+						visibility,
+						ty: wgpu::BindingType::UniformBuffer {
+							dynamic: false
+						}
+					},
+					wgpu::BindGroupLayoutBinding {
+						binding: 1,
+						visibility,
+						ty: wgpu::BindingType::UniformBuffer {
+							dynamic: false
+						}
+					},
+					wgpu::BindGroupLayoutBinding {
+						binding: 2,
+						visibility,
+						ty: wgpu::BindingType::SampledTexture {
+							multisampled: false,
+							dimension: wgpu::TextureViewDimension::D1,
+						}
+					},
+					wgpu::BindGroupLayoutBinding {
+						binding: 3,
+						visibility,
+						ty: wgpu::BindingType::Sampler
+					},
+					wgpu::BindGroupLayoutBinding {
+						binding: 4,
+						visibility,
+						ty: wgpu::BindingType::SampledTexture {
+							multisampled: false,
+							dimension: wgpu::TextureViewDimension::D2,
+						}
+					},
+					wgpu::BindGroupLayoutBinding {
+						binding: 5,
+						visibility,
+						ty: wgpu::BindingType::Sampler
+					},
+					wgpu::BindGroupLayoutBinding {
+						binding: 6,
+						visibility,
+						ty: wgpu::BindingType::StorageBuffer {
+							dynamic: false,
+							readonly: false
+						}
+					},
+					wgpu::BindGroupLayoutBinding {
+						binding: 7,
+						visibility,
+						ty: wgpu::BindingType::SampledTexture {
+							multisampled: false,
+							dimension: wgpu::TextureViewDimension::D2,
+						}
+					},
+					wgpu::BindGroupLayoutBinding {
+						binding: 8,
+						visibility,
+						ty: wgpu::BindingType::UniformBuffer {
+							dynamic: false
+						}
+					},
+					wgpu::BindGroupLayoutBinding {
+						binding: 9,
+						visibility,
+						ty: wgpu::BindingType::UniformBuffer {
+							dynamic: false
+						}
+					},
+					wgpu::BindGroupLayoutBinding {
+						binding: 10,
+						visibility,
+						ty: wgpu::BindingType::SampledTexture {
+							multisampled: false,
+							dimension: wgpu::TextureViewDimension::D2,
+						}
+					},
+					wgpu::BindGroupLayoutBinding {
+						binding: 11,
+						visibility,
+						ty: wgpu::BindingType::UniformBuffer {
+							dynamic: false
+						}
+					},
+					wgpu::BindGroupLayoutBinding {
+						binding: 12,
+						visibility,
+						ty: wgpu::BindingType::UniformBuffer {
+							dynamic: false
+						}
+					},
+				]
+			}
+		);
+		let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			bind_group_layouts: &[&bind_group_layout],
+		});
+
+		let compute_pipeline = |shader: &[u32]| {
+			let module = device.create_shader_module(shader);
+			device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+				layout: &pipeline_layout,
+				compute_stage: wgpu::ProgrammableStageDescriptor {
+					module: &module,
+					entry_point: "main",
+				},
+			})
+		};
+		let histogram_pipeline = compute_pipeline(&*HISTOGRAM_COMP_SHADER);
+		let cdf_pipeline = compute_pipeline(&*HISTOGRAM_CDF_COMP_SHADER);
+		let fs_module = device.create_shader_module(&*COLORIZE_FRAG_SHADER);
+		let pipeline = create_render_pipeline(device, &pipeline_layout, &data.vs_module, &fs_module, &[wgpu::TextureFormat::Bgra8UnormSrgb]);
+
+		let bind_group = Self::create_bind_group(
+			device,
+			&bind_group_layout,
+			data,
+			&iteration_view,
+			&orbit_view,
+			&normal_view,
+			&iteration_sampler,
+			&palette_sampler,
+			&histogram
+		);
+
+		Self {
+			size,
+			iteration_texture,
+			iteration_view,
+			orbit_texture,
+			orbit_view,
+			normal_texture,
+			normal_view,
+			iterated: None,
+			progress: Progress::default(),
+			progressive: true,
+			iteration_sampler,
+			palette_sampler,
+			histogram,
+			bind_group_layout,
+			bind_group,
+			histogram_pipeline,
+			cdf_pipeline,
+			pipeline,
+		}
+	}
+
+	fn create_target(device: &wgpu::Device, size: [f32; 2], format: wgpu::TextureFormat) -> (wgpu::Texture, wgpu::TextureView) {
+		let texture = device.create_texture(&wgpu::TextureDescriptor {
+			size: wgpu::Extent3d {
+				width: (size[0].round() as u32).max(1),
+				height: (size[1].round() as u32).max(1),
+				depth: 1,
+			},
+			array_layer_count: 1,
+			mip_level_count: 1,
+			sample_count: 1,
+			dimension: wgpu::TextureDimension::D2,
+			format,
+			usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
+		});
+		let view = texture.create_default_view();
+		(texture, view)
+	}
+
+	fn create_bind_group(
+		device: &wgpu::Device,
+		layout: &wgpu::BindGroupLayout,
+		data: &FractalViewData,
+		iteration_view: &wgpu::TextureView,
+		orbit_view: &wgpu::TextureView,
+		normal_view: &wgpu::TextureView,
+		iteration_sampler: &wgpu::Sampler,
+		palette_sampler: &wgpu::Sampler,
+		histogram: &wgpu::Buffer
+	) -> wgpu::BindGroup {
+		let palette_view = data.palette_texture.create_default_view();
+		device.create_bind_group(&wgpu::BindGroupDescriptor {
+			layout,
+			bindings: &[
+				wgpu::Binding {
+					binding: 0,
+					resource: wgpu::BindingResource::Buffer {
+						buffer: &data.bufs.iterations,
+						range: 0..*ITERATIONS_SIZE
+					}
+				},
+				wgpu::Binding {
+					binding: 1,
+					resource: wgpu::BindingResource::Buffer {
+						buffer: &data.bufs.coloring,
+						range: 0..*COLORING_SIZE
+					}
+				},
+				wgpu::Binding {
+					binding: 2,
+					resource: wgpu::BindingResource::TextureView(&palette_view)
+				},
+				wgpu::Binding {
+					binding: 3,
+					resource: wgpu::BindingResource::Sampler(palette_sampler)
+				},
+				wgpu::Binding {
+					binding: 4,
+					resource: wgpu::BindingResource::TextureView(iteration_view)
+				},
+				wgpu::Binding {
+					binding: 5,
+					resource: wgpu::BindingResource::Sampler(iteration_sampler)
+				},
+				wgpu::Binding {
+					binding: 6,
+					resource: wgpu::BindingResource::Buffer {
+						buffer: histogram,
+						range: 0..HISTOGRAM_SIZE
+					}
+				},
+				wgpu::Binding {
+					binding: 7,
+					resource: wgpu::BindingResource::TextureView(orbit_view)
+				},
+				wgpu::Binding {
+					binding: 8,
+					resource: wgpu::BindingResource::Buffer {
+						buffer: &data.bufs.trap,
+						range: 0..*TRAP_SIZE
+					}
+				},
+				wgpu::Binding {
+					binding: 9,
+					resource: wgpu::BindingResource::Buffer {
+						buffer: &data.bufs.average,
+						range: 0..*AVERAGE_SIZE
+					}
+				},
+				wgpu::Binding {
+					binding: 10,
+					resource: wgpu::BindingResource::TextureView(normal_view)
+				},
+				wgpu::Binding {
+					binding: 11,
+					resource: wgpu::BindingResource::Buffer {
+						buffer: &data.bufs.lighting,
+						range: 0..*LIGHTING_SIZE
+					}
+				},
+				wgpu::Binding {
+					binding: 12,
+					resource: wgpu::BindingResource::Buffer {
+						buffer: &data.bufs.progress,
+						range: 0..PROGRESS_SIZE
+					}
+				},
+			],
+		})
+	}
+
+	/// Makes the render targets as big as the window again, after it was resized.
+	pub fn resize(&mut self, device: &wgpu::Device, data: &FractalViewData) {
+		let size = data.window_size.size;
+		if self.size == size {
+			return;
+		}
+		log::info!("Resizing the iteration texture to {:?}", size);
+		let (iteration_texture, iteration_view) = Self::create_target(device, size, ITERATION_FORMAT);
+		let (orbit_texture, orbit_view) = Self::create_target(device, size, ORBIT_FORMAT);
+		let (normal_texture, normal_view) = Self::create_target(device, size, NORMAL_FORMAT);
+		self.bind_group = Self::create_bind_group(
+			device,
+			&self.bind_group_layout,
+			data,
+			&iteration_view,
+			&orbit_view,
+			&normal_view,
+			&self.iteration_sampler,
+			&self.palette_sampler,
+			&self.histogram
+		);
+		self.iteration_texture = iteration_texture;
+		self.iteration_view = iteration_view;
+		self.orbit_texture = orbit_texture;
+		self.orbit_view = orbit_view;
+		self.normal_texture = normal_texture;
+		self.normal_view = normal_view;
+		self.iterated = None;
+		self.size = size;
+	}
+
+	/// Colors the iteration texture into the part of `target` that `vertices` cover.
+	pub fn render(
+		&self,
+		encoder: &mut wgpu::CommandEncoder,
+		target: &wgpu::TextureView,
+		vertices: &wgpu::Buffer,
+		mode: ColoringMode
+	) {
+		if mode == ColoringMode::Histogram {
+			let workgroups = |length: f32| (length.round() as u32 + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE;
+			let mut cpass = encoder.begin_compute_pass();
+			cpass.set_bind_group(0, &self.bind_group, &[]);
+			cpass.set_pipeline(&self.histogram_pipeline);
+			cpass.dispatch(workgroups(self.size[0]), workgroups(self.size[1]), 1);
+			cpass.set_pipeline(&self.cdf_pipeline);
+			cpass.dispatch(1, 1, 1);
+		}
+
+		let mut rpass = encoder.begin_render_pass(
+			&wgpu::RenderPassDescriptor {
+				color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+					attachment: target,
+					resolve_target: None,
+					load_op: wgpu::LoadOp::Load,
+					store_op: wgpu::StoreOp::Store,
+					clear_color: wgpu::Color::BLACK
+				}],
+				depth_stencil_attachment: None,
+			}
+		);
+		rpass.set_pipeline(&self.pipeline);
+		rpass.set_bind_group(0, &self.bind_group, &[]);
+		rpass.set_vertex_buffers(0, &[(vertices, 0)]);
+		rpass.draw(0..4, 0..1);
+	}
+}
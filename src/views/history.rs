@@ -0,0 +1,188 @@
+//! Undo and redo of everything that moves a view around.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::utils::{Center, Zoom, Iterations, Position};
+
+/// Wheel events closer together than this are undone together.
+const BURST_GAP: Duration = Duration::from_millis(500);
+
+/// How many steps back are kept.
+const MAX_LENGTH: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+	pub center: Center,
+	pub zoom: Zoom,
+	pub iterations: Iterations,
+	pub generator: Position,
+}
+
+/// What the events of a burst change, bursts of different kinds are undone one by one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Burst {
+	Zoom,
+	Iterations,
+}
+
+#[derive(Default)]
+pub struct History {
+	/// Oldest first, so that the oldest go once there are `MAX_LENGTH`.
+	undo: VecDeque<Snapshot>,
+	redo: Vec<Snapshot>,
+	/// State from before the drag that is going on.
+	drag_start: Option<Snapshot>,
+	last_burst: Option<(Burst, Instant)>,
+}
+
+impl History {
+	/// Records the state from just before a change.
+	pub fn push(&mut self, snapshot: Snapshot) {
+		if self.undo.back() == Some(&snapshot) {
+			return;
+		}
+		if self.undo.len() == MAX_LENGTH {
+			self.undo.pop_front();
+		}
+		self.undo.push_back(snapshot);
+		self.redo.clear();
+		self.last_burst = None;
+	}
+
+	/// Like `push`, but only for the first of events of the same `kind` coming in quick succession.
+	pub fn push_burst(&mut self, snapshot: Snapshot, kind: Burst) {
+		let now = Instant::now();
+		let continues = self.last_burst.map_or(false, |(last_kind, last)| last_kind == kind && now - last < BURST_GAP);
+		if !continues {
+			self.push(snapshot);
+		}
+		self.last_burst = Some((kind, now));
+	}
+
+	pub fn begin_drag(&mut self, snapshot: Snapshot) {
+		self.drag_start = Some(snapshot);
+	}
+
+	/// Records the whole drag as one step, if it moved anything at all.
+	pub fn end_drag(&mut self, current: &Snapshot) {
+		if let Some(start) = self.drag_start.take() {
+			if start != *current {
+				self.push(start);
+			}
+		}
+	}
+
+	/// Returns the state to go back to, `current` becomes the next redo.
+	pub fn undo(&mut self, current: Snapshot) -> Option<Snapshot> {
+		let previous = self.undo.pop_back()?;
+		self.redo.push(current);
+		self.last_burst = None;
+		Some(previous)
+	}
+
+	pub fn redo(&mut self, current: Snapshot) -> Option<Snapshot> {
+		let next = self.redo.pop()?;
+		self.undo.push_back(current);
+		self.last_burst = None;
+		Some(next)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn at(x: f64) -> Snapshot {
+		Snapshot {
+			center: Center::new([x, 0.0]),
+			zoom: Zoom::default(),
+			iterations: Iterations::default(),
+			generator: Position::default(),
+		}
+	}
+
+	#[test]
+	fn bursts_of_one_kind_are_one_step() {
+		let mut history = History::default();
+		history.push_burst(at(0.0), Burst::Zoom);
+		history.push_burst(at(1.0), Burst::Zoom);
+		history.push_burst(at(2.0), Burst::Zoom);
+		assert_eq!(history.undo(at(3.0)), Some(at(0.0)));
+		assert_eq!(history.undo(at(0.0)), None);
+	}
+
+	#[test]
+	fn bursts_of_different_kinds_are_separate_steps() {
+		let mut history = History::default();
+		history.push_burst(at(0.0), Burst::Zoom);
+		history.push_burst(at(1.0), Burst::Iterations);
+		history.push_burst(at(2.0), Burst::Iterations);
+		history.push_burst(at(3.0), Burst::Zoom);
+		assert_eq!(history.undo(at(4.0)), Some(at(3.0)));
+		assert_eq!(history.undo(at(3.0)), Some(at(1.0)));
+		assert_eq!(history.undo(at(1.0)), Some(at(0.0)));
+	}
+
+	#[test]
+	fn push_ends_a_burst() {
+		let mut history = History::default();
+		history.push_burst(at(0.0), Burst::Zoom);
+		history.push(at(1.0));
+		history.push_burst(at(2.0), Burst::Zoom);
+		assert_eq!(history.undo(at(3.0)), Some(at(2.0)));
+		assert_eq!(history.undo(at(2.0)), Some(at(1.0)));
+	}
+
+	#[test]
+	fn drags_are_one_step_if_they_moved() {
+		let mut history = History::default();
+		history.begin_drag(at(0.0));
+		history.end_drag(&at(0.0));
+		assert_eq!(history.undo(at(0.0)), None);
+
+		history.begin_drag(at(0.0));
+		history.end_drag(&at(5.0));
+		assert_eq!(history.undo(at(5.0)), Some(at(0.0)));
+		// Without a drag going on there is nothing to end.
+		history.end_drag(&at(7.0));
+		assert_eq!(history.undo(at(0.0)), None);
+	}
+
+	#[test]
+	fn undo_and_redo() {
+		let mut history = History::default();
+		history.push(at(0.0));
+		history.push(at(1.0));
+		assert_eq!(history.undo(at(2.0)), Some(at(1.0)));
+		assert_eq!(history.undo(at(1.0)), Some(at(0.0)));
+		assert_eq!(history.redo(at(0.0)), Some(at(1.0)));
+		assert_eq!(history.redo(at(1.0)), Some(at(2.0)));
+		assert_eq!(history.redo(at(2.0)), None);
+	}
+
+	#[test]
+	fn push_clears_redo() {
+		let mut history = History::default();
+		history.push(at(0.0));
+		assert_eq!(history.undo(at(1.0)), Some(at(0.0)));
+		history.push(at(0.0));
+		assert_eq!(history.redo(at(2.0)), None);
+	}
+
+	#[test]
+	fn keeps_the_newest_steps() {
+		let mut history = History::default();
+		for i in 0..MAX_LENGTH + 10 {
+			history.push(at(i as f64));
+		}
+		let mut steps = 0;
+		let mut oldest = None;
+		while let Some(previous) = history.undo(at(-1.0)) {
+			steps += 1;
+			oldest = Some(previous);
+		}
+		assert_eq!(steps, MAX_LENGTH);
+		assert_eq!(oldest, Some(at(10.0)));
+	}
+}
@@ -0,0 +1,4 @@
+//! Home of the Lyapunov view, of the stability of the logistic map.
+
+mod lyapunov;
+pub use self::lyapunov::LyapunovViewManager;
@@ -3,7 +3,7 @@ use super::MandelbrotDoubleView;
 
 use crate::views::prelude::*;
 use wgpu::{Device, CommandBuffer};
-use winit::event::{MouseButton, ElementState};
+use winit::event::{MouseButton, ElementState, ModifiersState};
 
 pub struct DoubleViewManager {
 	left: MandelbrotDoubleView,
@@ -14,14 +14,20 @@ pub struct DoubleViewManager {
 	ever_had_pos: bool
 }
 
+impl DoubleViewManager {
+	fn is_on_left(&self) -> bool {
+		self.cursor_pos.pos[0] < f64::from(self.window_size.size[0]) / 2f64
+	}
+}
+
 impl FractalViewManager for DoubleViewManager {
 	fn new(device: &wgpu::Device, size: winit::dpi::LogicalSize) -> Self {
 		Self {
 			left: MandelbrotDoubleView::new(device, size),
 			right: JuliaDoubleView::new(device, size),
 			window_size: WindowSize{size: [size.width as f32, size.height as f32]},
-			cursor_pos: Position{pos: [size.width as f32/2f32, size.height as f32/2f32]},
-			prev_cursor_pos: Position{pos: [0f32, 0f32]},
+			cursor_pos: Position{pos: [size.width/2f64, size.height/2f64]},
+			prev_cursor_pos: Position{pos: [0f64, 0f64]},
 			ever_had_pos: false,
 		}
 	}
@@ -29,10 +35,10 @@ impl FractalViewManager for DoubleViewManager {
 	fn render(
 		&mut self,
 		device: &AtomicDevice,
-		frame: &wgpu::SwapChainOutput,
+		target: &wgpu::TextureView,
 	) -> Vec<CommandBuffer> {
-		let buf1 = self.left.render(device, frame);
-		let buf2 = self.right.render(device, frame);
+		let buf1 = self.left.render(device, target);
+		let buf2 = self.right.render(device, target);
 
 		vec![buf1, buf2]
 	}
@@ -45,16 +51,29 @@ impl FractalViewManager for DoubleViewManager {
 		vec![buf1, buf2]
 	}
 
-	fn mouse_input(&mut self, button: MouseButton, state: ElementState) {
-		self.left.mouse_input(button, state);
-		self.right.mouse_input(button, state);
+	/// Drags and selections only start in the half under the cursor, releases go to both.
+	fn mouse_input(&mut self, device: &Arc<Mutex<Device>>, button: MouseButton, state: ElementState, modifiers: ModifiersState) -> Option<Vec<CommandBuffer>> {
+		let on_left = self.is_on_left();
+		let pressed = state == ElementState::Pressed;
+		let mut buf = vec![];
+		if !pressed || on_left {
+			buf.extend(self.left.mouse_input(device, button, state, modifiers));
+		}
+		if !pressed || !on_left {
+			buf.extend(self.right.mouse_input(device, button, state, modifiers));
+		}
+		if !buf.is_empty() {
+			Some(buf)
+		} else {
+			None
+		}
 	}
 
-	fn iterations(&mut self, device: &AtomicDevice, y_delta: f32) -> Vec<CommandBuffer> {
-		if self.cursor_pos.pos[0] < self.window_size.size[0] / 2f32 {
-			vec![self.left.iterations(device, y_delta)]
+	fn iterations(&mut self, device: &AtomicDevice, factor: f32) -> Vec<CommandBuffer> {
+		if self.cursor_pos.pos[0] < f64::from(self.window_size.size[0]) / 2f64 {
+			vec![self.left.iterations(device, factor)]
 		} else {
-			vec![self.right.iterations(device, y_delta)]
+			vec![self.right.iterations(device, factor)]
 		}
 	}
 
@@ -62,8 +81,62 @@ impl FractalViewManager for DoubleViewManager {
 		None
 	}
 
+	fn set_formula(&mut self, device: &Arc<Mutex<Device>>, formula: Formula) -> Vec<CommandBuffer> {
+		vec![
+			self.left.set_formula(device, formula),
+			self.right.set_formula(device, formula)
+		]
+	}
+
+	/// Both halves or neither, the left one goes back to its formula where the right one fails.
+	fn set_custom_formula(&mut self, device: &Arc<Mutex<Device>>, custom_formula: Option<CustomFormula>) -> Result<(), String> {
+		let previous = self.left.data().custom_formula.clone();
+		self.left.set_custom_formula(device, custom_formula.clone())?;
+		let result = self.right.set_custom_formula(device, custom_formula);
+		if result.is_err() {
+			// It compiled before, so it does again.
+			let _ = self.left.set_custom_formula(device, previous);
+		}
+		result
+	}
+
+	fn set_palette(&mut self, device: &Arc<Mutex<Device>>, palette: Palette) -> Vec<CommandBuffer> {
+		vec![
+			self.left.set_palette(device, palette.clone()),
+			self.right.set_palette(device, palette)
+		]
+	}
+
+	fn set_coloring(&mut self, device: &Arc<Mutex<Device>>, coloring: Coloring) -> Vec<CommandBuffer> {
+		vec![
+			self.left.set_coloring(device, coloring.clone()),
+			self.right.set_coloring(device, coloring)
+		]
+	}
+
+	fn set_trap(&mut self, device: &Arc<Mutex<Device>>, trap: Trap) -> Vec<CommandBuffer> {
+		vec![
+			self.left.set_trap(device, trap),
+			self.right.set_trap(device, trap)
+		]
+	}
+
+	fn set_average(&mut self, device: &Arc<Mutex<Device>>, average: Average) -> Vec<CommandBuffer> {
+		vec![
+			self.left.set_average(device, average),
+			self.right.set_average(device, average)
+		]
+	}
+
+	fn set_lighting(&mut self, device: &Arc<Mutex<Device>>, lighting: Lighting) -> Vec<CommandBuffer> {
+		vec![
+			self.left.set_lighting(device, lighting),
+			self.right.set_lighting(device, lighting)
+		]
+	}
+
 	fn zoom(&mut self, device: &Arc<Mutex<Device>>, y_delta: f32) -> Vec<CommandBuffer> {
-		if self.cursor_pos.pos[0] < self.window_size.size[0] / 2f32 {
+		if self.cursor_pos.pos[0] < f64::from(self.window_size.size[0]) / 2f64 {
 			let buf1 = self.left.zoom(device, y_delta);
 			vec![buf1]
 		} else {
@@ -72,9 +145,38 @@ impl FractalViewManager for DoubleViewManager {
 		}
 	}
 
+	/// Position, zoom and iterations of the Mandelbrot on the left
+	/// together with the generator of the Julia on the right.
+	fn params(&mut self) -> FractalParams {
+		FractalParams {
+			generator: self.right.data().generator,
+			..self.left.params()
+		}
+	}
+
+	/// The position and zoom go to the Mandelbrot on the left, the Julia on the right keeps its own.
+	/// Everything else goes to both.
+	fn set_params(&mut self, device: &Arc<Mutex<Device>>, params: &FractalParams) -> Vec<CommandBuffer> {
+		let left = FractalParams {
+			julia: Julia { is_julia: false },
+			..params.clone()
+		};
+		let right = FractalParams {
+			center: self.right.data().center.clone(),
+			zoom: self.right.data().zoom,
+			julia: Julia { is_julia: true },
+			..params.clone()
+		};
+
+		vec![
+			self.left.set_params(device, &left),
+			self.right.set_params(device, &right)
+		]
+	}
+
 	fn new_position(&mut self, device: &Arc<Mutex<Device>>, x: f32, y: f32, active: bool) -> Option<Vec<CommandBuffer>> {
 		let mut buf = vec![];
-		self.cursor_pos.pos = [x, y];
+		self.cursor_pos.pos = [f64::from(x), f64::from(y)];
 		if x > self.window_size.size[0] / 2f32 {
 			log::info!("Sending new_position to right.");
 			if let Some(ok) = self.right.new_position(device, x, y, active) {
@@ -84,29 +186,29 @@ impl FractalViewManager for DoubleViewManager {
 			log::info!("Sending new_position to left.");
 			let mut prev_position = self.left.data().prev_position;
 			if !self.ever_had_pos {
-				prev_position.pos = [x, y];
+				prev_position.pos = [f64::from(x), f64::from(y)];
 				self.ever_had_pos = true;
 			}
 			if let Some(ok) = self.left.new_position(device, x,y, active) {
 				buf.push(ok);
 				if active {
 					self.prev_cursor_pos = self.left.data().prev_position;
-					log::info!("New position in left Mandelbrot after drag: {:?}", self.left.data().pos);
+					log::info!("New position in left Mandelbrot after drag: {:?}", self.left.data().center.position());
 				}
 			}
 			if !active {
-				let drag_pos = self.left.data().pos;
+				let drag_pos = self.left.data().center.position();
 //				let mut pos = self.prev_cursor_pos;
 				let zoom = self.left.data().zoom;
 //				let mut prev_position = self.prev_cursor_pos;
 //				let delta_x = x - self.prev_cursor_pos.pos[0];
 //				let delta_y = y - self.prev_cursor_pos.pos[1];
-				let mut pos = Position {pos:[0f32, 0f32]};
+				let mut pos = Position {pos:[0f64, 0f64]};
 				log::info!("Prev position: {:?}; Current position: {:?}", self.prev_cursor_pos, pos);
 				log::info!("Zoom: {:?}", zoom.zoom);
 
-				let half_w = self.window_size.size[0] * 0.5f32;
-				let half_h = self.window_size.size[1] * 0.5f32;
+				let half_w = f64::from(self.window_size.size[0]) * 0.5f64;
+				let half_h = f64::from(self.window_size.size[1]) * 0.5f64;
 
 //				let drag_pos = Position {
 //					pos: [
@@ -115,14 +217,15 @@ impl FractalViewManager for DoubleViewManager {
 //					]
 //				};
 
-				pos.pos[0] = (x - half_w) * zoom.zoom - drag_pos.pos[0];
-				pos.pos[1] = (half_h - y) * zoom.zoom + drag_pos.pos[1];
+				pos.pos[0] = (f64::from(x) - half_w) * zoom.zoom - drag_pos.pos[0];
+				pos.pos[1] = (half_h - f64::from(y)) * zoom.zoom + drag_pos.pos[1];
 
 				log::info!("Sending cursor pos {:?} to Julia", pos);
+				self.right.data().generator = pos;
 				let temp_buf = device.lock().unwrap().create_buffer_mapped(
 					1,
 					wgpu::BufferUsage::COPY_SRC
-				).fill_from_slice(&[pos]);
+				).fill_from_slice(&[pos.uniform()]);
 
 				let mut encoder =
 					device.lock().unwrap().create_command_encoder(&wgpu::CommandEncoderDescriptor { todo: 0 });
@@ -145,13 +248,56 @@ impl FractalViewManager for DoubleViewManager {
 		}
 	}
 
+	fn undo(&mut self, device: &Arc<Mutex<Device>>) -> Option<Vec<CommandBuffer>> {
+		if self.is_on_left() {
+			self.left.undo(device).map(|buf| vec![buf])
+		} else {
+			self.right.undo(device).map(|buf| vec![buf])
+		}
+	}
+
+	fn redo(&mut self, device: &Arc<Mutex<Device>>) -> Option<Vec<CommandBuffer>> {
+		if self.is_on_left() {
+			self.left.redo(device).map(|buf| vec![buf])
+		} else {
+			self.right.redo(device).map(|buf| vec![buf])
+		}
+	}
+
+	fn set_series_approximation(&mut self, state: bool) {
+		self.left.set_series_approximation(state);
+		self.right.set_series_approximation(state);
+	}
+
+	fn set_auto_iterations(&mut self, state: bool) {
+		self.left.set_auto_iterations(state);
+		self.right.set_auto_iterations(state);
+	}
+
+	/// Of the Mandelbrot on the left, the Julia on the right as a fallback.
+	fn skipped_iterations(&mut self) -> Option<usize> {
+		self.left.data().skipped.or(self.right.data().skipped)
+	}
+
+	fn is_refining(&mut self) -> bool {
+		self.left.data().is_refining() || self.right.data().is_refining()
+	}
+
+	/// Of the half that is further behind.
+	fn progress(&mut self) -> Option<f32> {
+		match (self.left.data().progress(), self.right.data().progress()) {
+			(Some(left), Some(right)) => Some(left.min(right)),
+			(left, right) => left.or(right),
+		}
+	}
+
 	fn create_render_pipeline(&mut self, device: &Device) {
 		self.left.create_render_pipeline(device);
 		self.right.create_render_pipeline(device);
 	}
 
-	fn reload_fs(&mut self, device: &Arc<Mutex<Device>>) {
-		self.left.reload_fs(device);
-		self.right.reload_fs(device);
+	fn reload_fs(&mut self, device: &Arc<Mutex<Device>>) -> Result<(), String> {
+		self.left.reload_fs(device)?;
+		self.right.reload_fs(device)
 	}
 }
\ No newline at end of file
@@ -2,13 +2,26 @@
 
 mod mandelbrot;
 mod mandelbrot_and_julia;
+mod newton;
+mod buddhabrot;
+mod lyapunov;
 mod view;
 mod utils;
 mod switchable;
+mod offscreen;
+mod selection;
+mod colorize;
+mod history;
+mod progress;
+mod perturber;
 
 pub use self::mandelbrot::MandelbrotViewManager;
 pub use self::mandelbrot_and_julia::{DoubleViewManager, JuliaDoubleView, MandelbrotDoubleView};
-pub use self::view::FractalViewManager;
+pub use self::newton::NewtonViewManager;
+pub use self::buddhabrot::BuddhabrotViewManager;
+pub use self::lyapunov::LyapunovViewManager;
+pub use self::view::{FractalViewManager, FractalViewable};
+pub use self::offscreen::OffscreenView;
 pub use self::prelude::FRAG_SHADER_PATH;
 pub use self::switchable::SwitchableViewManager;
 
@@ -0,0 +1,4 @@
+//! Home of the Newton fractal view, of the roots of a polynomial.
+
+mod newton;
+pub use self::newton::NewtonViewManager;
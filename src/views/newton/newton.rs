@@ -0,0 +1,137 @@
+use crate::views::prelude::*;
+use crate::views::utils::{new_with_shader, Center};
+use wgpu::{Device, TextureView, CommandBuffer};
+use winit::event::{MouseButton, ElementState, ModifiersState};
+use winit::dpi::LogicalSize;
+
+/// Basins of Newton's method for `FractalParams::polynomial`, moves around like the other views.
+pub struct NewtonViewManager {
+	view: NewtonView
+}
+
+impl FractalViewManager for NewtonViewManager {
+	fn new(device: &Device, size: LogicalSize) -> Self {
+		Self {
+			view: NewtonView::new(device, size)
+		}
+	}
+
+	fn render(&mut self, device: &Arc<Mutex<Device>>, target: &TextureView) -> Vec<CommandBuffer> {
+		vec![self.view.render(device, target)]
+	}
+
+	fn resized(&mut self, device: &Arc<Mutex<Device>>, window_size: &WindowSize) -> Vec<CommandBuffer> {
+		vec![self.view.resized(device, window_size)]
+	}
+
+	fn mouse_input(&mut self, device: &Arc<Mutex<Device>>, button: MouseButton, state: ElementState, modifiers: ModifiersState) -> Option<Vec<CommandBuffer>> {
+		self.view.mouse_input(device, button, state, modifiers).map(|buf| vec![buf])
+	}
+
+	fn iterations(&mut self, device: &Arc<Mutex<Device>>, factor: f32) -> Vec<CommandBuffer> {
+		vec![self.view.iterations(device, factor)]
+	}
+
+	/// There is no Julia set of a Newton fractal.
+	fn set_julia(&mut self, _device: &Arc<Mutex<Device>>, _state: bool) -> Option<Vec<CommandBuffer>> {
+		None
+	}
+
+	/// Kept for when switching back, the Newton view itself has no use for it.
+	fn set_formula(&mut self, device: &Arc<Mutex<Device>>, formula: Formula) -> Vec<CommandBuffer> {
+		vec![self.view.set_formula(device, formula)]
+	}
+
+	fn set_custom_formula(&mut self, device: &Arc<Mutex<Device>>, custom_formula: Option<CustomFormula>) -> Result<(), String> {
+		self.view.set_custom_formula(device, custom_formula)
+	}
+
+	fn set_palette(&mut self, device: &Arc<Mutex<Device>>, palette: Palette) -> Vec<CommandBuffer> {
+		vec![self.view.set_palette(device, palette)]
+	}
+
+	fn set_coloring(&mut self, device: &Arc<Mutex<Device>>, coloring: Coloring) -> Vec<CommandBuffer> {
+		vec![self.view.set_coloring(device, coloring)]
+	}
+
+	fn set_trap(&mut self, device: &Arc<Mutex<Device>>, trap: Trap) -> Vec<CommandBuffer> {
+		vec![self.view.set_trap(device, trap)]
+	}
+
+	fn set_average(&mut self, device: &Arc<Mutex<Device>>, average: Average) -> Vec<CommandBuffer> {
+		vec![self.view.set_average(device, average)]
+	}
+
+	fn set_lighting(&mut self, device: &Arc<Mutex<Device>>, lighting: Lighting) -> Vec<CommandBuffer> {
+		vec![self.view.set_lighting(device, lighting)]
+	}
+
+	fn zoom(&mut self, device: &Arc<Mutex<Device>>, y_delta: f32) -> Vec<CommandBuffer> {
+		vec![self.view.zoom(device, y_delta)]
+	}
+
+	fn params(&mut self) -> FractalParams {
+		self.view.params()
+	}
+
+	fn set_params(&mut self, device: &Arc<Mutex<Device>>, params: &FractalParams) -> Vec<CommandBuffer> {
+		vec![self.view.set_params(device, params)]
+	}
+
+	fn new_position(&mut self, device: &Arc<Mutex<Device>>, x: f32, y: f32, active: bool) -> Option<Vec<CommandBuffer>> {
+		self.view.new_position(device, x, y, active).map(|buf| vec![buf])
+	}
+
+	fn undo(&mut self, device: &Arc<Mutex<Device>>) -> Option<Vec<CommandBuffer>> {
+		self.view.undo(device).map(|buf| vec![buf])
+	}
+
+	fn redo(&mut self, device: &Arc<Mutex<Device>>) -> Option<Vec<CommandBuffer>> {
+		self.view.redo(device).map(|buf| vec![buf])
+	}
+
+	fn set_series_approximation(&mut self, _state: bool) {}
+
+	fn set_auto_iterations(&mut self, state: bool) {
+		self.view.set_auto_iterations(state)
+	}
+
+	fn skipped_iterations(&mut self) -> Option<usize> {
+		None
+	}
+
+	fn create_render_pipeline(&mut self, device: &Device) {
+		self.view.create_render_pipeline(device)
+	}
+
+	fn reload_fs(&mut self, device: &Arc<Mutex<Device>>) -> Result<(), String> {
+		self.view.reload_fs(device)
+	}
+}
+
+struct NewtonView {
+	data: FractalViewData,
+}
+
+impl FractalViewable for NewtonView {
+	fn new(device: &wgpu::Device, size: dpi::LogicalSize) -> Self {
+		let data
+			= new_with_shader(device, size, false, (*WHOLE_VERTICES).clone(), &*NEWTON_FRAG_SHADER, Center::default());
+
+		Self {
+			data,
+		}
+	}
+
+	fn data(&mut self) -> &mut FractalViewData {
+		&mut self.data
+	}
+
+	fn frag_shader_path(&self) -> &'static Path {
+		&*NEWTON_FRAG_SHADER_PATH
+	}
+
+	fn is_perturbable(&self) -> bool {
+		false
+	}
+}
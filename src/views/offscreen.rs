@@ -0,0 +1,32 @@
+//! View that is drawn into a texture instead of a window. Used by `crate::headless`.
+
+use super::prelude::*;
+use super::utils::new;
+
+pub struct OffscreenView {
+	data: FractalViewData,
+}
+
+impl FractalViewable for OffscreenView {
+	fn new(device: &wgpu::Device, size: dpi::LogicalSize) -> Self {
+		let mut data
+			= new(device, size, false, (*WHOLE_VERTICES).clone());
+		// The frame gets read back right away, it has to be complete.
+		if let Some(colorizer) = &mut data.colorizer {
+			colorizer.progressive = false;
+		}
+		data.perturber.blocking = true;
+
+		Self {
+			data,
+		}
+	}
+
+	fn data(&mut self) -> &mut FractalViewData {
+		&mut self.data
+	}
+
+	fn frag_shader_path(&self) -> &'static Path {
+		&*FRAG_SHADER_PATH
+	}
+}
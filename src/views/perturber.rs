@@ -0,0 +1,177 @@
+//! Computes the reference orbits of deep zooms on a thread of their own, so that the event loop keeps going.
+//!
+//! While the view keeps changing, the references from before are moved along with it, see `Perturbation::rebased`.
+//! Once it stood still for `SETTLE_TIME` new ones get computed, and the frames keep using the old ones until they arrive.
+
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::perturbation::{self, Perturbation, PerturbationUniform};
+use crate::utils::FractalParams;
+
+/// How long the view has to stay the same before its references get computed.
+pub const SETTLE_TIME: Duration = Duration::from_millis(150);
+
+/// What references are computed for: the parameters without the coloring, the window size and the series approximation.
+type Key = (FractalParams, [f32; 2], bool);
+
+#[derive(Default)]
+pub struct Perturber {
+	/// The references in the orbits buffer and what they were computed for.
+	computed: Option<(Key, Perturbation)>,
+	/// What the uniform was last uploaded for.
+	uploaded: Option<Key>,
+	/// The view that is waiting to settle, since when it is as it is.
+	settling: Option<(Key, Instant)>,
+	/// The references being computed on the other thread and what for.
+	worker: Option<(Key, mpsc::Receiver<Perturbation>)>,
+	/// Whether `update` computes the references right away, for views that draw a single frame, see `crate::headless`.
+	pub blocking: bool,
+}
+
+impl Perturber {
+	/// Brings the references up to date with `params` drawn into the `viewport` of a window of `size`,
+	/// see `perturbation::compute`. Returns the uniform to upload when it changed, with the orbits when new ones arrived.
+	pub fn update(
+		&mut self,
+		params: FractalParams,
+		size: [f32; 2],
+		viewport: [f32; 4],
+		series: bool
+	) -> Option<(PerturbationUniform, Option<&[[f32; 2]]>)> {
+		let mut arrived = false;
+		if let Some((key, receiver)) = self.worker.take() {
+			match receiver.try_recv() {
+				Ok(perturbation) => {
+					self.computed = Some((key, perturbation));
+					arrived = true;
+				}
+				Err(mpsc::TryRecvError::Empty) => self.worker = Some((key, receiver)),
+				Err(mpsc::TryRecvError::Disconnected) => log::error!("Computing the references failed"),
+			}
+		}
+
+		let key = (params, size, series);
+		if !perturbation::is_needed(&key.0) {
+			self.settling = None;
+			if self.uploaded.as_ref() == Some(&key) {
+				return None;
+			}
+			self.uploaded = Some(key);
+			return Some((Perturbation::disabled().uniform, None));
+		}
+
+		let computed = self.computed.as_ref().map(|(computed, _)| computed);
+		let computing = self.worker.as_ref().map(|(computing, _)| computing);
+		if computed != Some(&key) && computing != Some(&key) {
+			if self.blocking {
+				let perturbation = perturbation::compute(&key.0, size, viewport, series);
+				self.computed = Some((key.clone(), perturbation));
+				arrived = true;
+			} else {
+				match &self.settling {
+					Some((settling, since)) if *settling == key => {
+						if since.elapsed() >= SETTLE_TIME && self.worker.is_none() {
+							self.settling = None;
+							self.spawn(key.clone(), viewport);
+						}
+					}
+					_ => self.settling = Some((key.clone(), Instant::now())),
+				}
+			}
+		}
+
+		if !arrived && self.uploaded.as_ref() == Some(&key) {
+			return None;
+		}
+		let (uniform, orbits) = match &self.computed {
+			Some((computed, perturbation)) => (
+				perturbation.rebased(&computed.0, &key.0).unwrap_or(Perturbation::disabled().uniform),
+				if arrived { Some(&perturbation.orbits[..]) } else { None },
+			),
+			None => (Perturbation::disabled().uniform, None),
+		};
+		self.uploaded = Some(key);
+		Some((uniform, orbits))
+	}
+
+	fn spawn(&mut self, key: Key, viewport: [f32; 4]) {
+		let (sender, receiver) = mpsc::channel();
+		let (params, size, series) = key.clone();
+		thread::spawn(move || {
+			// The view may be gone by the time they are done.
+			let _ = sender.send(perturbation::compute(&params, size, viewport, series));
+		});
+		self.worker = Some((key, receiver));
+	}
+
+	/// Whether references are waiting for the view to settle or being computed, `main` keeps drawing frames until they are there.
+	pub fn is_busy(&self) -> bool {
+		self.settling.is_some() || self.worker.is_some()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::utils::{Center, Zoom};
+
+	const SIZE: [f32; 2] = [32.0, 24.0];
+	const VIEWPORT: [f32; 4] = [0.0, 0.0, 32.0, 24.0];
+
+	fn deep() -> FractalParams {
+		FractalParams {
+			center: Center::new([-0.743643887037158, 0.131825904205311]),
+			zoom: Zoom { zoom: 1e-13 },
+			..FractalParams::default()
+		}
+	}
+
+	#[test]
+	fn blocking_computes_right_away() {
+		let mut perturber = Perturber { blocking: true, ..Perturber::default() };
+		let (uniform, orbits) = perturber.update(deep(), SIZE, VIEWPORT, true).unwrap();
+		assert!(uniform.enabled());
+		assert!(!orbits.unwrap().is_empty());
+		assert!(perturber.update(deep(), SIZE, VIEWPORT, true).is_none());
+		assert!(!perturber.is_busy());
+	}
+
+	#[test]
+	fn computes_once_the_view_settles() {
+		let mut perturber = Perturber::default();
+		// Nothing to move along yet.
+		let (uniform, orbits) = perturber.update(deep(), SIZE, VIEWPORT, true).unwrap();
+		assert!(!uniform.enabled() && orbits.is_none());
+		assert!(perturber.is_busy());
+
+		let start = Instant::now();
+		let orbits = loop {
+			assert!(start.elapsed() < Duration::from_secs(10), "the references never arrived");
+			if let Some((uniform, Some(orbits))) = perturber.update(deep(), SIZE, VIEWPORT, true) {
+				assert!(uniform.enabled());
+				break orbits.len();
+			}
+			thread::sleep(Duration::from_millis(10));
+		};
+		assert!(orbits > 0);
+		assert!(!perturber.is_busy());
+
+		// Moving keeps the references and only moves them along, until it settles again.
+		let mut moved = deep();
+		moved.center = moved.center.offset([5.0, 0.0], moved.zoom.zoom);
+		let (uniform, orbits) = perturber.update(moved, SIZE, VIEWPORT, true).unwrap();
+		assert!(uniform.enabled() && orbits.is_none());
+		assert!(perturber.is_busy());
+	}
+
+	#[test]
+	fn shallow_zooms_need_no_references() {
+		let mut perturber = Perturber::default();
+		let (uniform, _) = perturber.update(FractalParams::default(), SIZE, VIEWPORT, true).unwrap();
+		assert!(!uniform.enabled());
+		assert!(!perturber.is_busy());
+		assert!(perturber.update(FractalParams::default(), SIZE, VIEWPORT, true).is_none());
+	}
+}
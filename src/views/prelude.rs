@@ -21,10 +21,18 @@ pub use crate::utils::{
 	Zoom, ZOOM_SIZE,
 	Iterations, ITERATIONS_SIZE,
 	Vertex, VERTEX_SIZE,
-	Julia, JULIA_SIZE
+	Julia, JULIA_SIZE,
+	Formula,
+	FractalParams
 };
 
 pub use views::view::Buffers;
+pub use crate::custom_formula::CustomFormula;
+pub use crate::palette::Palette;
+pub use crate::coloring::Coloring;
+pub use crate::trap::Trap;
+pub use crate::average::Average;
+pub use crate::lighting::Lighting;
 pub use notify::{RecommendedWatcher, DebouncedEvent};
 pub use std::ops::Deref;
 
@@ -44,6 +52,65 @@ lazy_static! {
 		LittleEndian::read_u32_into(bytes, &mut rs);
 		rs
 	};
+	/// Fragment shader of `crate::views::selection::Selection`.
+	pub static ref OVERLAY_FRAG_SHADER: Vec<u32> = {
+		let bytes = include_bytes!("../../shaders/overlay.frag.spv");
+		let mut rs = vec![0; bytes.len()/4];
+		LittleEndian::read_u32_into(bytes, &mut rs);
+		rs
+	};
+	/// Fragment shader of `crate::views::NewtonViewManager`.
+	pub static ref NEWTON_FRAG_SHADER: Vec<u32> = {
+		let bytes = include_bytes!("../../shaders/newton.frag.spv");
+		let mut rs = vec![0; bytes.len()/4];
+		LittleEndian::read_u32_into(bytes, &mut rs);
+		rs
+	};
+	/// Fragment shader of `crate::views::LyapunovViewManager`.
+	pub static ref LYAPUNOV_FRAG_SHADER: Vec<u32> = {
+		let bytes = include_bytes!("../../shaders/lyapunov.frag.spv");
+		let mut rs = vec![0; bytes.len()/4];
+		LittleEndian::read_u32_into(bytes, &mut rs);
+		rs
+	};
+	/// Shaders of `crate::views::colorize::Colorizer`.
+	pub static ref COLORIZE_FRAG_SHADER: Vec<u32> = {
+		let bytes = include_bytes!("../../shaders/colorize.frag.spv");
+		let mut rs = vec![0; bytes.len()/4];
+		LittleEndian::read_u32_into(bytes, &mut rs);
+		rs
+	};
+	pub static ref HISTOGRAM_COMP_SHADER: Vec<u32> = {
+		let bytes = include_bytes!("../../shaders/histogram.comp.spv");
+		let mut rs = vec![0; bytes.len()/4];
+		LittleEndian::read_u32_into(bytes, &mut rs);
+		rs
+	};
+	pub static ref HISTOGRAM_CDF_COMP_SHADER: Vec<u32> = {
+		let bytes = include_bytes!("../../shaders/histogram_cdf.comp.spv");
+		let mut rs = vec![0; bytes.len()/4];
+		LittleEndian::read_u32_into(bytes, &mut rs);
+		rs
+	};
+	/// Shaders of `crate::views::BuddhabrotViewManager`.
+	pub static ref BUDDHABROT_COMP_SHADER: Vec<u32> = {
+		let bytes = include_bytes!("../../shaders/buddhabrot.comp.spv");
+		let mut rs = vec![0; bytes.len()/4];
+		LittleEndian::read_u32_into(bytes, &mut rs);
+		rs
+	};
+	pub static ref BUDDHABROT_CLEAR_SHADER: Vec<u32> = {
+		let bytes = include_bytes!("../../shaders/buddhabrot_clear.comp.spv");
+		let mut rs = vec![0; bytes.len()/4];
+		LittleEndian::read_u32_into(bytes, &mut rs);
+		rs
+	};
+	pub static ref BUDDHABROT_FRAG_SHADER: Vec<u32> = {
+		let bytes = include_bytes!("../../shaders/buddhabrot.frag.spv");
+		let mut rs = vec![0; bytes.len()/4];
+		LittleEndian::read_u32_into(bytes, &mut rs);
+		rs
+	};
 	/// Path to shader file which gets reloaded in `main`.
 	pub static ref FRAG_SHADER_PATH: PathBuf = {
 		let mut frag_shader_path_buf: PathBuf = ABSOLUTE_PATH.clone();
@@ -53,5 +120,23 @@ lazy_static! {
 		log::info!("Frag shader path: {:?}", frag_shader_path_buf);
 		frag_shader_path_buf
 	};
+	pub static ref BUDDHABROT_FRAG_SHADER_PATH: PathBuf = {
+		let mut frag_shader_path_buf: PathBuf = ABSOLUTE_PATH.clone();
+		let x = ["shaders", "buddhabrot.frag"].iter().collect();
+		frag_shader_path_buf.push::<PathBuf>(x);
+		frag_shader_path_buf
+	};
+	pub static ref NEWTON_FRAG_SHADER_PATH: PathBuf = {
+		let mut frag_shader_path_buf: PathBuf = ABSOLUTE_PATH.clone();
+		let x = ["shaders", "newton.frag"].iter().collect();
+		frag_shader_path_buf.push::<PathBuf>(x);
+		frag_shader_path_buf
+	};
+	pub static ref LYAPUNOV_FRAG_SHADER_PATH: PathBuf = {
+		let mut frag_shader_path_buf: PathBuf = ABSOLUTE_PATH.clone();
+		let x = ["shaders", "lyapunov.frag"].iter().collect();
+		frag_shader_path_buf.push::<PathBuf>(x);
+		frag_shader_path_buf
+	};
 
 }
@@ -0,0 +1,225 @@
+//! Progressive drawing of the iteration texture, so that high iteration counts don't stall the event loop.
+//!
+//! Once the parameters change, a coarse pass iterates only one pixel out of every `BLOCK` x `BLOCK`.
+//! The following frames iterate the viewport in tiles at full resolution, as many of them as
+//! `PIXEL_ITERATIONS_PER_FRAME` allows, and `colorize.frag` fills the tiles not yet there from the coarse pass.
+//! Where the whole viewport fits into one frame it gets drawn right away, like before.
+
+use zerocopy::{AsBytes, FromBytes};
+
+/// Side of the blocks of the coarse pass, in pixels. `TILE_SIZE` has to be a multiple of it.
+pub const BLOCK: u32 = 8;
+
+/// Side of the tiles, in pixels.
+pub const TILE_SIZE: u32 = 128;
+
+/// Pixels times iterations per pixel a frame may iterate. Up to about 240 iterations 1920x1080 takes one frame,
+/// so that moving around at the default iterations stays sharp.
+pub const PIXEL_ITERATIONS_PER_FRAME: f32 = 5e8;
+
+/// `Progress` of `mandelbrot.frag` and `colorize.frag`.
+#[repr(C)]
+#[derive(Clone, Copy, AsBytes, FromBytes)]
+pub struct ProgressUniform {
+	/// Top left corner of the first tile, in pixels of the window.
+	pub origin: [i32; 2],
+	/// Whether this is the coarse pass, which `mandelbrot.frag` discards all but the corners of the blocks in.
+	pub coarse: i32,
+	pub block: i32,
+	pub tile_size: i32,
+	pub tiles_per_row: i32,
+	/// Tiles at full resolution so far, in rows from the top.
+	pub tiles_done: i32,
+}
+
+pub const PROGRESS_SIZE: wgpu::BufferAddress = std::mem::size_of::<ProgressUniform>() as wgpu::BufferAddress;
+
+/// What the next frame has `mandelbrot.frag` draw.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pass {
+	/// Everything at once.
+	Whole,
+	/// One pixel of every block, into cleared targets.
+	Coarse,
+	/// The tiles in this range, which the scissor rects of `Progress::tile` keep the drawing to.
+	Tiles(u32, u32),
+	/// Nothing, the iteration texture is complete.
+	Done,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+	/// Top left corner of the tiles in pixels of the window, and the bottom right one.
+	origin: [u32; 2],
+	end: [u32; 2],
+	tiles_per_row: u32,
+	tiles: u32,
+	tiles_per_frame: u32,
+	tiles_done: u32,
+	/// Whether the coarse pass was drawn already, and whether it is the one being drawn.
+	coarse_drawn: bool,
+	coarse: bool,
+}
+
+impl Progress {
+	/// Starts over for the viewport `viewport` in pixels, see `FractalViewData::viewport_pixels`,
+	/// at `iterations` per pixel. With `progressive` off the whole viewport is drawn in the next frame.
+	pub fn new(viewport: [f32; 4], iterations: f32, progressive: bool) -> Self {
+		let origin = [viewport[0].round() as u32, viewport[1].round() as u32];
+		let end = [viewport[2].round() as u32, viewport[3].round() as u32];
+		let tiles_in = |from: u32, to: u32| (to.saturating_sub(from) + TILE_SIZE - 1) / TILE_SIZE;
+		let tiles_per_row = tiles_in(origin[0], end[0]);
+		let tiles = tiles_per_row * tiles_in(origin[1], end[1]);
+		let pixels_per_frame = PIXEL_ITERATIONS_PER_FRAME / iterations.max(1.0);
+		let tiles_per_frame = if progressive {
+			((pixels_per_frame / (TILE_SIZE * TILE_SIZE) as f32) as u32).max(1)
+		} else {
+			tiles
+		};
+		Self {
+			origin,
+			end,
+			tiles_per_row,
+			tiles,
+			tiles_per_frame,
+			tiles_done: 0,
+			coarse_drawn: false,
+			coarse: false,
+		}
+	}
+
+	/// Moves on to the next frame and returns what it draws.
+	pub fn next(&mut self) -> Pass {
+		self.coarse = false;
+		if !self.is_refining() {
+			return Pass::Done;
+		}
+		if self.tiles_per_frame >= self.tiles {
+			self.tiles_done = self.tiles;
+			return Pass::Whole;
+		}
+		if !self.coarse_drawn {
+			self.coarse_drawn = true;
+			self.coarse = true;
+			return Pass::Coarse;
+		}
+		let from = self.tiles_done;
+		self.tiles_done = (from + self.tiles_per_frame).min(self.tiles);
+		Pass::Tiles(from, self.tiles_done)
+	}
+
+	/// The scissor rect of tile `index`: x, y, width and height in pixels of the window.
+	pub fn tile(&self, index: u32) -> [u32; 4] {
+		let x = self.origin[0] + index % self.tiles_per_row * TILE_SIZE;
+		let y = self.origin[1] + index / self.tiles_per_row * TILE_SIZE;
+		[x, y, TILE_SIZE.min(self.end[0] - x), TILE_SIZE.min(self.end[1] - y)]
+	}
+
+	/// Whether frames are still left to draw.
+	pub fn is_refining(&self) -> bool {
+		self.tiles_done < self.tiles
+	}
+
+	/// Fraction of the tiles drawn at full resolution, for the HUD.
+	pub fn fraction(&self) -> f32 {
+		if self.tiles == 0 {
+			1.0
+		} else {
+			self.tiles_done as f32 / self.tiles as f32
+		}
+	}
+
+	pub fn uniform(&self) -> ProgressUniform {
+		ProgressUniform {
+			origin: [self.origin[0] as i32, self.origin[1] as i32],
+			coarse: self.coarse as i32,
+			block: BLOCK as i32,
+			tile_size: TILE_SIZE as i32,
+			tiles_per_row: self.tiles_per_row as i32,
+			tiles_done: self.tiles_done as i32,
+		}
+	}
+}
+
+/// Of an empty viewport, which there is nothing to draw of.
+impl Default for Progress {
+	fn default() -> Self {
+		Self::new([0.0; 4], 1.0, false)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Not a multiple of `TILE_SIZE` and not at the corner of the window, as the Julia half of the double view.
+	const VIEWPORT: [f32; 4] = [100.0, 50.0, 1000.0, 650.0];
+
+	#[test]
+	fn tiles_cover_the_viewport_exactly_once() {
+		// Three tiles a frame.
+		let mut progress = Progress::new(VIEWPORT, 10000.0, true);
+		assert_eq!(progress.next(), Pass::Coarse);
+		assert!(progress.uniform().coarse != 0);
+		assert_eq!(progress.fraction(), 0.0);
+
+		let mut drawn = vec![0u8; 1000 * 650];
+		let mut next_tile = 0;
+		let mut fraction = 0.0;
+		loop {
+			match progress.next() {
+				Pass::Tiles(from, to) => {
+					assert_eq!(from, next_tile);
+					assert!(to > from && to - from <= 3);
+					for index in from..to {
+						let [x, y, width, height] = progress.tile(index);
+						for y in y..y + height {
+							for x in x..x + width {
+								drawn[(y * 1000 + x) as usize] += 1;
+							}
+						}
+					}
+					next_tile = to;
+					assert_eq!(progress.uniform().coarse, 0);
+					assert!(progress.fraction() > fraction);
+					fraction = progress.fraction();
+				}
+				Pass::Done => break,
+				pass => panic!("{:?} after the coarse pass", pass),
+			}
+		}
+		assert_eq!(fraction, 1.0);
+		assert!(!progress.is_refining());
+		assert_eq!(progress.next(), Pass::Done);
+
+		for y in 0..650 {
+			for x in 0..1000 {
+				let inside = x >= 100 && y >= 50;
+				assert_eq!(drawn[y * 1000 + x], inside as u8, "pixel {}, {}", x, y);
+			}
+		}
+	}
+
+	#[test]
+	fn draws_whatever_fits_into_a_frame_at_once() {
+		let mut progress = Progress::new(VIEWPORT, 100.0, true);
+		assert_eq!(progress.next(), Pass::Whole);
+		assert_eq!(progress.fraction(), 1.0);
+		assert_eq!(progress.next(), Pass::Done);
+	}
+
+	#[test]
+	fn draws_everything_at_once_unless_progressive() {
+		let mut progress = Progress::new(VIEWPORT, 10000.0, false);
+		assert_eq!(progress.next(), Pass::Whole);
+		assert_eq!(progress.next(), Pass::Done);
+	}
+
+	#[test]
+	fn empty_viewports_are_done() {
+		let mut progress = Progress::default();
+		assert!(!progress.is_refining());
+		assert_eq!(progress.next(), Pass::Done);
+		assert_eq!(progress.fraction(), 1.0);
+	}
+}
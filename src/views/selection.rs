@@ -0,0 +1,253 @@
+//! Rectangle drawn over a view while dragging with the right mouse button.
+
+use super::prelude::*;
+use crate::utils::Center;
+
+pub struct Selection {
+	pipeline: wgpu::RenderPipeline,
+	vertices: Option<wgpu::Buffer>,
+	/// Where the drag started, `None` when not selecting.
+	pub start: Option<[f64; 2]>,
+	pub end: [f64; 2],
+	/// Fit the current view into the rectangle instead of the rectangle into the view.
+	pub zoom_out: bool,
+}
+
+impl Selection {
+	pub fn new(device: &wgpu::Device, vs_module: &wgpu::ShaderModule) -> Self {
+		let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			bind_group_layouts: &[],
+		});
+		let fs_module = device.create_shader_module(&*OVERLAY_FRAG_SHADER);
+
+		let pipeline = device.create_render_pipeline(
+			&wgpu::RenderPipelineDescriptor {
+				layout: &pipeline_layout,
+				vertex_stage: wgpu::ProgrammableStageDescriptor {
+					module: vs_module,
+					entry_point: "main",
+				},
+				fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+					module: &fs_module,
+					entry_point: "main",
+				}),
+				rasterization_state: Some(wgpu::RasterizationStateDescriptor {
+					front_face: wgpu::FrontFace::Ccw,
+					cull_mode: wgpu::CullMode::None,
+					depth_bias: 0,
+					depth_bias_slope_scale: 0.0,
+					depth_bias_clamp: 0.0,
+				}),
+				primitive_topology: wgpu::PrimitiveTopology::LineStrip,
+				color_states: &[wgpu::ColorStateDescriptor {
+					format: wgpu::TextureFormat::Bgra8UnormSrgb,
+					color_blend: wgpu::BlendDescriptor::REPLACE,
+					alpha_blend: wgpu::BlendDescriptor::REPLACE,
+					write_mask: wgpu::ColorWrite::ALL,
+				}],
+				depth_stencil_state: None,
+				index_format: wgpu::IndexFormat::Uint32,
+				vertex_buffers: &[wgpu::VertexBufferDescriptor {
+					stride: *VERTEX_SIZE,
+					step_mode: wgpu::InputStepMode::Vertex,
+					attributes: &[wgpu::VertexAttributeDescriptor {
+						format: wgpu::VertexFormat::Float2,
+						offset: 0,
+						shader_location: 0,
+					}],
+				}],
+				sample_count: 1,
+				sample_mask: !0,
+				alpha_to_coverage_enabled: false,
+			}
+		);
+
+		Self {
+			pipeline,
+			vertices: None,
+			start: None,
+			end: [0.0, 0.0],
+			zoom_out: false,
+		}
+	}
+
+	pub fn begin(&mut self, position: [f64; 2], zoom_out: bool) {
+		log::info!("Starting selection at {:?}, zooming out: {:?}", position, zoom_out);
+		self.start = Some(position);
+		self.end = position;
+		self.zoom_out = zoom_out;
+		self.vertices = None;
+	}
+
+	/// Moves the corner under the cursor, `window_size` being the size the positions are relative to.
+	pub fn update(&mut self, device: &wgpu::Device, position: [f64; 2], window_size: &WindowSize) {
+		let start = match self.start {
+			Some(start) => start,
+			None => return
+		};
+		self.end = position;
+
+		let to_ndc = |p: [f64; 2]| [
+			(2.0 * p[0] / f64::from(window_size.size[0]) - 1.0) as f32,
+			(1.0 - 2.0 * p[1] / f64::from(window_size.size[1])) as f32,
+		];
+		let (a, b) = (to_ndc(start), to_ndc(position));
+		let outline = [
+			Vertex { pos: [a[0], a[1]] },
+			Vertex { pos: [b[0], a[1]] },
+			Vertex { pos: [b[0], b[1]] },
+			Vertex { pos: [a[0], b[1]] },
+			Vertex { pos: [a[0], a[1]] },
+		];
+		self.vertices = Some(
+			device.create_buffer_mapped(
+				outline.len(),
+				wgpu::BufferUsage::VERTEX
+			).fill_from_slice(&outline)
+		);
+	}
+
+	/// Ends the drag, returning the corners unless the rectangle is too small to mean anything.
+	pub fn finish(&mut self) -> Option<([f64; 2], [f64; 2])> {
+		let start = self.start.take()?;
+		self.vertices = None;
+		let end = self.end;
+		if (end[0] - start[0]).abs() < 4.0 || (end[1] - start[1]).abs() < 4.0 {
+			log::info!("Selection too small, ignoring");
+			return None;
+		}
+
+		Some((start, end))
+	}
+
+	pub fn render(&self, encoder: &mut wgpu::CommandEncoder, target: &wgpu::TextureView) {
+		let vertices = match &self.vertices {
+			Some(vertices) => vertices,
+			None => return
+		};
+
+		let mut rpass = encoder.begin_render_pass(
+			&wgpu::RenderPassDescriptor {
+				color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+					attachment: target,
+					resolve_target: None,
+					load_op: wgpu::LoadOp::Load,
+					store_op: wgpu::StoreOp::Store,
+					clear_color: wgpu::Color::BLACK
+				}],
+				depth_stencil_attachment: None,
+			}
+		);
+		rpass.set_pipeline(&self.pipeline);
+		rpass.set_vertex_buffers(0, &[(vertices, 0)]);
+		rpass.draw(0..5, 0..1);
+	}
+}
+
+/// The zoom and center after selecting from `start` to `end` in a window of `size`, drawing into `viewport`,
+/// all in pixels, when at `zoom` around `center`.
+pub fn fit(
+	center: &Center,
+	zoom: f64,
+	start: [f64; 2],
+	end: [f64; 2],
+	size: [f32; 2],
+	viewport: [f32; 4],
+	zoom_out: bool
+) -> (f64, Center) {
+	let half = [f64::from(size[0]) / 2.0, f64::from(size[1]) / 2.0];
+	// Both relative to the middle of the window, where `center` is.
+	let selected = [(start[0] + end[0]) / 2.0 - half[0], (start[1] + end[1]) / 2.0 - half[1]];
+	let middle = [
+		f64::from(viewport[0] + viewport[2]) / 2.0 - half[0],
+		f64::from(viewport[1] + viewport[3]) / 2.0 - half[1],
+	];
+	let ratios = [
+		(end[0] - start[0]).abs() / f64::from(viewport[2] - viewport[0]),
+		(end[1] - start[1]).abs() / f64::from(viewport[3] - viewport[1]),
+	];
+
+	if zoom_out {
+		// The smaller ratio keeps the aspect ratio while fitting all of the view in.
+		let new_zoom = zoom / ratios[0].min(ratios[1]);
+		// What is in the middle of the view now moves to the middle of the selection.
+		let center = center
+			.offset(middle, zoom)
+			.offset([-selected[0], -selected[1]], new_zoom);
+		(new_zoom, center)
+	} else {
+		// The bigger ratio keeps the aspect ratio while fitting all of the selection in.
+		let new_zoom = zoom * ratios[0].max(ratios[1]);
+		// The middle of the selection moves to the middle of the view.
+		let center = center
+			.offset(selected, zoom)
+			.offset([-middle[0], -middle[1]], new_zoom);
+		(new_zoom, center)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const SIZE: [f32; 2] = [400.0, 300.0];
+	const WHOLE: [f32; 4] = [0.0, 0.0, 400.0, 300.0];
+
+	/// Where the pixel `p` of the window points at, see `Center::offset`.
+	fn point(center: &Center, zoom: f64, p: [f64; 2]) -> [f64; 2] {
+		let position = center.offset([p[0] - 200.0, p[1] - 150.0], zoom).position().pos;
+		[-position[0], -position[1]]
+	}
+
+	fn assert_close(a: [f64; 2], b: [f64; 2]) {
+		assert!((a[0] - b[0]).abs() < 1e-12 && (a[1] - b[1]).abs() < 1e-12, "{:?} != {:?}", a, b);
+	}
+
+	#[test]
+	fn zooming_in_fills_the_view_with_the_selection() {
+		let center = Center::new([-0.5, 0.25]);
+		let (start, end) = ([100.0, 75.0], [300.0, 225.0]);
+		let (zoom, fitted) = fit(&center, 0.01, start, end, SIZE, WHOLE, false);
+		assert_eq!(zoom, 0.005);
+		assert_close(point(&fitted, zoom, [0.0, 0.0]), point(&center, 0.01, start));
+		assert_close(point(&fitted, zoom, [400.0, 300.0]), point(&center, 0.01, end));
+	}
+
+	#[test]
+	fn zooming_in_keeps_the_aspect_ratio() {
+		let center = Center::new([0.0, 0.0]);
+		// Wider than the window, so the selection fills its width and is centered vertically.
+		let (start, end) = ([50.0, 100.0], [250.0, 120.0]);
+		let (zoom, fitted) = fit(&center, 0.01, start, end, SIZE, WHOLE, false);
+		assert_eq!(zoom, 0.005);
+		assert_close(point(&fitted, zoom, [0.0, 150.0]), point(&center, 0.01, [50.0, 110.0]));
+		assert_close(point(&fitted, zoom, [400.0, 150.0]), point(&center, 0.01, [250.0, 110.0]));
+	}
+
+	#[test]
+	fn zooming_out_fits_the_view_into_the_selection() {
+		let center = Center::new([-0.5, 0.25]);
+		let (start, end) = ([100.0, 75.0], [300.0, 225.0]);
+		let (zoom, fitted) = fit(&center, 0.01, start, end, SIZE, WHOLE, true);
+		assert_eq!(zoom, 0.02);
+		assert_close(point(&fitted, zoom, start), point(&center, 0.01, [0.0, 0.0]));
+		assert_close(point(&fitted, zoom, end), point(&center, 0.01, [400.0, 300.0]));
+
+		// Selecting the same rectangle again zooms back in to where it started.
+		let (zoom, back) = fit(&fitted, zoom, start, end, SIZE, WHOLE, false);
+		assert_eq!(zoom, 0.01);
+		assert_close(point(&back, zoom, [200.0, 150.0]), point(&center, 0.01, [200.0, 150.0]));
+	}
+
+	#[test]
+	fn fits_into_the_viewport_of_half_a_window() {
+		let center = Center::new([0.0, 0.0]);
+		// The right half, as the Julia set of the double view.
+		let viewport = [200.0, 0.0, 400.0, 300.0];
+		let (start, end) = ([250.0, 100.0], [350.0, 250.0]);
+		let (zoom, fitted) = fit(&center, 0.01, start, end, SIZE, viewport, false);
+		assert_eq!(zoom, 0.005);
+		assert_close(point(&fitted, zoom, [300.0, 0.0]), point(&center, 0.01, [300.0, 100.0]));
+		assert_close(point(&fitted, zoom, [300.0, 300.0]), point(&center, 0.01, [300.0, 250.0]));
+	}
+}
@@ -2,25 +2,113 @@
 //! and passes events to the manager dependent on its `current` property.
 
 use super::prelude::*;
-use super::{FractalViewManager, MandelbrotViewManager, DoubleViewManager};
-use wgpu::{Device, SwapChainOutput, CommandBuffer};
-use winit::event::{MouseButton, ElementState};
+use super::{FractalViewManager, MandelbrotViewManager, DoubleViewManager, NewtonViewManager, BuddhabrotViewManager, LyapunovViewManager};
+use wgpu::{Device, TextureView, CommandBuffer};
+use winit::event::{MouseButton, ElementState, ModifiersState};
 use winit::dpi::{PhysicalSize, LogicalSize};
 use crate::utils::CurrentView;
+use crate::cli::View;
 
 
 pub struct SwitchableViewManager {
 	single: Arc<Mutex<MandelbrotViewManager>>,
 	double: Arc<Mutex<DoubleViewManager>>,
+	newton: Arc<Mutex<NewtonViewManager>>,
+	buddhabrot: Arc<Mutex<BuddhabrotViewManager>>,
+	lyapunov: Arc<Mutex<LyapunovViewManager>>,
 	pub current: CurrentView
 }
 
+/// Calls `$method` on the manager of the current view.
+macro_rules! current {
+	($self:ident.$method:ident($($arg:expr),*)) => {
+		match $self.current {
+			CurrentView::Single => $self.single.lock().unwrap().$method($($arg),*),
+			CurrentView::Double => $self.double.lock().unwrap().$method($($arg),*),
+			CurrentView::Newton => $self.newton.lock().unwrap().$method($($arg),*),
+			CurrentView::Buddhabrot => $self.buddhabrot.lock().unwrap().$method($($arg),*),
+			CurrentView::Lyapunov => $self.lyapunov.lock().unwrap().$method($($arg),*),
+		}
+	};
+}
+
+/// Calls `$method` on the managers of all views, so that what it sets sticks when switching between them.
+/// The arguments are evaluated once per view. Returns the command buffers of all of them, see `Gathered`.
+macro_rules! all {
+	($self:ident.$method:ident($($arg:expr),*)) => {{
+		let mut buf = Vec::new();
+		$self.single.lock().unwrap().$method($($arg),*).gather(&mut buf);
+		$self.double.lock().unwrap().$method($($arg),*).gather(&mut buf);
+		$self.newton.lock().unwrap().$method($($arg),*).gather(&mut buf);
+		$self.buddhabrot.lock().unwrap().$method($($arg),*).gather(&mut buf);
+		$self.lyapunov.lock().unwrap().$method($($arg),*).gather(&mut buf);
+		buf
+	}};
+}
+
+/// What `all!` collects from every manager, nothing for the methods that don't record any commands.
+trait Gathered {
+	fn gather(self, buf: &mut Vec<CommandBuffer>);
+}
+
+impl Gathered for Vec<CommandBuffer> {
+	fn gather(self, buf: &mut Vec<CommandBuffer>) {
+		buf.extend(self);
+	}
+}
+
+impl Gathered for () {
+	fn gather(self, _buf: &mut Vec<CommandBuffer>) {}
+}
+
 impl SwitchableViewManager {
-	pub fn init(single: Arc<Mutex<MandelbrotViewManager>>, double: Arc<Mutex<DoubleViewManager>>, current: CurrentView) -> Self {
-		Self {
+	pub fn init(
+		single: Arc<Mutex<MandelbrotViewManager>>,
+		double: Arc<Mutex<DoubleViewManager>>,
+		newton: Arc<Mutex<NewtonViewManager>>,
+		buddhabrot: Arc<Mutex<BuddhabrotViewManager>>,
+		lyapunov: Arc<Mutex<LyapunovViewManager>>,
+		view: View
+	) -> Self {
+		let mut manager = Self {
 			single,
 			double,
-			current
+			newton,
+			buddhabrot,
+			lyapunov,
+			current: view.current_view()
+		};
+		manager.set_view(view);
+		manager
+	}
+
+	/// Switches to `view`, which the Buddhabrot needs to know about to tell itself from the Nebulabrot.
+	/// Julia sets are a matter of `FractalParams::julia`.
+	pub fn set_view(&mut self, view: View) {
+		self.current = view.current_view();
+		match view {
+			View::Buddhabrot => self.buddhabrot.lock().unwrap().set_nebulabrot(false),
+			View::Nebulabrot => self.buddhabrot.lock().unwrap().set_nebulabrot(true),
+			_ => ()
+		}
+	}
+
+	/// Which of the views from the command line is showing.
+	pub fn view(&mut self) -> View {
+		match self.current {
+			CurrentView::Double => View::Double,
+			CurrentView::Newton => View::Newton,
+			CurrentView::Lyapunov => View::Lyapunov,
+			CurrentView::Buddhabrot => if self.buddhabrot.lock().unwrap().is_nebulabrot() {
+				View::Nebulabrot
+			} else {
+				View::Buddhabrot
+			},
+			CurrentView::Single => if self.params().julia.is_julia {
+				View::Julia
+			} else {
+				View::Single
+			},
 		}
 	}
 }
@@ -30,76 +118,124 @@ impl FractalViewManager for SwitchableViewManager where {
 		unimplemented!()
 	}
 
-	fn render(&mut self, device: &Arc<Mutex<Device>>, frame: &SwapChainOutput) -> Vec<CommandBuffer> {
-		if self.current == CurrentView::Double {
-			self.double.lock().unwrap().render(device, frame)
-		} else {
-			self.single.lock().unwrap().render(device, frame)
-		}
+	fn render(&mut self, device: &Arc<Mutex<Device>>, target: &TextureView) -> Vec<CommandBuffer> {
+		current!(self.render(device, target))
 	}
 
 	fn resized(&mut self, device: &Arc<Mutex<Device>>, window_size: &WindowSize) -> Vec<CommandBuffer> {
-		if self.current == CurrentView::Double {
-			self.double.lock().unwrap().resized(device, window_size)
-		} else {
-			self.single.lock().unwrap().resized(device, window_size)
-		}
+		current!(self.resized(device, window_size))
 	}
 
-	fn mouse_input(&mut self, button: MouseButton, state: ElementState) {
-		if self.current == CurrentView::Double {
-			self.double.lock().unwrap().mouse_input(button, state)
-		} else {
-			self.single.lock().unwrap().mouse_input(button, state)
-		}
+	fn mouse_input(&mut self, device: &Arc<Mutex<Device>>, button: MouseButton, state: ElementState, modifiers: ModifiersState) -> Option<Vec<CommandBuffer>> {
+		current!(self.mouse_input(device, button, state, modifiers))
 	}
 
-	fn iterations(&mut self, device: &Arc<Mutex<Device>>, y_delta: f32) -> Vec<CommandBuffer> {
-		if self.current == CurrentView::Double {
-			self.double.lock().unwrap().iterations(device, y_delta)
-		} else {
-			self.single.lock().unwrap().iterations(device, y_delta)
-		}
+	/// Going by hand turns the automatic iterations off in all views, not just in the one that changes.
+	fn iterations(&mut self, device: &Arc<Mutex<Device>>, factor: f32) -> Vec<CommandBuffer> {
+		self.set_auto_iterations(false);
+		current!(self.iterations(device, factor))
 	}
 
 	fn set_julia(&mut self, device: &Arc<Mutex<Device>>, state: bool) -> Option<Vec<CommandBuffer>> {
-		if self.current == CurrentView::Double {
-			self.double.lock().unwrap().set_julia(device, state)
-		} else {
-			self.single.lock().unwrap().set_julia(device, state)
+		current!(self.set_julia(device, state))
+	}
+
+	/// Goes to the Mandelbrot and the double view, the only ones of the five that iterate a formula of choice,
+	/// so that it sticks when switching between them.
+	fn set_formula(&mut self, device: &Arc<Mutex<Device>>, formula: Formula) -> Vec<CommandBuffer> {
+		let mut buf = self.single.lock().unwrap().set_formula(device, formula);
+		buf.extend(self.double.lock().unwrap().set_formula(device, formula));
+		buf
+	}
+
+	/// Goes to the same two views as `set_formula`, to both or neither of them.
+	fn set_custom_formula(&mut self, device: &Arc<Mutex<Device>>, custom_formula: Option<CustomFormula>) -> Result<(), String> {
+		let previous = self.single.lock().unwrap().params().custom_formula;
+		self.single.lock().unwrap().set_custom_formula(device, custom_formula.clone())?;
+		let result = self.double.lock().unwrap().set_custom_formula(device, custom_formula);
+		if result.is_err() {
+			// It compiled before, so it does again.
+			let _ = self.single.lock().unwrap().set_custom_formula(device, previous);
 		}
+		result
+	}
+
+	fn set_palette(&mut self, device: &Arc<Mutex<Device>>, palette: Palette) -> Vec<CommandBuffer> {
+		all!(self.set_palette(device, palette.clone()))
+	}
+
+	fn set_coloring(&mut self, device: &Arc<Mutex<Device>>, coloring: Coloring) -> Vec<CommandBuffer> {
+		all!(self.set_coloring(device, coloring.clone()))
+	}
+
+	fn set_trap(&mut self, device: &Arc<Mutex<Device>>, trap: Trap) -> Vec<CommandBuffer> {
+		all!(self.set_trap(device, trap))
+	}
+
+	fn set_average(&mut self, device: &Arc<Mutex<Device>>, average: Average) -> Vec<CommandBuffer> {
+		all!(self.set_average(device, average))
+	}
+
+	fn set_lighting(&mut self, device: &Arc<Mutex<Device>>, lighting: Lighting) -> Vec<CommandBuffer> {
+		all!(self.set_lighting(device, lighting))
 	}
 
 	fn zoom(&mut self, device: &Arc<Mutex<Device>>, y_delta: f32) -> Vec<CommandBuffer> {
-		if self.current == CurrentView::Double {
-			self.double.lock().unwrap().zoom(device, y_delta)
-		} else {
-			self.single.lock().unwrap().zoom(device, y_delta)
-		}
+		current!(self.zoom(device, y_delta))
+	}
+
+	fn params(&mut self) -> FractalParams {
+		current!(self.params())
+	}
+
+	/// All of `params` goes to the current view, the others keep where they are and take the rest,
+	/// so that the palette and the coloring of a location or the command line stay when switching views.
+	fn set_params(&mut self, device: &Arc<Mutex<Device>>, params: &FractalParams) -> Vec<CommandBuffer> {
+		let mut buf = all!(self.set_shared_params(device, params));
+		buf.extend(current!(self.set_params(device, params)));
+		buf
 	}
 
 	fn new_position(&mut self, device: &Arc<Mutex<Device>>, x: f32, y: f32, active: bool) -> Option<Vec<CommandBuffer>> {
-		if self.current == CurrentView::Double {
-			self.double.lock().unwrap().new_position(device, x, y, active)
-		} else {
-			self.single.lock().unwrap().new_position(device, x, y, active)
-		}
+		current!(self.new_position(device, x, y, active))
+	}
+
+	fn undo(&mut self, device: &Arc<Mutex<Device>>) -> Option<Vec<CommandBuffer>> {
+		current!(self.undo(device))
+	}
+
+	fn redo(&mut self, device: &Arc<Mutex<Device>>) -> Option<Vec<CommandBuffer>> {
+		current!(self.redo(device))
+	}
+
+	/// Goes to the Mandelbrot and the double view, the other three have no perturbation.
+	fn set_series_approximation(&mut self, state: bool) {
+		self.single.lock().unwrap().set_series_approximation(state);
+		self.double.lock().unwrap().set_series_approximation(state);
+	}
+
+	fn set_auto_iterations(&mut self, state: bool) {
+		all!(self.set_auto_iterations(state));
+	}
+
+	fn skipped_iterations(&mut self) -> Option<usize> {
+		current!(self.skipped_iterations())
+	}
+
+	fn is_refining(&mut self) -> bool {
+		current!(self.is_refining())
+	}
+
+	fn progress(&mut self) -> Option<f32> {
+		current!(self.progress())
 	}
 
 	fn create_render_pipeline(&mut self, device: &Device) {
-		if self.current == CurrentView::Double {
-			self.double.lock().unwrap().create_render_pipeline(device)
-		} else {
-			self.single.lock().unwrap().create_render_pipeline(device)
-		}
+		current!(self.create_render_pipeline(device))
 	}
 
-	fn reload_fs(&mut self, device: &Arc<Mutex<Device>>) {
-		if self.current == CurrentView::Double {
-			self.double.lock().unwrap().reload_fs(device)
-		} else {
-			self.single.lock().unwrap().reload_fs(device)
-		}
+	fn reload_fs(&mut self, device: &Arc<Mutex<Device>>) -> Result<(), String> {
+		current!(self.reload_fs(device))
 	}
 }
 
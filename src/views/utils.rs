@@ -8,7 +8,24 @@ pub use crate::utils::{
 	Iterations, ITERATIONS_SIZE,
 	Vertex, VERTEX_SIZE,
 	Julia, JULIA_SIZE,
+	Formula, FORMULA_SIZE,
+	ROOTS_SIZE,
+	LYAPUNOV_SIZE,
+	COLORING_SIZE,
+	TRAP_SIZE,
+	AVERAGE_SIZE,
+	LIGHTING_SIZE,
+	Center,
 };
+pub use crate::polynomial::Polynomial;
+pub use crate::lyapunov::Lyapunov;
+pub use crate::palette::{Palette, PALETTE_SIZE};
+pub use crate::coloring::Coloring;
+pub use crate::trap::Trap;
+pub use crate::average::Average;
+pub use crate::lighting::Lighting;
+pub use crate::perturbation::{Perturbation, PERTURBATION_SIZE, ORBITS_SIZE};
+pub use super::progress::{Progress, PROGRESS_SIZE};
 
 pub const ZOOM_SENSITIVITY: f32 = 0.9;
 
@@ -20,6 +37,128 @@ pub fn create_buffer<T: 'static + Copy>(device: &wgpu::Device, value: T) -> wgpu
 	).fill_from_slice(&[value])
 }
 
+/// Records a copy of `value` into `target` on the `encoder`.
+pub fn copy_to_buffer<T: 'static + Copy>(
+	device: &wgpu::Device,
+	encoder: &mut wgpu::CommandEncoder,
+	value: T,
+	target: &wgpu::Buffer
+) {
+	let temp_buf = device.create_buffer_mapped(
+		1,
+		wgpu::BufferUsage::COPY_SRC
+	).fill_from_slice(&[value]);
+
+	encoder.copy_buffer_to_buffer(
+		&temp_buf,
+		0,
+		target,
+		0,
+		std::mem::size_of::<T>() as wgpu::BufferAddress
+	);
+}
+
+/// Records an upload of the RGBA `texels` of `Palette::texels` into the palette texture.
+pub fn copy_to_texture(
+	device: &wgpu::Device,
+	encoder: &mut wgpu::CommandEncoder,
+	texels: &[u8],
+	target: &wgpu::Texture
+) {
+	let temp_buf = device.create_buffer_mapped(
+		texels.len(),
+		wgpu::BufferUsage::COPY_SRC
+	).fill_from_slice(texels);
+
+	encoder.copy_buffer_to_texture(
+		wgpu::BufferCopyView {
+			buffer: &temp_buf,
+			offset: 0,
+			// A single row, 256 texels of 4 bytes are as aligned as rows need to be.
+			row_pitch: PALETTE_SIZE * 4,
+			image_height: 1,
+		},
+		wgpu::TextureCopyView {
+			texture: target,
+			mip_level: 0,
+			array_layer: 0,
+			origin: wgpu::Origin3d { x: 0.0, y: 0.0, z: 0.0 },
+		},
+		wgpu::Extent3d {
+			width: PALETTE_SIZE,
+			height: 1,
+			depth: 1,
+		}
+	);
+}
+
+/// Pipeline drawing the vertices of a view with the fragment shader `fs_module` into targets of `formats`.
+pub fn create_render_pipeline(
+	device: &wgpu::Device,
+	layout: &wgpu::PipelineLayout,
+	vs_module: &wgpu::ShaderModule,
+	fs_module: &wgpu::ShaderModule,
+	formats: &[wgpu::TextureFormat]
+) -> wgpu::RenderPipeline {
+	log::info!("Creating render pipeline");
+	let color_states: Vec<wgpu::ColorStateDescriptor> = formats.iter()
+		.map(|&format| wgpu::ColorStateDescriptor {
+			format,
+			color_blend: wgpu::BlendDescriptor::REPLACE,
+			alpha_blend: wgpu::BlendDescriptor::REPLACE,
+			write_mask: wgpu::ColorWrite::ALL,
+		})
+		.collect();
+	device.create_render_pipeline(
+		&wgpu::RenderPipelineDescriptor {
+			layout,
+			vertex_stage: wgpu::ProgrammableStageDescriptor {
+				module: vs_module,
+				entry_point: "main",
+			},
+			fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+				module: fs_module,
+				entry_point: "main",
+			}),
+			rasterization_state: Some(wgpu::RasterizationStateDescriptor {
+				front_face: wgpu::FrontFace::Ccw,
+				cull_mode: wgpu::CullMode::None,
+				depth_bias: 0,
+				depth_bias_slope_scale: 0.0,
+				depth_bias_clamp: 0.0,
+			}),
+			primitive_topology: wgpu::PrimitiveTopology::TriangleStrip,
+			color_states: &color_states,
+			depth_stencil_state: None,
+			index_format: wgpu::IndexFormat::Uint32,
+			vertex_buffers: &[wgpu::VertexBufferDescriptor {
+				stride: *VERTEX_SIZE,
+				step_mode: wgpu::InputStepMode::Vertex,
+				attributes: &[wgpu::VertexAttributeDescriptor {
+					format: wgpu::VertexFormat::Float2,
+					offset: 0,
+					shader_location: 0,
+				}],
+			}],
+			sample_count: 1,
+			sample_mask: !0,
+			alpha_to_coverage_enabled: false,
+		}
+	)
+}
+
+/// Part of the window covered by `vertices`, as fractions of its size: left, top, right, bottom.
+pub fn viewport(vertices: &[Vertex]) -> [f32; 4] {
+	let xs = vertices.iter().map(|vertex| (vertex.pos[0] + 1.0) / 2.0);
+	let ys = vertices.iter().map(|vertex| (1.0 - vertex.pos[1]) / 2.0);
+	[
+		xs.clone().fold(1.0, f32::min),
+		ys.clone().fold(1.0, f32::min),
+		xs.fold(0.0, f32::max),
+		ys.fold(0.0, f32::max),
+	]
+}
+
 lazy_static! {
 	pub static ref WHOLE_VERTICES: Vec<Vertex> = vec![
 		Vertex{pos: [1f32, 1f32]},
@@ -43,6 +182,10 @@ lazy_static! {
 	];
 }
 use super::prelude::*;
+use super::selection::Selection;
+use super::history::History;
+use super::perturber::Perturber;
+use super::colorize::{Colorizer, ITERATION_FORMATS};
 
 /// Creates data for each `FractalViewManager`.
 /// Takes care of whether the view is a Julia set and given
@@ -52,6 +195,32 @@ pub fn new(
 	size: dpi::LogicalSize,
 	is_julia: bool,
 	vertices: Vec<Vertex>
+) -> FractalViewData {
+	create(device, size, is_julia, vertices, &*FRAG_SHADER_INIT, Center::default(), true)
+}
+
+/// Like `new`, drawn with the fragment shader `fs` instead of `mandelbrot.frag`
+/// and starting at `center`. Every shader gets the same bindings, using only the ones it needs.
+/// `fs` draws colors straight into the window, there is no `Colorizer`.
+pub fn new_with_shader(
+	device: &wgpu::Device,
+	size: dpi::LogicalSize,
+	is_julia: bool,
+	vertices: Vec<Vertex>,
+	fs: &[u32],
+	center: Center
+) -> FractalViewData {
+	create(device, size, is_julia, vertices, fs, center, false)
+}
+
+fn create(
+	device: &wgpu::Device,
+	size: dpi::LogicalSize,
+	is_julia: bool,
+	vertices: Vec<Vertex>,
+	fs: &[u32],
+	center: Center,
+	colorized: bool
 ) -> FractalViewData {
 	let window_size = WindowSize {
 		size: [size.width as f32, size.height as f32]
@@ -59,10 +228,9 @@ pub fn new(
 	let window_size_buf = create_buffer(&device, window_size);
 
 	let zoom = Zoom::default();
-	let zoom_buf = create_buffer(&device, zoom);
+	let zoom_buf = create_buffer(&device, zoom.uniform());
 
-	let pos = Position::default();
-	let position_buf = create_buffer(&device, pos);
+	let position_buf = create_buffer(&device, center.position().uniform());
 
 	let iterations = Iterations::default();
 	let iterations_buf = create_buffer(&device, iterations);
@@ -70,10 +238,68 @@ pub fn new(
 	let julia = Julia { is_julia };
 	let julia_buf = create_buffer(&device, julia);
 
+	let formula = Formula::default();
+	let formula_buf = create_buffer(&device, formula.uniform());
+
+	let polynomial = Polynomial::default();
+	let roots_buf = create_buffer(&device, polynomial.uniform());
+
+	let lyapunov = Lyapunov::default();
+	let lyapunov_buf = create_buffer(&device, lyapunov.uniform());
+
+	let trap = Trap::default();
+	let trap_buf = create_buffer(&device, trap.uniform());
+
+	let average = Average::default();
+	let average_buf = create_buffer(&device, average.uniform());
+
+	let lighting = Lighting::default();
+	let lighting_buf = create_buffer(&device, lighting.uniform());
+
+	let progress_buf = create_buffer(&device, Progress::default().uniform());
+
+	let palette = Palette::default();
+	let coloring = Coloring::default();
+	let coloring_buf = create_buffer(&device, coloring.uniform(&palette));
+	let palette_texture = device.create_texture(&wgpu::TextureDescriptor {
+		size: wgpu::Extent3d {
+			width: PALETTE_SIZE,
+			height: 1,
+			depth: 1,
+		},
+		array_layer_count: 1,
+		mip_level_count: 1,
+		sample_count: 1,
+		dimension: wgpu::TextureDimension::D1,
+		format: wgpu::TextureFormat::Rgba8UnormSrgb,
+		usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+	});
+	// For the views that color the pixels right away, like the colorize pass does for the others.
+	let palette_view = palette_texture.create_default_view();
+	let palette_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+		address_mode_u: wgpu::AddressMode::Repeat,
+		address_mode_v: wgpu::AddressMode::Repeat,
+		address_mode_w: wgpu::AddressMode::Repeat,
+		mag_filter: wgpu::FilterMode::Linear,
+		min_filter: wgpu::FilterMode::Linear,
+		mipmap_filter: wgpu::FilterMode::Nearest,
+		lod_min_clamp: 0.0,
+		lod_max_clamp: 0.0,
+		compare_function: wgpu::CompareFunction::Always,
+	});
+
+	let viewport = viewport(&vertices);
 	let vertices_data = vertices;
 
-	let generator = Position { pos: [size.width as f32/2f32, size.width as f32/2f32]};
-	let generator_buf = create_buffer(&device, generator);
+	let generator = Position { pos: [size.width/2f64, size.width/2f64]};
+	let generator_buf = create_buffer(&device, generator.uniform());
+
+	let perturbation_buf = create_buffer(&device, Perturbation::disabled().uniform);
+	let orbits_buf = device.create_buffer(&wgpu::BufferDescriptor {
+		size: *ORBITS_SIZE,
+		usage: wgpu::BufferUsage::STORAGE_READ
+			| wgpu::BufferUsage::COPY_DST
+	});
 
 	let vertex_buf = device.create_buffer_mapped(
 		4,
@@ -126,6 +352,83 @@ pub fn new(
 							dynamic: false
 						}
 					},
+					wgpu::BindGroupLayoutBinding {
+						binding: 6,
+						visibility: wgpu::ShaderStage::FRAGMENT,
+						ty: wgpu::BindingType::StorageBuffer {
+							dynamic: false,
+							readonly: true
+						}
+					},
+					wgpu::BindGroupLayoutBinding {
+						binding: 7,
+						visibility: wgpu::ShaderStage::FRAGMENT,
+						ty: wgpu::BindingType::UniformBuffer {
+							dynamic: false
+						}
+					},
+					wgpu::BindGroupLayoutBinding {
+						binding: 8,
+						visibility: wgpu::ShaderStage::FRAGMENT,
+						ty: wgpu::BindingType::UniformBuffer {
+							dynamic: false
+						}
+					},
+					wgpu::BindGroupLayoutBinding {
+						binding: 9,
+						visibility: wgpu::ShaderStage::FRAGMENT,
+						ty: wgpu::BindingType::UniformBuffer {
+							dynamic: false
+						}
+					},
+					wgpu::BindGroupLayoutBinding {
+						binding: 10,
+						visibility: wgpu::ShaderStage::FRAGMENT,
+						ty: wgpu::BindingType::UniformBuffer {
+							dynamic: false
+						}
+					},
+					wgpu::BindGroupLayoutBinding {
+						binding: 11,
+						visibility: wgpu::ShaderStage::FRAGMENT,
+						ty: wgpu::BindingType::UniformBuffer {
+							dynamic: false
+						}
+					},
+					wgpu::BindGroupLayoutBinding {
+						binding: 12,
+						visibility: wgpu::ShaderStage::FRAGMENT,
+						ty: wgpu::BindingType::UniformBuffer {
+							dynamic: false
+						}
+					},
+					wgpu::BindGroupLayoutBinding {
+						binding: 13,
+						visibility: wgpu::ShaderStage::FRAGMENT,
+						ty: wgpu::BindingType::UniformBuffer {
+							dynamic: false
+						}
+					},
+					wgpu::BindGroupLayoutBinding {
+						binding: 14,
+						visibility: wgpu::ShaderStage::FRAGMENT,
+						ty: wgpu::BindingType::UniformBuffer {
+							dynamic: false
+						}
+					},
+					wgpu::BindGroupLayoutBinding {
+						binding: 15,
+						visibility: wgpu::ShaderStage::FRAGMENT,
+						ty: wgpu::BindingType::SampledTexture {
+							multisampled: false,
+							dimension: wgpu::TextureViewDimension::D1,
+						}
+					},
+					wgpu::BindGroupLayoutBinding {
+						binding: 16,
+						visibility: wgpu::ShaderStage::FRAGMENT,
+						ty: wgpu::BindingType::Sampler
+					},
 				]
 			}
 		);
@@ -176,6 +479,77 @@ pub fn new(
 						range: 0..*POSITION_SIZE
 					}
 				},
+				wgpu::Binding {
+					binding: 6,
+					resource: wgpu::BindingResource::Buffer {
+						buffer: &orbits_buf,
+						range: 0..*ORBITS_SIZE
+					}
+				},
+				wgpu::Binding {
+					binding: 7,
+					resource: wgpu::BindingResource::Buffer {
+						buffer: &perturbation_buf,
+						range: 0..*PERTURBATION_SIZE
+					}
+				},
+				wgpu::Binding {
+					binding: 8,
+					resource: wgpu::BindingResource::Buffer {
+						buffer: &formula_buf,
+						range: 0..*FORMULA_SIZE
+					}
+				},
+				wgpu::Binding {
+					binding: 9,
+					resource: wgpu::BindingResource::Buffer {
+						buffer: &roots_buf,
+						range: 0..*ROOTS_SIZE
+					}
+				},
+				wgpu::Binding {
+					binding: 10,
+					resource: wgpu::BindingResource::Buffer {
+						buffer: &lyapunov_buf,
+						range: 0..*LYAPUNOV_SIZE
+					}
+				},
+				wgpu::Binding {
+					binding: 11,
+					resource: wgpu::BindingResource::Buffer {
+						buffer: &trap_buf,
+						range: 0..*TRAP_SIZE
+					}
+				},
+				wgpu::Binding {
+					binding: 12,
+					resource: wgpu::BindingResource::Buffer {
+						buffer: &average_buf,
+						range: 0..*AVERAGE_SIZE
+					}
+				},
+				wgpu::Binding {
+					binding: 13,
+					resource: wgpu::BindingResource::Buffer {
+						buffer: &progress_buf,
+						range: 0..PROGRESS_SIZE
+					}
+				},
+				wgpu::Binding {
+					binding: 14,
+					resource: wgpu::BindingResource::Buffer {
+						buffer: &coloring_buf,
+						range: 0..*COLORING_SIZE
+					}
+				},
+				wgpu::Binding {
+					binding: 15,
+					resource: wgpu::BindingResource::TextureView(&palette_view)
+				},
+				wgpu::Binding {
+					binding: 16,
+					resource: wgpu::BindingResource::Sampler(&palette_sampler)
+				},
 			],
 		});
 
@@ -185,54 +559,14 @@ pub fn new(
 
 	let vs_module =
 		device.create_shader_module(&*VERT_SHADER);
+	let selection = Selection::new(device, &vs_module);
 
-	let fs = &*FRAG_SHADER_INIT;
-	let fs_module = device.create_shader_module(&fs);
+	let fs_module = device.create_shader_module(fs);
 
-	log::info!("Creating render pipeline");
-	let render_pipeline = device.create_render_pipeline(
-		&wgpu::RenderPipelineDescriptor {
-			layout: &pipeline_layout,
-			vertex_stage: wgpu::ProgrammableStageDescriptor {
-				module: &vs_module,
-				entry_point: "main",
-			},
-			fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
-				module: &fs_module,
-				entry_point: "main",
-			}),
-			rasterization_state: Some(wgpu::RasterizationStateDescriptor {
-				front_face: wgpu::FrontFace::Ccw,
-				cull_mode: wgpu::CullMode::None,
-				depth_bias: 0,
-				depth_bias_slope_scale: 0.0,
-				depth_bias_clamp: 0.0,
-			}),
-			primitive_topology: wgpu::PrimitiveTopology::TriangleStrip,
-			color_states: &[wgpu::ColorStateDescriptor {
-				format: wgpu::TextureFormat::Bgra8UnormSrgb,
-				color_blend: wgpu::BlendDescriptor::REPLACE,
-				alpha_blend: wgpu::BlendDescriptor::REPLACE,
-				write_mask: wgpu::ColorWrite::ALL,
-			}],
-			depth_stencil_state: None,
-			index_format: wgpu::IndexFormat::Uint32,
-			vertex_buffers: &[wgpu::VertexBufferDescriptor {
-				stride: *VERTEX_SIZE,
-				step_mode: wgpu::InputStepMode::Vertex,
-				attributes: &[wgpu::VertexAttributeDescriptor {
-					format: wgpu::VertexFormat::Float2,
-					offset: 0,
-					shader_location: 0,
-				}],
-			}],
-			sample_count: 1,
-			sample_mask: !0,
-			alpha_to_coverage_enabled: false,
-		}
-	);
+	let formats: &[wgpu::TextureFormat] = if colorized { &ITERATION_FORMATS } else { &[wgpu::TextureFormat::Bgra8UnormSrgb] };
+	let render_pipeline = create_render_pipeline(device, &pipeline_layout, &vs_module, &fs_module, formats);
 
-	FractalViewData {
+	let mut data = FractalViewData {
 			bufs: Buffers {
 				window_size: window_size_buf,
 				position: position_buf,
@@ -240,18 +574,57 @@ pub fn new(
 				iterations: iterations_buf,
 				vertex: vertex_buf,
 				julia: julia_buf,
-				generator: generator_buf
+				generator: generator_buf,
+				orbits: orbits_buf,
+				perturbation: perturbation_buf,
+				formula: formula_buf,
+				roots: roots_buf,
+				lyapunov: lyapunov_buf,
+				coloring: coloring_buf,
+				trap: trap_buf,
+				average: average_buf,
+				lighting: lighting_buf,
+				progress: progress_buf,
 			},
+			palette_texture,
 			vs_module: Arc::new(vs_module),
 			pipeline_layout: Arc::new(pipeline_layout),
 			frag_shader_module: Arc::new(Mutex::new(fs_module)),
 			render_pipeline: Arc::new(Mutex::new(render_pipeline)),
 			bind_group: Arc::new(Mutex::new(bind_group)),
-			pos,
+			center,
 			prev_position: Position::default(),
 			first_drag_pos_received: false,
 			left_button_pressed: false,
 			zoom,
 			iterations: Iterations::default(),
+			julia,
+			formula,
+			custom_formula: None,
+			generator,
+			polynomial,
+			lyapunov,
+			palette,
+			uploaded_gradient: None,
+			coloring,
+			colorizer: None,
+			trap,
+			trap_drag: false,
+			average,
+			lighting,
+			light_drag: false,
+			window_size,
+			viewport,
+			perturber: Perturber::default(),
+			references: 0,
+			series_approximation: true,
+			auto_iterations: false,
+			skipped: None,
+			selection,
+			history: History::default(),
+	};
+	if colorized {
+		data.colorizer = Some(Colorizer::new(device, &data));
 	}
-}
\ No newline at end of file
+	data
+}
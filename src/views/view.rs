@@ -6,14 +6,30 @@ use std::sync::{Arc, Mutex};
 use crate::utils::{
 	AtomicDevice,
 	Position, POSITION_SIZE,
-	Zoom, ZOOM_SIZE,
+	Zoom,
 	WindowSize, WINDOW_SIZE_SIZE,
-	Iterations, ITERATIONS_SIZE,
-	VERTEX_SIZE,
-	Julia, JULIA_SIZE
+	Iterations, ITERATIONS_SIZE, MAX_ITERATIONS,
+	Julia, JULIA_SIZE,
+	Formula,
+	FractalParams,
+	Center,
+	DOUBLE_SINGLE_ZOOM
 };
-
-use super::utils::ZOOM_SENSITIVITY;
+use crate::polynomial::Polynomial;
+use crate::lyapunov::Lyapunov;
+use crate::custom_formula::{CustomFormula, MARKER};
+use crate::palette::{Palette, Stop, Interpolation};
+use crate::coloring::Coloring;
+use crate::trap::Trap;
+use crate::average::Average;
+use crate::lighting::Lighting;
+
+use super::utils::{ZOOM_SENSITIVITY, copy_to_buffer, copy_to_texture, create_render_pipeline};
+use super::colorize::{Colorizer, ITERATION_FORMATS};
+use super::progress::{Progress, Pass};
+use super::perturber::Perturber;
+use super::selection::{self, Selection};
+use super::history::{History, Snapshot, Burst};
 use std::ops::Deref;
 
 pub struct Buffers {
@@ -24,6 +40,16 @@ pub struct Buffers {
 	pub vertex: wgpu::Buffer,
 	pub julia: wgpu::Buffer,
 	pub generator: wgpu::Buffer,
+	pub orbits: wgpu::Buffer,
+	pub perturbation: wgpu::Buffer,
+	pub formula: wgpu::Buffer,
+	pub roots: wgpu::Buffer,
+	pub lyapunov: wgpu::Buffer,
+	pub coloring: wgpu::Buffer,
+	pub trap: wgpu::Buffer,
+	pub average: wgpu::Buffer,
+	pub lighting: wgpu::Buffer,
+	pub progress: wgpu::Buffer,
 }
 
 pub struct FractalViewData {
@@ -33,19 +59,82 @@ pub struct FractalViewData {
 	pub bufs: Buffers,
 	pub vs_module: Arc<wgpu::ShaderModule>,
 	pub pipeline_layout: Arc<wgpu::PipelineLayout>,
+	pub palette_texture: wgpu::Texture,
 
 	pub prev_position: Position,
-	pub pos: Position,
+	pub center: Center,
 	pub first_drag_pos_received: bool,
 	pub left_button_pressed: bool,
 	pub zoom: Zoom,
 	pub iterations: Iterations,
+	pub julia: Julia,
+	pub formula: Formula,
+	/// Compiled into the shader, see `FractalViewable::set_custom_formula`.
+	pub custom_formula: Option<CustomFormula>,
+	pub generator: Position,
+	pub polynomial: Polynomial,
+	pub lyapunov: Lyapunov,
+	pub palette: Palette,
+	/// What the palette texture was last filled with, see `FractalViewable::update_palette`.
+	pub uploaded_gradient: Option<(Vec<Stop>, Interpolation)>,
+	pub coloring: Coloring,
+	/// Colors what the fragment shader drew, `None` where it draws colors itself.
+	pub colorizer: Option<Colorizer>,
+	pub trap: Trap,
+	/// Whether the left button drags the trap instead of the view, see `FractalViewable::mouse_input`.
+	pub trap_drag: bool,
+	pub average: Average,
+	pub lighting: Lighting,
+	/// Whether the left button moves the light instead of the view, see `FractalViewable::mouse_input`.
+	pub light_drag: bool,
+
+	pub window_size: WindowSize,
+	/// Part of the window this view is drawn into, see `super::utils::viewport`.
+	pub viewport: [f32; 4],
+	pub perturber: Perturber,
+	pub references: usize,
+	pub series_approximation: bool,
+	/// Whether `FractalViewable::update_iterations` follows the zoom with the iterations.
+	pub auto_iterations: bool,
+	/// Iterations skipped by the series approximation, `None` without perturbation.
+	pub skipped: Option<usize>,
+	pub selection: Selection,
+	pub history: History,
 }
 
 impl FractalViewData {
 	fn set_fs(&mut self, sm: wgpu::ShaderModule) {
 		self.frag_shader_module = Arc::new(Mutex::new(sm));
 	}
+
+	/// `viewport` in pixels of the current window size.
+	pub fn viewport_pixels(&self) -> [f32; 4] {
+		let size = self.window_size.size;
+		[
+			self.viewport[0] * size[0],
+			self.viewport[1] * size[1],
+			self.viewport[2] * size[0],
+			self.viewport[3] * size[1],
+		]
+	}
+
+	/// Fraction of the iteration texture drawn at full resolution, `None` once it is complete or without a `Colorizer`.
+	pub fn progress(&self) -> Option<f32> {
+		self.colorizer.as_ref()
+			.filter(|colorizer| colorizer.progress.is_refining())
+			.map(|colorizer| colorizer.progress.fraction())
+	}
+
+	/// Whether the picture keeps getting better without any input, see `FractalViewManager::is_refining`.
+	pub fn is_refining(&self) -> bool {
+		self.progress().is_some() || self.perturber.is_busy()
+	}
+
+	fn contains(&self, position: [f64; 2]) -> bool {
+		let viewport = self.viewport_pixels();
+		position[0] >= f64::from(viewport[0]) && position[0] <= f64::from(viewport[2])
+			&& position[1] >= f64::from(viewport[1]) && position[1] <= f64::from(viewport[3])
+	}
 }
 
 pub trait FractalViewManager {
@@ -54,7 +143,7 @@ pub trait FractalViewManager {
 	fn render(
 		&mut self,
 		device: &AtomicDevice,
-		frame: &wgpu::SwapChainOutput,
+		target: &wgpu::TextureView,
 	) -> Vec<wgpu::CommandBuffer>;
 
 	fn resized(
@@ -63,42 +152,86 @@ pub trait FractalViewManager {
 		window_size: &WindowSize
 	) -> Vec<wgpu::CommandBuffer>;
 
-	/// Load fragment shader.
-	fn load_fs(path: &Path) -> Option<Vec<u32>> {
-		log::info!("Loading fragment shader {:?}", path);
-		let buffer = std::fs::read_to_string(
-			path
-		).unwrap();
+	fn mouse_input(
+		&mut self,
+		device: &AtomicDevice,
+		button: winit::event::MouseButton,
+		state: winit::event::ElementState,
+		modifiers: winit::event::ModifiersState
+	) -> Option<Vec<wgpu::CommandBuffer>>;
 
-		let spirv = glsl_to_spirv::compile(
-			&buffer,
-			glsl_to_spirv::ShaderType::Fragment
-		);
-		match spirv {
-			Ok(spirv) => {
-				// Load fragment shader
-				Some(wgpu::read_spirv(spirv).unwrap())
-			}
-			Err(err) => {
-				log::error!("Spirv compilation error: {:?}", err);
-				None
-			}
-		}
-	}
+	/// Multiplies the iterations by `factor`, up to `MAX_ITERATIONS`, and turns `set_auto_iterations` off.
+	fn iterations(&mut self, device: &AtomicDevice, factor: f32) -> Vec<wgpu::CommandBuffer>;
 
-	fn mouse_input(&mut self, button: winit::event::MouseButton, state: winit::event::ElementState);
+	fn set_julia(&mut self, device: &AtomicDevice, state: bool) -> Option<Vec<wgpu::CommandBuffer>>;
 
-	fn iterations(&mut self, device: &AtomicDevice, y_delta: f32) -> Vec<wgpu::CommandBuffer>;
+	/// Applies to the Mandelbrot and the double view, each keeps being of the Mandelbrot or the Julia type.
+	/// The Newton, Buddhabrot and Lyapunov views have formulas of their own.
+	fn set_formula(&mut self, device: &AtomicDevice, formula: Formula) -> Vec<wgpu::CommandBuffer>;
 
-	fn set_julia(&mut self, device: &AtomicDevice, state: bool) -> Option<Vec<wgpu::CommandBuffer>>;
+	/// Rebuilds the pipelines with the formula of `Family::Custom`.
+	/// Where that fails the views keep the formula they had and the error is returned.
+	fn set_custom_formula(&mut self, device: &AtomicDevice, custom_formula: Option<CustomFormula>) -> Result<(), String>;
+
+	/// Applies to every view, as do the coloring, the trap, the average and the lighting.
+	fn set_palette(&mut self, device: &AtomicDevice, palette: Palette) -> Vec<wgpu::CommandBuffer>;
+
+	fn set_coloring(&mut self, device: &AtomicDevice, coloring: Coloring) -> Vec<wgpu::CommandBuffer>;
+
+	fn set_trap(&mut self, device: &AtomicDevice, trap: Trap) -> Vec<wgpu::CommandBuffer>;
+
+	fn set_average(&mut self, device: &AtomicDevice, average: Average) -> Vec<wgpu::CommandBuffer>;
+
+	fn set_lighting(&mut self, device: &AtomicDevice, lighting: Lighting) -> Vec<wgpu::CommandBuffer>;
 
 	fn zoom(&mut self, device: &AtomicDevice, y_delta: f32) -> Vec<wgpu::CommandBuffer>;
 
+	fn params(&mut self) -> FractalParams;
+
+	fn set_params(&mut self, device: &AtomicDevice, params: &FractalParams) -> Vec<wgpu::CommandBuffer>;
+
+	/// `set_params` with everything but the center, the zoom, the iterations and whether it shows a Julia set,
+	/// which stay the view's own.
+	fn set_shared_params(&mut self, device: &AtomicDevice, params: &FractalParams) -> Vec<wgpu::CommandBuffer> {
+		let own = self.params();
+		self.set_params(device, &FractalParams {
+			center: own.center,
+			zoom: own.zoom,
+			iterations: own.iterations,
+			julia: own.julia,
+			..params.clone()
+		})
+	}
+
 	fn new_position(&mut self, device: &AtomicDevice, x: f32, y: f32, active: bool) -> Option<Vec<wgpu::CommandBuffer>>;
 
+	/// Goes back one step in the history, of the half under the cursor where there are two.
+	fn undo(&mut self, device: &AtomicDevice) -> Option<Vec<wgpu::CommandBuffer>>;
+
+	fn redo(&mut self, device: &AtomicDevice) -> Option<Vec<wgpu::CommandBuffer>>;
+
+	/// Turns the series approximation of deep zooms on or off, to compare the two.
+	fn set_series_approximation(&mut self, state: bool);
+
+	/// Applies to every view. Changing the iterations by hand turns it off again.
+	fn set_auto_iterations(&mut self, state: bool);
+
+	/// For the HUD, see `FractalViewData::skipped`.
+	fn skipped_iterations(&mut self) -> Option<usize>;
+
+	/// Whether the picture keeps getting better without any input, `main` keeps drawing frames while it does.
+	fn is_refining(&mut self) -> bool {
+		false
+	}
+
+	/// For the HUD, how far the picture got while it is refining, from 0 to 1.
+	fn progress(&mut self) -> Option<f32> {
+		None
+	}
+
 	fn create_render_pipeline(&mut self, device: &wgpu::Device);
 
-	fn reload_fs(&mut self, device: &AtomicDevice);
+	fn reload_fs(&mut self, device: &AtomicDevice) -> Result<(), String>;
 }
 
 
@@ -107,39 +240,183 @@ pub trait FractalViewable {
 
 	fn data(&mut self) -> &mut FractalViewData;
 
+	/// Draws the view into `target`, which is either the swap chain frame
+	/// or an offscreen texture (see `crate::headless`).
+	/// With a `Colorizer` the fragment shader draws into its iteration texture, which it then colors into `target`,
+	/// over as many frames as `Colorizer::progress` takes.
 	fn render(
 		&mut self,
 		device: &AtomicDevice,
-		frame: &wgpu::SwapChainOutput,
+		target: &wgpu::TextureView,
 	) -> wgpu::CommandBuffer {
 		let mut encoder =
 			device.lock().unwrap().create_command_encoder(&wgpu::CommandEncoderDescriptor { todo: 0 });
-		{
+		self.update_iterations(&device.lock().unwrap(), &mut encoder);
+		self.update_perturbation(&device.lock().unwrap(), &mut encoder);
+		self.update_palette(&device.lock().unwrap(), &mut encoder);
+		let params = self.params();
+		let iterations = params.iterations.iterations;
+		let iterated = Some((params.without_coloring(), self.data().viewport));
+		let data = self.data();
+		// Out for a moment, its bind group is made of the rest of `data`.
+		if let Some(mut colorizer) = data.colorizer.take() {
+			colorizer.resize(&device.lock().unwrap(), data);
+			data.colorizer = Some(colorizer);
+		}
+		let viewport = data.viewport_pixels();
+		// Palettes and colorings only need the colorize pass, the iterations stay the same.
+		let pass = match &mut data.colorizer {
+			Some(colorizer) => {
+				if colorizer.iterated != iterated {
+					colorizer.iterated = iterated;
+					colorizer.progress = Progress::new(viewport, iterations, colorizer.progressive);
+				}
+				let pass = colorizer.progress.next();
+				copy_to_buffer(&device.lock().unwrap(), &mut encoder, colorizer.progress.uniform(), &data.bufs.progress);
+				pass
+			},
+			None => Pass::Whole,
+		};
+		if pass != Pass::Done {
+			let (attachments, load_op) = match &data.colorizer {
+				// Pixels outside of `vertices` stay 0, like the inside of the set.
+				// The tiles go over what the coarse pass left.
+				Some(colorizer) => (
+					vec![&colorizer.iteration_view, &colorizer.orbit_view, &colorizer.normal_view],
+					match pass {
+						Pass::Tiles(..) => wgpu::LoadOp::Load,
+						_ => wgpu::LoadOp::Clear,
+					}
+				),
+				None => (vec![target], wgpu::LoadOp::Load),
+			};
+			let color_attachments: Vec<_> = attachments.into_iter()
+				.map(|attachment| wgpu::RenderPassColorAttachmentDescriptor {
+					attachment,
+					resolve_target: None,
+					load_op,
+					store_op: wgpu::StoreOp::Store,
+					clear_color: wgpu::Color::BLACK
+				})
+				.collect();
 			let mut rpass = encoder.begin_render_pass(
 				&wgpu::RenderPassDescriptor {
-					color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
-						attachment: &frame.view,
-						resolve_target: None,
-						load_op: wgpu::LoadOp::Load,
-						store_op: wgpu::StoreOp::Store,
-						clear_color: wgpu::Color::BLACK
-					}],
+					color_attachments: &color_attachments,
 					depth_stencil_attachment: None,
 				}
 			);
-			rpass.set_pipeline(self.data().render_pipeline.lock().unwrap().deref());
-			rpass.set_bind_group(0, self.data().bind_group.lock().unwrap().deref(), &[]);
-			rpass.set_vertex_buffers(0, &[(&self.data().bufs.vertex, 0)]);
-			rpass.draw(0..4, 0..1);
+			rpass.set_pipeline(data.render_pipeline.lock().unwrap().deref());
+			rpass.set_bind_group(0, data.bind_group.lock().unwrap().deref(), &[]);
+			rpass.set_vertex_buffers(0, &[(&data.bufs.vertex, 0)]);
+			match (pass, &data.colorizer) {
+				(Pass::Tiles(from, to), Some(colorizer)) => for index in from..to {
+					let [x, y, width, height] = colorizer.progress.tile(index);
+					rpass.set_scissor_rect(x, y, width, height);
+					rpass.draw(0..4, 0..1);
+				},
+				_ => rpass.draw(0..4, 0..1),
+			}
+		}
+		if let Some(colorizer) = &data.colorizer {
+			colorizer.render(&mut encoder, target, &data.bufs.vertex, data.coloring.mode);
 		}
+		data.selection.render(&mut encoder, target);
 
 		encoder.finish()
 	}
+
+	/// Whether the shader iterates `mandelbrot.frag`'s formulas, and so can use perturbation.
+	fn is_perturbable(&self) -> bool {
+		true
+	}
+
+	/// Follows the zoom with the iterations while `FractalViewData::auto_iterations` is on.
+	fn update_iterations(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
+		let data = self.data();
+		if !data.auto_iterations {
+			return;
+		}
+		let iterations = Iterations::auto(data.zoom);
+		if iterations != data.iterations {
+			log::info!("Auto iterations: {}", iterations.iterations);
+			data.iterations = iterations;
+			copy_to_buffer(device, encoder, iterations, &data.bufs.iterations);
+		}
+	}
+
+	fn set_auto_iterations(&mut self, state: bool) {
+		log::info!("Auto iterations: {}", state);
+		self.data().auto_iterations = state;
+	}
+
+	/// Uploads the reference orbits when they or the view changed, see `Perturber`.
+	/// New ones are computed on a thread of their own, the view is drawn with the ones from before until they arrive.
+	fn update_perturbation(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
+		if !self.is_perturbable() {
+			return;
+		}
+		let params = self.params().without_coloring();
+		let data = self.data();
+		let size = data.window_size.size;
+		let viewport = data.viewport_pixels();
+		let (uniform, orbits) = match data.perturber.update(params, size, viewport, data.series_approximation) {
+			Some(upload) => upload,
+			None => return,
+		};
+		if uniform.references() != data.references {
+			log::info!("Perturbation references: {}", uniform.references());
+		}
+
+		copy_to_buffer(device, encoder, uniform, &data.bufs.perturbation);
+		if let Some(orbits) = orbits {
+			if !orbits.is_empty() {
+				let temp_buf = device.create_buffer_mapped(
+					orbits.len(),
+					wgpu::BufferUsage::COPY_SRC
+				).fill_from_slice(orbits);
+
+				encoder.copy_buffer_to_buffer(
+					&temp_buf,
+					0,
+					&data.bufs.orbits,
+					0,
+					(orbits.len() * std::mem::size_of::<[f32; 2]>()) as wgpu::BufferAddress
+				);
+			}
+			// The same view again, with the new references.
+			if let Some(colorizer) = &mut data.colorizer {
+				colorizer.iterated = None;
+			}
+		}
+
+		data.references = uniform.references();
+		data.skipped = if uniform.enabled() { Some(uniform.skipped()) } else { None };
+	}
+
+	/// Fills the palette texture with the stops of the palette, if they or how they are blended changed since it last was.
+	fn update_palette(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
+		let data = self.data();
+		let gradient = (data.palette.stops.clone(), data.palette.interpolation);
+		if data.uploaded_gradient.as_ref() == Some(&gradient) {
+			return;
+		}
+		copy_to_texture(device, encoder, &data.palette.texels(), &data.palette_texture);
+		data.uploaded_gradient = Some(gradient);
+	}
+
+	fn set_series_approximation(&mut self, state: bool) {
+		log::info!("Setting series approximation to: {:?}", state);
+		let data = self.data();
+		// `update_perturbation` computes the references again, and the shader iterates again once they are there.
+		data.series_approximation = state;
+	}
+
 	fn resized(
 		&mut self,
 		device: &AtomicDevice,
 		window_size: &WindowSize
 	) -> wgpu::CommandBuffer {
+		self.data().window_size = *window_size;
 		let temp_buf = device.lock().unwrap().create_buffer_mapped(
 			1,
 			wgpu::BufferUsage::COPY_SRC
@@ -159,53 +436,154 @@ pub trait FractalViewable {
 		encoder.finish()
 	}
 
-	fn load_fs(path: &Path) -> Option<Vec<u32>> {
+	/// Loads and compiles the fragment shader at `path`, with `custom_formula` in it if there is one.
+	fn load_fs(path: &Path, custom_formula: Option<&CustomFormula>) -> Result<Vec<u32>, String> {
 		log::info!("Loading fragment shader {:?}", path);
-		let buffer = std::fs::read_to_string(
-			path
-		).unwrap();
+		let source = std::fs::read_to_string(path)
+			.map_err(|err| format!("Error reading {:?}: {}", path, err))?;
+		let source = match custom_formula {
+			Some(custom_formula) => custom_formula.inject(&source)
+				.ok_or_else(|| format!("{:?} has no line ending in {:?} for the custom formula", path, MARKER))?,
+			None => source
+		};
 
-		let spirv = glsl_to_spirv::compile(
-			&buffer,
-			glsl_to_spirv::ShaderType::Fragment
-		);
-		match spirv {
-			Ok(spirv) => {
-				// Load fragment shader
-				Some(wgpu::read_spirv(spirv).unwrap())
-			}
-			Err(err) => {
-				log::error!("Spirv compilation error: {:?}", err);
-				None
-			}
-		}
+		Self::compile_fs(&source)
 	}
 
-	fn mouse_input(&mut self, button: winit::event::MouseButton, state: winit::event::ElementState) {
+	/// The left button drags the view around, the right one selects a rectangle to zoom into,
+	/// or with Shift a rectangle to fit the current view into.
+	fn mouse_input(
+		&mut self,
+		device: &AtomicDevice,
+		button: winit::event::MouseButton,
+		state: winit::event::ElementState,
+		modifiers: winit::event::ModifiersState
+	) -> Option<wgpu::CommandBuffer> {
 		use winit::event;
-		if button != event::MouseButton::Left {
-			return;
-		}
-		match state {
-			event::ElementState::Pressed => {
+		match (button, state) {
+			// With Ctrl the trap follows the cursor instead, see `new_position`.
+			(event::MouseButton::Left, event::ElementState::Pressed) if modifiers.ctrl => {
+				log::info!("Pressed left mouse button to drag the trap.");
+				self.data().trap_drag = true;
+				self.move_trap(device)
+			}
+			(event::MouseButton::Left, event::ElementState::Released) if self.data().trap_drag => {
+				self.data().trap_drag = false;
+				None
+			}
+			// With Shift it moves the light.
+			(event::MouseButton::Left, event::ElementState::Pressed) if modifiers.shift => {
+				log::info!("Pressed left mouse button to move the light.");
+				self.data().light_drag = true;
+				self.move_light(device)
+			}
+			(event::MouseButton::Left, event::ElementState::Released) if self.data().light_drag => {
+				self.data().light_drag = false;
+				None
+			}
+			(event::MouseButton::Left, event::ElementState::Pressed) => {
 				log::info!("Pressed left mouse button.");
-				self.data().left_button_pressed = true;
+				let snapshot = self.snapshot();
+				let data = self.data();
+				data.left_button_pressed = true;
+				data.history.begin_drag(snapshot);
+				None
 			}
-			event::ElementState::Released => {
+			(event::MouseButton::Left, event::ElementState::Released) => {
 				log::info!("Released left mouse button.");
-				self.data().left_button_pressed = false;
+				let snapshot = self.snapshot();
+				let data = self.data();
+				data.left_button_pressed = false;
+				data.history.end_drag(&snapshot);
+				None
+			}
+			(event::MouseButton::Right, event::ElementState::Pressed) => {
+				let data = self.data();
+				let cursor = data.prev_position.pos;
+				if data.first_drag_pos_received && data.contains(cursor) {
+					data.selection.begin(cursor, modifiers.shift);
+				}
+				None
 			}
+			(event::MouseButton::Right, event::ElementState::Released) => {
+				let (start, end) = self.data().selection.finish()?;
+				let zoom_out = self.data().selection.zoom_out;
+				Some(self.select(device, start, end, zoom_out))
+			}
+			_ => None
+		}
+	}
+
+	/// Moves the center of the trap to the point under the cursor.
+	fn move_trap(&mut self, device: &AtomicDevice) -> Option<wgpu::CommandBuffer> {
+		let data = self.data();
+		if !data.first_drag_pos_received {
+			return None;
 		}
+		let cursor = data.prev_position.pos;
+		let size = data.window_size.size;
+		let offset = [cursor[0] - f64::from(size[0]) / 2.0, cursor[1] - f64::from(size[1]) / 2.0];
+		// `position` is what the shader subtracts, the opposite of the point.
+		let point = data.center.offset(offset, data.zoom.zoom).position().pos;
+		let trap = Trap {
+			center: [-point[0], -point[1]],
+			..data.trap
+		};
+		Some(self.set_trap(device, trap))
 	}
 
-	fn iterations(&mut self, device: &AtomicDevice, y_delta: f32) -> wgpu::CommandBuffer {
+	/// Moves the light over the point under the cursor, see `Lighting::towards`.
+	fn move_light(&mut self, device: &AtomicDevice) -> Option<wgpu::CommandBuffer> {
+		let data = self.data();
+		if !data.first_drag_pos_received {
+			return None;
+		}
+		let cursor = data.prev_position.pos;
+		let size = data.window_size.size;
+		let offset = [cursor[0] - f64::from(size[0]) / 2.0, cursor[1] - f64::from(size[1]) / 2.0];
+		let lighting = data.lighting.towards(offset, size);
+		Some(self.set_lighting(device, lighting))
+	}
+
+	/// Zooms so that the rectangle between the corners `start` and `end` fills the view,
+	/// or with `zoom_out` so that what the view shows now fits into the rectangle.
+	fn select(&mut self, device: &AtomicDevice, start: [f64; 2], end: [f64; 2], zoom_out: bool) -> wgpu::CommandBuffer {
+		let snapshot = self.snapshot();
+		self.data().history.push(snapshot);
+		let data = self.data();
+		let (zoom, center) = selection::fit(
+			&data.center,
+			data.zoom.zoom,
+			start,
+			end,
+			data.window_size.size,
+			data.viewport_pixels(),
+			zoom_out
+		);
+		log::info!("Selected zoom {:?} at {:?}", zoom, center.position());
+
+		data.zoom = Zoom { zoom };
+		data.center = center;
+
+		let device = device.lock().unwrap();
+		let mut encoder =
+			device.create_command_encoder(&wgpu::CommandEncoderDescriptor { todo: 0 });
+		copy_to_buffer(&device, &mut encoder, data.zoom.uniform(), &data.bufs.zoom);
+		copy_to_buffer(&device, &mut encoder, data.center.position().uniform(), &data.bufs.position);
+
+		encoder.finish()
+	}
+
+	fn iterations(&mut self, device: &AtomicDevice, factor: f32) -> wgpu::CommandBuffer {
+		let snapshot = self.snapshot();
+		self.data().history.push_burst(snapshot, Burst::Iterations);
 		let mut iterations = self.data().iterations;
 
-		iterations.iterations *= 0.99f32.powi(y_delta.signum() as i32);
+		iterations.iterations *= factor;
 		if iterations.iterations < 0.0 {
 			iterations.iterations = 0.0;
-		} else if iterations.iterations > 800.0 {
-			iterations.iterations = 800.0;
+		} else if iterations.iterations > MAX_ITERATIONS {
+			iterations.iterations = MAX_ITERATIONS;
 		}
 		log::info!("Iterations: {:#?}", iterations);
 		self.data().iterations = iterations;
@@ -231,6 +609,7 @@ pub trait FractalViewable {
 
 	fn set_julia(&mut self, device: &AtomicDevice, state: bool) -> wgpu::CommandBuffer {
 		log::info!("Setting is_julia to: {:?}", state);
+		self.data().julia = Julia { is_julia: state };
 		let temp_buf = device.lock().unwrap().create_buffer_mapped(
 			1,
 			wgpu::BufferUsage::COPY_SRC
@@ -250,28 +629,221 @@ pub trait FractalViewable {
 		encoder.finish()
 	}
 
+	fn set_formula(&mut self, device: &AtomicDevice, formula: Formula) -> wgpu::CommandBuffer {
+		log::info!("Setting formula to: {:?}", formula);
+		let data = self.data();
+		data.formula = formula;
+
+		let device = device.lock().unwrap();
+		let mut encoder =
+			device.create_command_encoder(&wgpu::CommandEncoderDescriptor { todo: 0 });
+		copy_to_buffer(&device, &mut encoder, formula.uniform(), &data.bufs.formula);
+
+		encoder.finish()
+	}
+
+	/// The texture follows on the next `render`.
+	fn set_palette(&mut self, device: &AtomicDevice, palette: Palette) -> wgpu::CommandBuffer {
+		log::info!("Setting palette to: {:?} at offset {:?} and scale {:?}", palette.source, palette.offset, palette.scale);
+		let data = self.data();
+		data.palette = palette;
+
+		let device = device.lock().unwrap();
+		let mut encoder =
+			device.create_command_encoder(&wgpu::CommandEncoderDescriptor { todo: 0 });
+		copy_to_buffer(&device, &mut encoder, data.coloring.uniform(&data.palette), &data.bufs.coloring);
+
+		encoder.finish()
+	}
+
+	fn set_trap(&mut self, device: &AtomicDevice, trap: Trap) -> wgpu::CommandBuffer {
+		log::info!("Setting trap to: {:?}", trap);
+		let data = self.data();
+		data.trap = trap;
+
+		let device = device.lock().unwrap();
+		let mut encoder =
+			device.create_command_encoder(&wgpu::CommandEncoderDescriptor { todo: 0 });
+		copy_to_buffer(&device, &mut encoder, trap.uniform(), &data.bufs.trap);
+
+		encoder.finish()
+	}
+
+	fn set_average(&mut self, device: &AtomicDevice, average: Average) -> wgpu::CommandBuffer {
+		log::info!("Setting average to: {:?}", average);
+		let data = self.data();
+		data.average = average;
+
+		let device = device.lock().unwrap();
+		let mut encoder =
+			device.create_command_encoder(&wgpu::CommandEncoderDescriptor { todo: 0 });
+		copy_to_buffer(&device, &mut encoder, average.uniform(), &data.bufs.average);
+
+		encoder.finish()
+	}
+
+	fn set_lighting(&mut self, device: &AtomicDevice, lighting: Lighting) -> wgpu::CommandBuffer {
+		log::info!("Setting lighting to: {:?}", lighting);
+		let data = self.data();
+		data.lighting = lighting;
+
+		let device = device.lock().unwrap();
+		let mut encoder =
+			device.create_command_encoder(&wgpu::CommandEncoderDescriptor { todo: 0 });
+		copy_to_buffer(&device, &mut encoder, lighting.uniform(), &data.bufs.lighting);
+
+		encoder.finish()
+	}
+
+	fn set_coloring(&mut self, device: &AtomicDevice, coloring: Coloring) -> wgpu::CommandBuffer {
+		log::info!("Setting coloring to: {:?}", coloring);
+		let data = self.data();
+		data.coloring = coloring;
+
+		let device = device.lock().unwrap();
+		let mut encoder =
+			device.create_command_encoder(&wgpu::CommandEncoderDescriptor { todo: 0 });
+		copy_to_buffer(&device, &mut encoder, data.coloring.uniform(&data.palette), &data.bufs.coloring);
+
+		encoder.finish()
+	}
+
+	/// Snapshot of the values currently uploaded to the shader.
+	fn params(&mut self) -> FractalParams {
+		let data = self.data();
+		FractalParams {
+			center: data.center.clone(),
+			zoom: data.zoom,
+			iterations: data.iterations,
+			auto_iterations: data.auto_iterations,
+			julia: data.julia,
+			formula: data.formula,
+			custom_formula: data.custom_formula.clone(),
+			generator: data.generator,
+			polynomial: data.polynomial.clone(),
+			lyapunov: data.lyapunov.clone(),
+			palette: data.palette.clone(),
+			coloring: data.coloring.clone(),
+			trap: data.trap,
+			average: data.average,
+			lighting: data.lighting,
+		}
+	}
+
+	/// The part of `params` that the history keeps.
+	fn snapshot(&mut self) -> Snapshot {
+		let data = self.data();
+		Snapshot {
+			center: data.center.clone(),
+			zoom: data.zoom,
+			iterations: data.iterations,
+			generator: data.generator,
+		}
+	}
+
+	fn restore(&mut self, device: &AtomicDevice, snapshot: Snapshot) -> wgpu::CommandBuffer {
+		let params = FractalParams {
+			center: snapshot.center,
+			zoom: snapshot.zoom,
+			iterations: snapshot.iterations,
+			generator: snapshot.generator,
+			..self.params()
+		};
+		self.set_params(device, &params)
+	}
+
+	fn undo(&mut self, device: &AtomicDevice) -> Option<wgpu::CommandBuffer> {
+		let current = self.snapshot();
+		let previous = self.data().history.undo(current)?;
+		log::info!("Undo to {:?}", previous);
+		Some(self.restore(device, previous))
+	}
+
+	fn redo(&mut self, device: &AtomicDevice) -> Option<wgpu::CommandBuffer> {
+		let current = self.snapshot();
+		let next = self.data().history.redo(current)?;
+		log::info!("Redo to {:?}", next);
+		Some(self.restore(device, next))
+	}
+
+	/// Overwrites every uniform with the values from `params`.
+	fn set_params(&mut self, device: &AtomicDevice, params: &FractalParams) -> wgpu::CommandBuffer {
+		log::info!("Setting params: {:?}", params);
+		// Where the custom formula doesn't compile the formula stays what it was, as when typing one in.
+		let formula = match self.set_custom_formula(device, params.custom_formula.clone()) {
+			Ok(()) => params.formula,
+			Err(err) => {
+				log::error!("{}", err);
+				self.data().formula
+			}
+		};
+		{
+			let data = self.data();
+			data.center = params.center.clone();
+			data.zoom = params.zoom;
+			data.iterations = params.iterations;
+			data.auto_iterations = params.auto_iterations;
+			data.julia = params.julia;
+			data.formula = formula;
+			data.generator = params.generator;
+			data.polynomial = params.polynomial.clone();
+			data.lyapunov = params.lyapunov.clone();
+			data.palette = params.palette.clone();
+			data.coloring = params.coloring.clone();
+			data.trap = params.trap;
+			data.average = params.average;
+			data.lighting = params.lighting;
+		}
+
+		let device = device.lock().unwrap();
+		let mut encoder =
+			device.create_command_encoder(&wgpu::CommandEncoderDescriptor { todo: 0 });
+		let bufs = &self.data().bufs;
+		copy_to_buffer(&device, &mut encoder, params.center.position().uniform(), &bufs.position);
+		copy_to_buffer(&device, &mut encoder, params.zoom.uniform(), &bufs.zoom);
+		copy_to_buffer(&device, &mut encoder, params.iterations, &bufs.iterations);
+		copy_to_buffer(&device, &mut encoder, params.julia, &bufs.julia);
+		copy_to_buffer(&device, &mut encoder, formula.uniform(), &bufs.formula);
+		copy_to_buffer(&device, &mut encoder, params.generator.uniform(), &bufs.generator);
+		copy_to_buffer(&device, &mut encoder, params.polynomial.uniform(), &bufs.roots);
+		copy_to_buffer(&device, &mut encoder, params.lyapunov.uniform(), &bufs.lyapunov);
+		copy_to_buffer(&device, &mut encoder, params.coloring.uniform(&params.palette), &bufs.coloring);
+		copy_to_buffer(&device, &mut encoder, params.trap.uniform(), &bufs.trap);
+		copy_to_buffer(&device, &mut encoder, params.average.uniform(), &bufs.average);
+		copy_to_buffer(&device, &mut encoder, params.lighting.uniform(), &bufs.lighting);
+
+		encoder.finish()
+	}
+
+	/// Zooms around the cursor, moving the center so that the point under it stays put.
 	fn zoom(&mut self, device: &AtomicDevice, y_delta: f32) -> wgpu::CommandBuffer {
+		let snapshot = self.snapshot();
+		self.data().history.push_burst(snapshot, Burst::Zoom);
 		let mut zoom = self.data().zoom;
-		zoom.zoom *= (ZOOM_SENSITIVITY as f32).powi(y_delta.signum() as i32);
+		let prev_zoom = zoom.zoom;
+		zoom.zoom *= f64::from(ZOOM_SENSITIVITY).powi(y_delta.signum() as i32);
 
 		self.data().zoom = zoom;
 		log::info!("Zoom now of value: {:?}", zoom.zoom);
+		if (prev_zoom < DOUBLE_SINGLE_ZOOM) != (zoom.zoom < DOUBLE_SINGLE_ZOOM) {
+			log::info!("Double-single precision: {:?}", zoom.zoom < DOUBLE_SINGLE_ZOOM);
+		}
 
-		let temp_buf = device.lock().unwrap().create_buffer_mapped(
-			1,
-			wgpu::BufferUsage::COPY_SRC
-		).fill_from_slice(&[zoom]);
+		// Before the cursor ever moved there is nothing to zoom around but the middle.
+		if self.data().first_drag_pos_received {
+			let cursor = self.data().prev_position.pos;
+			let size = self.data().window_size.size;
+			let offset = [cursor[0] - f64::from(size[0]) / 2.0, cursor[1] - f64::from(size[1]) / 2.0];
+			let center = self.data().center.zoomed(offset, prev_zoom, zoom.zoom);
+			self.data().center = center;
+		}
 
+		let device = device.lock().unwrap();
 		let mut encoder =
-			device.lock().unwrap().create_command_encoder(&wgpu::CommandEncoderDescriptor { todo: 0 });
-
-		encoder.copy_buffer_to_buffer(
-			&temp_buf,
-			0,
-			&self.data().bufs.zoom,
-			0,
-			*ZOOM_SIZE
-		);
+			device.create_command_encoder(&wgpu::CommandEncoderDescriptor { todo: 0 });
+		let data = self.data();
+		copy_to_buffer(&device, &mut encoder, zoom.uniform(), &data.bufs.zoom);
+		copy_to_buffer(&device, &mut encoder, data.center.position().uniform(), &data.bufs.position);
 
 		encoder.finish()
 	}
@@ -281,13 +853,24 @@ pub trait FractalViewable {
 	/// Otherwise, on new drags there will be jumps.
 	fn new_position(&mut self, device: &AtomicDevice, x: f32, y: f32, active: bool) -> Option<wgpu::CommandBuffer> {
 		let mut prev_position = self.data().prev_position;
-		let mut pos = self.data().pos;
+		let mut center = self.data().center.clone();
+
+		let (x, y) = (f64::from(x), f64::from(y));
 
 		if !self.data().first_drag_pos_received {
 			prev_position.pos = [x, y];
 			self.data().first_drag_pos_received = true;
 		}
 
+		if self.data().trap_drag {
+			self.data().prev_position.pos = [x, y];
+			return self.move_trap(device);
+		}
+		if self.data().light_drag {
+			self.data().prev_position.pos = [x, y];
+			return self.move_light(device);
+		}
+
 		if active {
 			log::info!("Initial: {:?} Current: {:?},{:?}", prev_position, x, y);
 			let delta_x = x - prev_position.pos[0];
@@ -295,14 +878,18 @@ pub trait FractalViewable {
 
 			let zoom = self.data().zoom;
 
-			pos.pos[0] += delta_x * zoom.zoom;
-			pos.pos[1] += delta_y * zoom.zoom;
-			log::info!("New position: {:?}", pos);
+			center = center.offset([-delta_x, -delta_y], zoom.zoom);
+			log::info!("New position: {:?}", center.position());
 		}
 		prev_position.pos = [x, y];
 
-		self.data().pos = pos;
+		self.data().center = center.clone();
 		self.data().prev_position = prev_position;
+		{
+			let data = self.data();
+			let window_size = data.window_size;
+			data.selection.update(&device.lock().unwrap(), [x, y], &window_size);
+		}
 		if !active {
 			return None;
 		}
@@ -310,7 +897,7 @@ pub trait FractalViewable {
 		let temp_buf = device.lock().unwrap().create_buffer_mapped(
 			1,
 			wgpu::BufferUsage::COPY_SRC
-		).fill_from_slice(&[pos]);
+		).fill_from_slice(&[center.position().uniform()]);
 
 
 		let mut encoder =
@@ -330,67 +917,56 @@ pub trait FractalViewable {
 	fn frag_shader_path(&self) -> &'static Path;
 
 	fn create_render_pipeline(&mut self, device: &wgpu::Device) {
-		log::info!("Creating render pipeline");
-		let pipeline_layout = Arc::clone(&self.data().pipeline_layout);
-		let vs_module = Arc::clone(&self.data().vs_module);
-		let fs_module = {
-			let fs_module = self.data().frag_shader_module.lock().unwrap();
-			Arc::new(
-				Mutex::new(
-					device.create_render_pipeline(
-						&wgpu::RenderPipelineDescriptor {
-							layout: &pipeline_layout,
-							vertex_stage: wgpu::ProgrammableStageDescriptor {
-								module: &vs_module,
-								entry_point: "main",
-							},
-							fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
-								module: &fs_module,
-								entry_point: "main",
-							}),
-							rasterization_state: Some(wgpu::RasterizationStateDescriptor {
-								front_face: wgpu::FrontFace::Ccw,
-								cull_mode: wgpu::CullMode::None,
-								depth_bias: 0,
-								depth_bias_slope_scale: 0.0,
-								depth_bias_clamp: 0.0,
-							}),
-							primitive_topology: wgpu::PrimitiveTopology::TriangleStrip,
-							color_states: &[wgpu::ColorStateDescriptor {
-								format: wgpu::TextureFormat::Bgra8UnormSrgb,
-								color_blend: wgpu::BlendDescriptor::REPLACE,
-								alpha_blend: wgpu::BlendDescriptor::REPLACE,
-								write_mask: wgpu::ColorWrite::ALL,
-							}],
-							depth_stencil_state: None,
-							index_format: wgpu::IndexFormat::Uint32,
-							vertex_buffers: &[wgpu::VertexBufferDescriptor {
-								stride: *VERTEX_SIZE,
-								step_mode: wgpu::InputStepMode::Vertex,
-								attributes: &[wgpu::VertexAttributeDescriptor {
-									format: wgpu::VertexFormat::Float2,
-									offset: 0,
-									shader_location: 0,
-								}],
-							}],
-							sample_count: 1,
-							sample_mask: !0,
-							alpha_to_coverage_enabled: false,
-						}
-					)
-				)
-			)
-		};
-		self.data().render_pipeline = fs_module;
+		let data = self.data();
+		let formats: &[wgpu::TextureFormat] = if data.colorizer.is_some() { &ITERATION_FORMATS } else { &[wgpu::TextureFormat::Bgra8UnormSrgb] };
+		let render_pipeline = create_render_pipeline(
+			device,
+			&data.pipeline_layout,
+			&data.vs_module,
+			&data.frag_shader_module.lock().unwrap(),
+			formats
+		);
+		data.render_pipeline = Arc::new(Mutex::new(render_pipeline));
+		// The shader changed, what it iterated before may not be what it iterates now.
+		if let Some(colorizer) = &mut data.colorizer {
+			colorizer.iterated = None;
+		}
 	}
 
-	fn reload_fs(&mut self, device: &AtomicDevice) {
-		if let Some(fs) = Self::load_fs(self.frag_shader_path()) {
-			log::info!("Setting fs");
-			self.data().set_fs(device.lock().unwrap().create_shader_module(&fs));
-			self.create_render_pipeline(&device.lock().unwrap());
-		} else {
-			log::info!("Spirv compilation failed! Ignoring tho");
+	/// Recompiles the shader with `custom_formula` in it, when it changed.
+	/// Views not drawn by `mandelbrot.frag` only keep it for `params`.
+	/// Where it doesn't compile the view keeps the formula and the shader it had.
+	fn set_custom_formula(&mut self, device: &AtomicDevice, custom_formula: Option<CustomFormula>) -> Result<(), String> {
+		if self.data().custom_formula == custom_formula {
+			return Ok(());
+		}
+		log::info!("Setting custom formula to: {:?}", custom_formula);
+		let previous = std::mem::replace(&mut self.data().custom_formula, custom_formula);
+		if self.is_perturbable() {
+			if let Err(err) = self.reload_fs(device) {
+				self.data().custom_formula = previous;
+				return Err(err);
+			}
 		}
+		Ok(())
+	}
+
+	/// Compiles the GLSL `source` of a fragment shader.
+	fn compile_fs(source: &str) -> Result<Vec<u32>, String> {
+		let spirv = glsl_to_spirv::compile(
+			source,
+			glsl_to_spirv::ShaderType::Fragment
+		).map_err(|err| format!("Spirv compilation error: {}", err))?;
+		wgpu::read_spirv(spirv).map_err(|err| format!("Error reading SPIR-V: {}", err))
+	}
+
+	/// Loads the shader from `frag_shader_path` again, with the custom formula if there is one.
+	/// Keeps the shader it had where that fails.
+	fn reload_fs(&mut self, device: &AtomicDevice) -> Result<(), String> {
+		let fs = Self::load_fs(self.frag_shader_path(), self.data().custom_formula.as_ref())?;
+		log::info!("Setting fs");
+		self.data().set_fs(device.lock().unwrap().create_shader_module(&fs));
+		self.create_render_pipeline(&device.lock().unwrap());
+		Ok(())
 	}
 }